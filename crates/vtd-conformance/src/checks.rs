@@ -0,0 +1,470 @@
+use std::{
+    mem,
+    process,
+    ptr,
+};
+
+use anyhow::Context as _;
+use vtd_libum::protocol::{
+    command::{
+        DriverCommand,
+        DriverCommandInitialize,
+        DriverCommandMemoryRead,
+        DriverCommandMemoryWrite,
+        DriverCommandProcessList,
+        DriverCommandProcessModules,
+        InitializeResult,
+    },
+    types::{
+        DriverFeature,
+        MemoryAccessResult,
+        ProcessId,
+        ProcessInfo,
+        ProcessModuleInfo,
+    },
+    CommandResult,
+    PROTOCOL_VERSION,
+};
+
+use crate::suite::{
+    Check,
+    Context,
+    Requirement,
+};
+
+/// A process id which should never be assigned to a running process
+const UNKNOWN_PROCESS_ID: ProcessId = 0xFFFF_FFF0;
+
+/// A command id which is not part of the protocol
+const UNKNOWN_COMMAND_ID: u32 = 0x7FFF_FFFF;
+
+/// Marker for entries which have not been written by the driver
+const SENTINEL_PROCESS_ID: ProcessId = 0xDEAD_BEEF;
+const SENTINEL_MODULE_ADDRESS: u64 = 0xDEAD_BEEF_DEAD_BEEF;
+
+/// Memory region of the current process which will be read by the driver
+static READ_TARGET: [u8; 0x40] = {
+    let mut buffer = [0u8; 0x40];
+    let mut index = 0;
+    while index < buffer.len() {
+        buffer[index] = (index as u8).wrapping_mul(0x1D) ^ 0xA5;
+        index += 1;
+    }
+    buffer
+};
+
+pub const CHECKS: &[Check] = &[
+    Check {
+        name: "initialize/protocol-mismatch",
+        requirement: Requirement::None,
+        run: initialize_protocol_mismatch,
+    },
+    Check {
+        name: "initialize/success",
+        requirement: Requirement::None,
+        run: initialize_success,
+    },
+    Check {
+        name: "command/unknown-id",
+        requirement: Requirement::Initialized,
+        run: command_unknown_id,
+    },
+    Check {
+        name: "command/payload-too-small",
+        requirement: Requirement::Initialized,
+        run: command_payload_too_small,
+    },
+    Check {
+        name: "command/payload-too-large",
+        requirement: Requirement::Initialized,
+        run: command_payload_too_large,
+    },
+    Check {
+        name: "process-list/count-only",
+        requirement: Requirement::Feature(DriverFeature::ProcessList),
+        run: process_list_count_only,
+    },
+    Check {
+        name: "process-list/truncation",
+        requirement: Requirement::Feature(DriverFeature::ProcessList),
+        run: process_list_truncation,
+    },
+    Check {
+        name: "process-list/current-process",
+        requirement: Requirement::Feature(DriverFeature::ProcessList),
+        run: process_list_current_process,
+    },
+    Check {
+        name: "process-modules/count-only",
+        requirement: Requirement::Feature(DriverFeature::ProcessModules),
+        run: process_modules_count_only,
+    },
+    Check {
+        name: "process-modules/truncation",
+        requirement: Requirement::Feature(DriverFeature::ProcessModules),
+        run: process_modules_truncation,
+    },
+    Check {
+        name: "process-modules/current-module",
+        requirement: Requirement::Feature(DriverFeature::ProcessModules),
+        run: process_modules_current_module,
+    },
+    Check {
+        name: "process-modules/unknown-process",
+        requirement: Requirement::Feature(DriverFeature::ProcessModules),
+        run: process_modules_unknown_process,
+    },
+    Check {
+        name: "memory-read/current-process",
+        requirement: Requirement::Feature(DriverFeature::MemoryRead),
+        run: memory_read_current_process,
+    },
+    Check {
+        name: "memory-read/invalid-address",
+        requirement: Requirement::Feature(DriverFeature::MemoryRead),
+        run: memory_read_invalid_address,
+    },
+    Check {
+        name: "memory-read/unknown-process",
+        requirement: Requirement::Feature(DriverFeature::MemoryRead),
+        run: memory_read_unknown_process,
+    },
+    Check {
+        name: "memory-write/current-process",
+        requirement: Requirement::Feature(DriverFeature::MemoryWrite),
+        run: memory_write_current_process,
+    },
+    Check {
+        name: "memory-write/unknown-process",
+        requirement: Requirement::Feature(DriverFeature::MemoryWrite),
+        run: memory_write_unknown_process,
+    },
+];
+
+fn current_process_id() -> ProcessId {
+    process::id() as ProcessId
+}
+
+fn initialize_protocol_mismatch(context: &mut Context) -> anyhow::Result<()> {
+    let mut command = DriverCommandInitialize {
+        client_protocol_version: PROTOCOL_VERSION.wrapping_add(0x1000),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        command.driver_protocol_version == PROTOCOL_VERSION,
+        "driver reported protocol version {} (expected {})",
+        command.driver_protocol_version,
+        PROTOCOL_VERSION
+    );
+    anyhow::ensure!(
+        !matches!(command.result, InitializeResult::Success),
+        "driver reported a successful initialization for an unsupported client protocol"
+    );
+    Ok(())
+}
+
+fn initialize_success(context: &mut Context) -> anyhow::Result<()> {
+    let command = context.initialize()?;
+
+    let name = command
+        .driver_version
+        .get_application_name()
+        .context("driver application name is not valid UTF-8")?;
+    anyhow::ensure!(!name.is_empty(), "driver application name is empty");
+
+    log::info!(
+        "Driver {} v{}.{}.{} ({:?})",
+        name,
+        command.driver_version.version_major,
+        command.driver_version.version_minor,
+        command.driver_version.version_patch,
+        command.driver_features
+    );
+    Ok(())
+}
+
+fn command_unknown_id(context: &mut Context) -> anyhow::Result<()> {
+    let mut payload = [0u8; 0x40];
+    unsafe { context.execute_raw(UNKNOWN_COMMAND_ID, &mut payload) }?
+        .ensure_status(CommandResult::CommandInvalid)
+}
+
+fn command_payload_size(context: &mut Context, payload_size: usize) -> anyhow::Result<()> {
+    let mut payload = vec![0u8; payload_size];
+    let outcome =
+        unsafe { context.execute_raw(DriverCommandInitialize::COMMAND_ID, &mut payload) }?;
+    outcome.ensure_status(CommandResult::CommandParameterInvalid)?;
+
+    anyhow::ensure!(
+        payload.iter().all(|value| *value == 0),
+        "driver modified the invalid payload"
+    );
+    Ok(())
+}
+
+fn command_payload_too_small(context: &mut Context) -> anyhow::Result<()> {
+    self::command_payload_size(context, mem::size_of::<DriverCommandInitialize>() - 1)
+}
+
+fn command_payload_too_large(context: &mut Context) -> anyhow::Result<()> {
+    self::command_payload_size(context, mem::size_of::<DriverCommandInitialize>() + 8)
+}
+
+fn process_count(context: &Context) -> anyhow::Result<usize> {
+    let mut command = DriverCommandProcessList::default();
+    context.execute_success(&mut command)?;
+    Ok(command.process_count)
+}
+
+fn process_list_count_only(context: &mut Context) -> anyhow::Result<()> {
+    let process_count = self::process_count(context)?;
+    anyhow::ensure!(process_count > 0, "driver reported no processes");
+    Ok(())
+}
+
+fn process_list_truncation(context: &mut Context) -> anyhow::Result<()> {
+    let mut buffer = [ProcessInfo {
+        process_id: SENTINEL_PROCESS_ID,
+        ..Default::default()
+    }; 2];
+
+    let mut command = DriverCommandProcessList {
+        buffer: buffer.as_mut_ptr(),
+        buffer_capacity: 1,
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        command.process_count > 1,
+        "expected the total process count but received {}",
+        command.process_count
+    );
+    anyhow::ensure!(
+        buffer[0].process_id != SENTINEL_PROCESS_ID,
+        "driver did not fill the available entry"
+    );
+    anyhow::ensure!(
+        buffer[1].process_id == SENTINEL_PROCESS_ID,
+        "driver wrote beyond the buffer capacity"
+    );
+    Ok(())
+}
+
+fn process_list_current_process(context: &mut Context) -> anyhow::Result<()> {
+    /* reserve additional entries in case new processes have been started */
+    let capacity = self::process_count(context)? + 0x40;
+    let mut buffer = vec![ProcessInfo::default(); capacity];
+
+    let mut command = DriverCommandProcessList {
+        buffer: buffer.as_mut_ptr(),
+        buffer_capacity: buffer.len(),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        command.process_count <= capacity,
+        "process count increased from {} to {}",
+        capacity - 0x40,
+        command.process_count
+    );
+
+    let process_id = self::current_process_id();
+    let process = buffer[0..command.process_count]
+        .iter()
+        .find(|process| process.process_id == process_id)
+        .with_context(|| format!("current process {} is not listed", process_id))?;
+
+    anyhow::ensure!(
+        process.get_image_base_name().is_some(),
+        "image base name is not valid UTF-8"
+    );
+    Ok(())
+}
+
+fn process_modules(
+    context: &Context,
+    process_id: ProcessId,
+    buffer: &mut [ProcessModuleInfo],
+    capacity: usize,
+) -> anyhow::Result<DriverCommandProcessModules> {
+    let mut command = DriverCommandProcessModules {
+        process_id,
+        buffer: buffer.as_mut_ptr(),
+        buffer_capacity: capacity.min(buffer.len()),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+    Ok(command)
+}
+
+fn process_modules_count_only(context: &mut Context) -> anyhow::Result<()> {
+    let command = self::process_modules(context, self::current_process_id(), &mut [], 0)?;
+    anyhow::ensure!(!command.process_unknown, "current process is unknown");
+    anyhow::ensure!(command.module_count > 0, "driver reported no modules");
+    Ok(())
+}
+
+fn process_modules_truncation(context: &mut Context) -> anyhow::Result<()> {
+    let mut buffer = [ProcessModuleInfo {
+        base_address: SENTINEL_MODULE_ADDRESS,
+        ..Default::default()
+    }; 2];
+
+    let command = self::process_modules(context, self::current_process_id(), &mut buffer, 1)?;
+    anyhow::ensure!(!command.process_unknown, "current process is unknown");
+    anyhow::ensure!(
+        command.module_count > 1,
+        "expected the total module count but received {}",
+        command.module_count
+    );
+    anyhow::ensure!(
+        buffer[0].base_address != SENTINEL_MODULE_ADDRESS,
+        "driver did not fill the available entry"
+    );
+    anyhow::ensure!(
+        buffer[1].base_address == SENTINEL_MODULE_ADDRESS,
+        "driver wrote beyond the buffer capacity"
+    );
+    Ok(())
+}
+
+fn process_modules_current_module(context: &mut Context) -> anyhow::Result<()> {
+    let process_id = self::current_process_id();
+    let module_count = self::process_modules(context, process_id, &mut [], 0)?.module_count;
+
+    let mut buffer = vec![ProcessModuleInfo::default(); module_count + 0x40];
+    let capacity = buffer.len();
+    let command = self::process_modules(context, process_id, &mut buffer, capacity)?;
+    anyhow::ensure!(
+        command.module_count <= capacity,
+        "module count increased from {} to {}",
+        module_count,
+        command.module_count
+    );
+
+    let address = READ_TARGET.as_ptr() as u64;
+    let module = buffer[0..command.module_count]
+        .iter()
+        .find(|module| {
+            address >= module.base_address && address < module.base_address + module.module_size
+        })
+        .with_context(|| format!("no module contains the address {:X}", address))?;
+
+    anyhow::ensure!(
+        module.get_base_dll_name().is_some(),
+        "module name is not valid UTF-8"
+    );
+    Ok(())
+}
+
+fn process_modules_unknown_process(context: &mut Context) -> anyhow::Result<()> {
+    let command = self::process_modules(context, UNKNOWN_PROCESS_ID, &mut [], 0)?;
+    anyhow::ensure!(
+        command.process_unknown,
+        "driver did not report the process as unknown"
+    );
+    Ok(())
+}
+
+fn memory_read_current_process(context: &mut Context) -> anyhow::Result<()> {
+    let mut buffer = [0u8; READ_TARGET.len()];
+    let mut command = DriverCommandMemoryRead {
+        process_id: self::current_process_id(),
+        address: READ_TARGET.as_ptr() as u64,
+        buffer: buffer.as_mut_ptr(),
+        count: buffer.len(),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        matches!(command.result, MemoryAccessResult::Success),
+        "driver reported {:?}",
+        command.result
+    );
+    anyhow::ensure!(buffer == READ_TARGET, "read memory does not match");
+    Ok(())
+}
+
+fn memory_read_invalid_address(context: &mut Context) -> anyhow::Result<()> {
+    let mut buffer = [0u8; 0x10];
+    let mut command = DriverCommandMemoryRead {
+        process_id: self::current_process_id(),
+        address: 0,
+        buffer: buffer.as_mut_ptr(),
+        count: buffer.len(),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        !matches!(command.result, MemoryAccessResult::Success),
+        "driver reported a successful read of address 0"
+    );
+    Ok(())
+}
+
+fn memory_read_unknown_process(context: &mut Context) -> anyhow::Result<()> {
+    let mut buffer = [0u8; 0x10];
+    let mut command = DriverCommandMemoryRead {
+        process_id: UNKNOWN_PROCESS_ID,
+        address: READ_TARGET.as_ptr() as u64,
+        buffer: buffer.as_mut_ptr(),
+        count: buffer.len(),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        matches!(command.result, MemoryAccessResult::ProcessUnknown),
+        "expected ProcessUnknown but received {:?}",
+        command.result
+    );
+    Ok(())
+}
+
+fn memory_write_current_process(context: &mut Context) -> anyhow::Result<()> {
+    let mut target = vec![0u8; READ_TARGET.len()];
+    let mut command = DriverCommandMemoryWrite {
+        process_id: self::current_process_id(),
+        address: target.as_mut_ptr() as u64,
+        buffer: READ_TARGET.as_ptr(),
+        count: READ_TARGET.len(),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        matches!(command.result, MemoryAccessResult::Success),
+        "driver reported {:?}",
+        command.result
+    );
+
+    /* the target has been modified behind the compilers back */
+    let written = unsafe { ptr::read_volatile(target.as_ptr() as *const [u8; 0x40]) };
+    anyhow::ensure!(written == READ_TARGET, "written memory does not match");
+    Ok(())
+}
+
+fn memory_write_unknown_process(context: &mut Context) -> anyhow::Result<()> {
+    let target = [0u8; 0x10];
+    let mut command = DriverCommandMemoryWrite {
+        process_id: UNKNOWN_PROCESS_ID,
+        address: target.as_ptr() as u64,
+        buffer: READ_TARGET.as_ptr(),
+        count: target.len(),
+        ..Default::default()
+    };
+    context.execute_success(&mut command)?;
+
+    anyhow::ensure!(
+        matches!(command.result, MemoryAccessResult::ProcessUnknown),
+        "expected ProcessUnknown but received {:?}",
+        command.result
+    );
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::{
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use anyhow::Context as _;
+use clap::Parser;
+use vtd_libum::LibraryBackend;
+
+mod checks;
+mod suite;
+
+use suite::{
+    CheckStatus,
+    Context,
+};
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Path to the driver library which should be checked
+    #[arg(long)]
+    pub library: PathBuf,
+
+    /// Only run checks whose name contains this value
+    #[arg(long)]
+    pub filter: Option<String>,
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    env_logger::init();
+    let args = Args::parse();
+
+    log::debug!("Loading driver {}", args.library.display());
+    let backend = LibraryBackend::load(&args.library).context("load driver")?;
+
+    let mut context = Context::new(Box::new(backend));
+    let reports = suite::run(&mut context, checks::CHECKS, args.filter.as_deref());
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for report in reports.iter() {
+        println!("{}", report);
+        match report.status {
+            CheckStatus::Passed => passed += 1,
+            CheckStatus::Failed(_) => failed += 1,
+            CheckStatus::Skipped(_) => skipped += 1,
+        }
+    }
+
+    println!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+
+    Ok(if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
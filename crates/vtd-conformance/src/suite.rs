@@ -0,0 +1,239 @@
+use std::{
+    fmt,
+    mem,
+    slice,
+};
+
+use anyhow::Context as _;
+use vtd_libum::{
+    protocol::{
+        command::{
+            DriverCommand,
+            DriverCommandInitialize,
+            InitializeResult,
+        },
+        types::DriverFeature,
+        utils,
+        CommandResult,
+        PROTOCOL_VERSION,
+    },
+    DriverBackend,
+};
+
+/// Size of the error message buffer passed to the driver
+const ERROR_MESSAGE_SIZE: usize = 0x400;
+
+/// Status and error message of an executed command
+pub struct CommandOutcome {
+    pub status: CommandResult,
+    pub error_message: String,
+}
+
+impl CommandOutcome {
+    pub fn ensure_status(&self, expected: CommandResult) -> anyhow::Result<()> {
+        if self.status == expected {
+            return Ok(());
+        }
+
+        if self.error_message.is_empty() {
+            anyhow::bail!(
+                "expected status {} but received {}",
+                self::status_name(&expected),
+                self::status_name(&self.status)
+            )
+        } else {
+            anyhow::bail!(
+                "expected status {} but received {} ({})",
+                self::status_name(&expected),
+                self::status_name(&self.status),
+                self.error_message
+            )
+        }
+    }
+}
+
+fn status_name(status: &CommandResult) -> String {
+    status
+        .name()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:X}", status.bits()))
+}
+
+/// State shared between all checks
+pub struct Context {
+    backend: Box<dyn DriverBackend>,
+
+    /// Result of the last driver initialization.
+    /// Contains the reported features if the initialization succeeded.
+    initialization: Option<Result<DriverFeature, String>>,
+}
+
+impl Context {
+    pub fn new(backend: Box<dyn DriverBackend>) -> Self {
+        Self {
+            backend,
+            initialization: None,
+        }
+    }
+
+    /// Initialize the driver using the current protocol version and remember its features.
+    pub fn initialize(&mut self) -> anyhow::Result<DriverCommandInitialize> {
+        let result = self.execute_initialize();
+        self.initialization = Some(match &result {
+            Ok(command) => Ok(command.driver_features),
+            Err(err) => Err(format!("{:#}", err)),
+        });
+        result
+    }
+
+    fn execute_initialize(&self) -> anyhow::Result<DriverCommandInitialize> {
+        let mut command = DriverCommandInitialize {
+            client_protocol_version: PROTOCOL_VERSION,
+            ..Default::default()
+        };
+        self.execute_success(&mut command)?;
+
+        if command.driver_protocol_version != PROTOCOL_VERSION {
+            anyhow::bail!(
+                "driver implements protocol version {} but {} is required",
+                command.driver_protocol_version,
+                PROTOCOL_VERSION
+            );
+        }
+
+        if !matches!(command.result, InitializeResult::Success) {
+            anyhow::bail!("driver reported {:?}", command.result);
+        }
+
+        Ok(command)
+    }
+
+    /// Features reported by the driver.
+    /// The driver will be initialized if that has not yet been attempted.
+    pub fn features(&mut self) -> Result<DriverFeature, String> {
+        if self.initialization.is_none() {
+            let _ = self.initialize();
+        }
+
+        self.initialization.clone().unwrap()
+    }
+
+    /// Execute a command with an arbitrary payload.
+    ///
+    /// # Safety
+    /// All buffers referenced by the payload must be valid.
+    pub unsafe fn execute_raw(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+    ) -> anyhow::Result<CommandOutcome> {
+        let mut error_message = vec![0u8; ERROR_MESSAGE_SIZE];
+        let status = self
+            .backend
+            .execute_command(command_id, payload, &mut error_message)
+            .context("execute command")?;
+
+        let error_message = utils::fixed_buffer_to_str(&error_message)
+            .context("error message is not valid UTF-8")?
+            .to_string();
+
+        Ok(CommandOutcome {
+            status: CommandResult::from_bits_retain(status),
+            error_message,
+        })
+    }
+
+    /// Execute a command.
+    /// All buffers referenced by the command must be valid.
+    pub fn execute<C: DriverCommand>(&self, command: &mut C) -> anyhow::Result<CommandOutcome> {
+        let payload =
+            unsafe { slice::from_raw_parts_mut(command as *mut _ as *mut u8, mem::size_of::<C>()) };
+        unsafe { self.execute_raw(C::COMMAND_ID, payload) }
+    }
+
+    /// Execute a command and ensure it succeeded.
+    pub fn execute_success<C: DriverCommand>(&self, command: &mut C) -> anyhow::Result<()> {
+        self.execute(command)?.ensure_status(CommandResult::Success)
+    }
+}
+
+/// Preconditions of a check
+#[derive(Debug, Clone, Copy)]
+pub enum Requirement {
+    None,
+
+    /// The driver must have been initialized successfully
+    Initialized,
+
+    /// The driver must have been initialized and report the feature
+    Feature(DriverFeature),
+}
+
+pub struct Check {
+    pub name: &'static str,
+    pub requirement: Requirement,
+    pub run: fn(&mut Context) -> anyhow::Result<()>,
+}
+
+pub enum CheckStatus {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+pub struct CheckReport {
+    pub name: &'static str,
+    pub status: CheckStatus,
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            CheckStatus::Passed => write!(f, "[PASS] {}", self.name),
+            CheckStatus::Failed(message) => write!(f, "[FAIL] {}: {}", self.name, message),
+            CheckStatus::Skipped(reason) => write!(f, "[SKIP] {}: {}", self.name, reason),
+        }
+    }
+}
+
+/// Run all checks whose name contains the filter (if any) in order.
+pub fn run(context: &mut Context, checks: &[Check], filter: Option<&str>) -> Vec<CheckReport> {
+    let mut reports = Vec::with_capacity(checks.len());
+    for check in checks {
+        if let Some(filter) = filter {
+            if !check.name.contains(filter) {
+                continue;
+            }
+        }
+
+        let skip_reason = match check.requirement {
+            Requirement::None => None,
+            Requirement::Initialized => context
+                .features()
+                .err()
+                .map(|err| format!("driver initialization failed: {}", err)),
+            Requirement::Feature(feature) => match context.features() {
+                Ok(features) if features.contains(feature) => None,
+                Ok(_) => Some(format!("driver does not support {:?}", feature)),
+                Err(err) => Some(format!("driver initialization failed: {}", err)),
+            },
+        };
+
+        let status = if let Some(reason) = skip_reason {
+            CheckStatus::Skipped(reason)
+        } else {
+            log::debug!("Running {}", check.name);
+            match (check.run)(context) {
+                Ok(_) => CheckStatus::Passed,
+                Err(err) => CheckStatus::Failed(format!("{:#}", err)),
+            }
+        };
+
+        reports.push(CheckReport {
+            name: check.name,
+            status,
+        });
+    }
+
+    reports
+}
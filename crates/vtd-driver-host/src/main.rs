@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use vtd_libum::{
+    host::{
+        self,
+        HostListener,
+    },
+    IntegrityPolicy,
+    LibraryBackend,
+};
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Path to the driver library which should be hosted
+    #[arg(long)]
+    pub library: PathBuf,
+
+    /// Hex encoded SHA-256 hash the library has been verified with.
+    /// The library will only be loaded if it still matches this hash.
+    #[arg(long)]
+    pub sha256: String,
+
+    /// Endpoint (unix socket path or named pipe name) the host should listen on
+    #[arg(long)]
+    pub endpoint: String,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let policy = IntegrityPolicy::default()
+        .with_allowed_hash_hex(&args.sha256)
+        .context("sha256")?;
+    vtd_libum::set_integrity_policy(policy);
+
+    let listener = HostListener::bind(&args.endpoint).context("bind endpoint")?;
+
+    log::debug!("Loading driver {}", args.library.display());
+    let backend = LibraryBackend::load(&args.library).context("load driver")?;
+
+    let mut stream = listener.accept().context("accept client")?;
+    host::write_handshake(&mut stream).context("handshake")?;
+
+    log::debug!("Client connected. Serving driver commands.");
+    while let Some(request) = host::read_frame(&mut stream).context("read request")? {
+        let response = host::execute_encoded_request(&backend, &request);
+        host::write_frame(&mut stream, &response.encode()).context("write response")?;
+    }
+
+    log::debug!("Client disconnected. Shutting down.");
+    drop(backend);
+    Ok(())
+}
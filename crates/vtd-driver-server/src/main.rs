@@ -0,0 +1,112 @@
+use std::{
+    env,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::Context;
+use clap::Parser;
+use vtd_libum::{
+    remote::{
+        self,
+        RemoteEndpoint,
+        RemoteListener,
+    },
+    LibraryBackend,
+};
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Path to the driver library which should execute the commands
+    #[arg(long)]
+    pub library: PathBuf,
+
+    /// Endpoint the server should listen on (`tcp:<address>:<port>` or `unix:<path>`)
+    #[arg(long)]
+    pub listen: RemoteEndpoint,
+
+    /// File containing the pre-shared key.
+    /// By default the key will be read from `VT_REMOTE_KEY`.
+    #[arg(long)]
+    pub key_file: Option<PathBuf>,
+
+    /// Maximum number of concurrently served clients.
+    /// Additional connections will be closed immediately.
+    #[arg(long, default_value_t = 8)]
+    pub max_connections: usize,
+}
+
+/// Releases a connection slot once the connection has been closed
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn acquire(connections: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < limit).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(connections.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let key = match &args.key_file {
+        Some(path) => fs::read(path).with_context(|| format!("read {}", path.display()))?,
+        None => env::var("VT_REMOTE_KEY")
+            .context("neither a key file nor VT_REMOTE_KEY has been specified")?
+            .into_bytes(),
+    };
+    if key.is_empty() {
+        anyhow::bail!("the pre-shared key must not be empty");
+    }
+    let key = Arc::new(key);
+
+    log::debug!("Loading driver {}", args.library.display());
+    let backend = Arc::new(LibraryBackend::load(&args.library).context("load driver")?);
+
+    let listener = RemoteListener::bind(&args.listen).context("bind endpoint")?;
+    log::info!(
+        "Serving driver commands on {}",
+        listener.local_endpoint().context("local endpoint")?
+    );
+
+    let connections = Arc::new(AtomicUsize::new(0));
+    loop {
+        let stream = listener.accept().context("accept client")?;
+        let Some(slot) = ConnectionSlot::acquire(&connections, args.max_connections) else {
+            log::warn!(
+                "Rejecting client: {} connections already active",
+                args.max_connections
+            );
+            continue;
+        };
+
+        let backend = backend.clone();
+        let key = key.clone();
+        thread::spawn(move || {
+            let _slot = slot;
+            match remote::serve_connection(stream, &key, backend.as_ref()) {
+                Ok(_) => log::info!("Client disconnected"),
+                Err(err) => log::warn!("Client connection failed: {}", err),
+            }
+        });
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::LevelFilter;
+use vtd_libum::{
+    DriverInfo,
+    DriverInterface,
+    DriverManifest,
+    ValthrunLibrary,
+};
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Path to the driver library the manifest should be created for
+    pub library: PathBuf,
+}
+
+pub fn main() -> anyhow::Result<()> {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let args = Args::parse();
+
+    let info = {
+        let library = ValthrunLibrary::load(&args.library)?;
+        let interface = DriverInterface::create(library)?;
+        DriverInfo::from_interface(&interface)
+    };
+
+    let manifest = DriverManifest::create(&args.library, &info)?;
+    manifest.save_for_library(&args.library)?;
+
+    log::info!(
+        "Created manifest {} for {} v{}",
+        DriverManifest::manifest_path(&args.library).display(),
+        manifest.name,
+        manifest.version
+    );
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use clap::{
+    Parser,
+    ValueEnum,
+};
+use log::LevelFilter;
+use vtd_libum::{
+    dump::{
+        DumpFormat,
+        ProcessSnapshot,
+        SnapshotConfig,
+    },
+    DriverInterface,
+};
+use vtd_protocol::types::ProcessId;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ArgDumpFormat {
+    /// Windows minidump
+    Minidump,
+
+    /// ELF core file
+    Core,
+}
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Process id of the process which should be captured
+    pub process_id: ProcessId,
+
+    /// Path of the resulting dump file
+    pub output: PathBuf,
+
+    #[arg(short, long, value_enum, default_value = "minidump")]
+    pub format: ArgDumpFormat,
+
+    /// Additional memory ranges to capture (start-end in hex, e.g. 7FF000-800000)
+    #[arg(short, long, value_parser = parse_range)]
+    pub range: Vec<(u64, u64)>,
+}
+
+fn parse_range(value: &str) -> Result<(u64, u64), String> {
+    let (start, end) = value
+        .split_once('-')
+        .ok_or_else(|| "expected start-end".to_string())?;
+
+    let parse = |value: &str| {
+        u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|err| err.to_string())
+    };
+    Ok((parse(start)?, parse(end)?))
+}
+
+pub fn main() -> anyhow::Result<()> {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let args = Args::parse();
+    let interface = DriverInterface::create_from_env()?;
+
+    let mut config = SnapshotConfig::default();
+    for (start, end) in args.range {
+        config = config.with_range(start..end);
+    }
+
+    let snapshot = ProcessSnapshot::capture(&interface, args.process_id, &config)?;
+    let format = match args.format {
+        ArgDumpFormat::Minidump => DumpFormat::Minidump,
+        ArgDumpFormat::Core => DumpFormat::Core,
+    };
+    snapshot.save(format, &args.output)?;
+
+    log::info!(
+        "Saved {} modules and {} memory regions ({} bytes, {} pages skipped) to {}",
+        snapshot.modules.len(),
+        snapshot.regions.len(),
+        snapshot
+            .regions
+            .iter()
+            .map(|region| region.data.len())
+            .sum::<usize>(),
+        snapshot.skipped_pages,
+        args.output.display()
+    );
+    Ok(())
+}
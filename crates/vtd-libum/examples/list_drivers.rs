@@ -0,0 +1,37 @@
+use log::LevelFilter;
+use vtd_libum::{
+    DriverRequirements,
+    DriverSelection,
+};
+
+pub fn main() -> anyhow::Result<()> {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let mut selection = DriverSelection::from_env(DriverRequirements::default());
+    selection.probe();
+
+    log::info!("Found {} drivers:", selection.candidates().len());
+    for candidate in selection.candidates() {
+        match &candidate.info {
+            Some(Ok(info)) => log::info!(
+                " - {} {} v{} ({:?}){}",
+                candidate.path.display(),
+                info.name,
+                info.version,
+                info.features,
+                if candidate.manifest.is_some() {
+                    " [manifest]"
+                } else {
+                    ""
+                }
+            ),
+            Some(Err(err)) => log::info!(" - {} failed: {}", candidate.path.display(), err),
+            None => log::info!(" - {} not probed", candidate.path.display()),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::{
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use clap::Parser;
+use ed25519_dalek::{
+    Signer,
+    SigningKey,
+};
+use log::LevelFilter;
+use sha2::{
+    Digest,
+    Sha256,
+};
+use vtd_libum::IntegrityPolicy;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Hex encoded ed25519 secret key
+    #[arg(short, long)]
+    pub secret_key: String,
+
+    /// Path to the driver library which should be signed
+    pub library: PathBuf,
+}
+
+pub fn main() -> anyhow::Result<()> {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let args = Args::parse();
+
+    let mut secret_key = [0u8; 0x20];
+    hex::decode_to_slice(&args.secret_key, &mut secret_key).context("invalid secret key")?;
+    let signing_key = SigningKey::from_bytes(&secret_key);
+
+    let library = fs::read(&args.library).context("read library")?;
+    let hash: [u8; 0x20] = Sha256::digest(&library).into();
+    let signature = signing_key.sign(&hash);
+
+    let signature_path = IntegrityPolicy::signature_path(&args.library);
+    fs::write(&signature_path, hex::encode(signature.to_bytes())).context("write signature")?;
+
+    log::info!("Signature written to {}", signature_path.display());
+    log::info!(
+        "Public key: {}",
+        hex::encode(signing_key.verifying_key().to_bytes())
+    );
+    Ok(())
+}
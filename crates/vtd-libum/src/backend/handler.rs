@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    mem,
+};
+
+use vtd_protocol::{
+    command::DriverCommand,
+    utils::str_to_fixed_buffer,
+    CommandResult,
+};
+
+use super::DriverBackend;
+use crate::IResult;
+
+trait HandlerInvoker: Send + Sync {
+    fn invoke(&self, command: &mut [u8], error_buffer: &mut [u8]) -> CommandResult;
+}
+
+struct HandlerImpl<C, F> {
+    inner: F,
+    _command: std::marker::PhantomData<fn(&mut C)>,
+}
+
+impl<C, F, E> HandlerInvoker for HandlerImpl<C, F>
+where
+    C: DriverCommand,
+    F: Fn(&mut C) -> Result<(), E> + Send + Sync,
+    E: Display,
+{
+    fn invoke(&self, command: &mut [u8], error_buffer: &mut [u8]) -> CommandResult {
+        if mem::size_of::<C>() != command.len() {
+            let message = format!(
+                "command size miss match (expected {}, received: {})",
+                mem::size_of::<C>(),
+                command.len()
+            );
+
+            str_to_fixed_buffer(error_buffer, &message);
+            return CommandResult::CommandParameterInvalid;
+        }
+
+        let command = unsafe { &mut *(command.as_mut_ptr() as *mut C) };
+        match (self.inner)(command) {
+            Ok(_) => CommandResult::Success,
+            Err(error) => {
+                str_to_fixed_buffer(error_buffer, &format!("{:#}", error));
+                CommandResult::Error
+            }
+        }
+    }
+}
+
+/// In-process backend dispatching commands to registered Rust handlers.
+/// Commands without a handler fail with [CommandResult::CommandInvalid].
+///
+/// Handlers are identified by the command id and the size of the command struct
+/// as some commands share the same id (e.g. metrics flush and CR3 mitigation enable).
+#[derive(Default)]
+pub struct HandlerBackend {
+    handler: BTreeMap<(u32, usize), Box<dyn HandlerInvoker>>,
+}
+
+impl HandlerBackend {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register<C, F, E>(&mut self, handler: F)
+    where
+        C: DriverCommand + 'static,
+        F: Fn(&mut C) -> Result<(), E> + Send + Sync + 'static,
+        E: Display + 'static,
+    {
+        self.handler.insert(
+            (C::COMMAND_ID, mem::size_of::<C>()),
+            Box::new(HandlerImpl {
+                inner: handler,
+                _command: Default::default(),
+            }),
+        );
+    }
+
+    pub fn with_handler<C, F, E>(mut self, handler: F) -> Self
+    where
+        C: DriverCommand + 'static,
+        F: Fn(&mut C) -> Result<(), E> + Send + Sync + 'static,
+        E: Display + 'static,
+    {
+        self.register(handler);
+        self
+    }
+}
+
+impl DriverBackend for HandlerBackend {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        /* fall back to any handler with the same id so size miss matches get reported */
+        let handler = self.handler.get(&(command_id, payload.len())).or_else(|| {
+            self.handler
+                .range((command_id, 0)..=(command_id, usize::MAX))
+                .map(|(_, handler)| handler)
+                .next()
+        });
+
+        let result = match handler {
+            Some(handler) => handler.invoke(payload, error_message),
+            None => CommandResult::CommandInvalid,
+        };
+
+        Ok(result.bits())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use vtd_protocol::{
+        command::{
+            DriverCommand,
+            DriverCommandCr3ShenanigansEnable,
+            DriverCommandInitialize,
+            DriverCommandMemoryRead,
+            DriverCommandMetricsFlush,
+            InitializeResult,
+        },
+        types::{
+            DirectoryTableType,
+            DriverFeature,
+            MemoryAccessResult,
+        },
+        CommandResult,
+        PROTOCOL_VERSION,
+    };
+
+    use super::HandlerBackend;
+    use crate::{
+        DriverBackend,
+        DriverInterface,
+        InterfaceError,
+    };
+
+    #[test]
+    fn interface_with_handlers() {
+        let backend = HandlerBackend::new()
+            .with_handler(|command: &mut DriverCommandInitialize| {
+                command.driver_protocol_version = PROTOCOL_VERSION;
+                command.driver_features = DriverFeature::MemoryRead;
+                command.result = InitializeResult::Success;
+                Ok::<_, String>(())
+            })
+            .with_handler(|command: &mut DriverCommandMemoryRead| {
+                if command.address == 0 {
+                    return Err("null pointer");
+                }
+
+                let buffer =
+                    unsafe { core::slice::from_raw_parts_mut(command.buffer, command.count) };
+                buffer.fill(0x11);
+                command.result = MemoryAccessResult::Success;
+                Ok(())
+            });
+
+        let interface = DriverInterface::with_backend(backend).unwrap();
+        assert_eq!(interface.driver_features(), DriverFeature::MemoryRead);
+
+        let mut buffer = [0u8; 4];
+        interface
+            .read_slice(1, DirectoryTableType::Default, 0x1000, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [0x11; 4]);
+
+        assert!(matches!(
+            interface.read_slice(1, DirectoryTableType::Default, 0, &mut buffer),
+            Err(InterfaceError::CommandGenericError { message }) if message == "null pointer"
+        ));
+        assert!(matches!(
+            interface.list_processes(),
+            Err(InterfaceError::CommandGenericError { .. })
+        ));
+    }
+
+    #[test]
+    fn shared_command_id() {
+        let backend = HandlerBackend::new()
+            .with_handler(|command: &mut DriverCommandInitialize| {
+                command.driver_protocol_version = PROTOCOL_VERSION;
+                command.result = InitializeResult::Success;
+                Ok::<_, String>(())
+            })
+            .with_handler(|command: &mut DriverCommandMetricsFlush| {
+                command.queue_remaining = 3;
+                Ok::<_, String>(())
+            })
+            .with_handler(|command: &mut DriverCommandCr3ShenanigansEnable| {
+                command.success = command.mitigation_strategy == 1;
+                Ok::<_, String>(())
+            });
+
+        /* a known command id with an unexpected payload size */
+        let mut payload = [0u8; 3];
+        let mut error = [0u8; 0x100];
+        let result = unsafe {
+            backend.execute_command(
+                DriverCommandMetricsFlush::COMMAND_ID,
+                &mut payload,
+                &mut error,
+            )
+        };
+        assert_eq!(
+            result.unwrap(),
+            CommandResult::CommandParameterInvalid.bits()
+        );
+
+        let interface = DriverInterface::with_backend(backend).unwrap();
+        assert_eq!(interface.flush_metrics(false).unwrap(), 3);
+        assert!(interface.enable_cr3_shenanigan_mitigation(1, 0).unwrap());
+    }
+}
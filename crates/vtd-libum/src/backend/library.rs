@@ -0,0 +1,117 @@
+use std::{
+    ops::Deref,
+    path::Path,
+};
+
+use libloading::Library;
+use vtd_protocol::FnCommandHandler;
+
+use super::DriverBackend;
+use crate::{
+    integrity,
+    IResult,
+    LibraryFile,
+};
+
+type LibraryStartup = unsafe extern "C" fn() -> ();
+type LibraryTeardown = unsafe extern "C" fn() -> ();
+
+pub struct ValthrunLibrary {
+    inner: Library,
+
+    fn_teardown: Option<LibraryTeardown>,
+}
+
+impl ValthrunLibrary {
+    /// Load a driver library.
+    /// The library must satisfy the configured [IntegrityPolicy](crate::IntegrityPolicy) before it gets loaded.
+    pub fn load(target: &Path) -> IResult<Self> {
+        Self::load_file(&LibraryFile::open(target)?)
+    }
+
+    /// Load a driver library from an opened file.
+    /// The library must satisfy the configured [IntegrityPolicy](crate::IntegrityPolicy) before it gets loaded.
+    pub fn load_file(file: &LibraryFile) -> IResult<Self> {
+        integrity::verify_library(file)?;
+        let library = unsafe { Library::new(file.load_path()) }?;
+
+        let fn_startup = unsafe {
+            library
+                .get::<LibraryStartup>(c"startup".to_bytes_with_nul())
+                .ok()
+        };
+
+        let fn_teardown = unsafe {
+            library
+                .get::<LibraryTeardown>(c"teardown".to_bytes_with_nul())
+                .ok()
+                .map(|v| *v)
+        };
+
+        if let Some(startup) = fn_startup {
+            unsafe {
+                startup();
+            }
+        }
+
+        Ok(Self {
+            inner: library,
+            fn_teardown,
+        })
+    }
+}
+
+impl Deref for ValthrunLibrary {
+    type Target = Library;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl Drop for ValthrunLibrary {
+    fn drop(&mut self) {
+        if let Some(teardown) = self.fn_teardown {
+            unsafe {
+                teardown();
+            }
+        }
+    }
+}
+
+/// Backend for a driver library loaded into the current process
+pub struct LibraryBackend {
+    _library: ValthrunLibrary,
+    fn_command_handler: FnCommandHandler,
+}
+
+impl LibraryBackend {
+    pub fn new(library: ValthrunLibrary) -> IResult<Self> {
+        let fn_command_handler = unsafe { *library.get::<FnCommandHandler>(b"execute_command\0")? };
+        Ok(Self {
+            _library: library,
+            fn_command_handler,
+        })
+    }
+
+    pub fn load(target: &Path) -> IResult<Self> {
+        Self::new(ValthrunLibrary::load(target)?)
+    }
+}
+
+impl DriverBackend for LibraryBackend {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        Ok((self.fn_command_handler)(
+            command_id,
+            payload.as_mut_ptr(),
+            payload.len(),
+            error_message.as_mut_ptr(),
+            error_message.len(),
+        ))
+    }
+}
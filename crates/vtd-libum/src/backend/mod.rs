@@ -0,0 +1,72 @@
+//! Backends executing driver commands on behalf of the [DriverInterface](crate::DriverInterface).
+use vtd_protocol::CommandResult;
+
+use crate::{
+    marshal::{
+        CommandRequest,
+        CommandResponse,
+    },
+    IResult,
+    InterfaceError,
+};
+
+mod library;
+pub use library::*;
+
+mod handler;
+pub use handler::*;
+
+/// A driver implementation which executes raw driver commands.
+///
+/// The dynamic driver library, the in-process [HandlerBackend] and the
+/// [HostedDriver](crate::host::HostedDriver) are implementations of this trait.
+pub trait DriverBackend: Send + Sync {
+    /// Execute a driver command and return the [CommandResult] bits.
+    ///
+    /// # Safety
+    /// `payload` must contain a valid command identified by `command_id`.
+    /// All buffers referenced by the command must be valid for the duration of the call.
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64>;
+
+    /// Execute a marshalled command request.
+    /// The default implementation recreates all buffers locally and calls [DriverBackend::execute_command].
+    fn execute_request(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        let mut result = Ok(());
+        let response = request
+            .execute(|command_id, payload, error_message| {
+                match unsafe { self.execute_command(command_id, payload, error_message) } {
+                    Ok(status) => status,
+                    Err(err) => {
+                        result = Err(err);
+                        CommandResult::Error.bits()
+                    }
+                }
+            })
+            .map_err(|err| InterfaceError::CommandGenericError {
+                message: format!("marshal: {}", err),
+            })?;
+
+        result?;
+        Ok(response)
+    }
+}
+
+impl<B: DriverBackend + ?Sized> DriverBackend for Box<B> {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        (**self).execute_command(command_id, payload, error_message)
+    }
+
+    fn execute_request(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        (**self).execute_request(request)
+    }
+}
@@ -0,0 +1,905 @@
+use std::{
+    cmp::Reverse,
+    collections::VecDeque,
+    env,
+    fmt,
+    fs,
+    path::PathBuf,
+};
+
+use obfstr::obfstr;
+use vtd_protocol::{
+    command::VersionInfo,
+    types::DriverFeature,
+};
+
+use crate::{
+    host::HostedDriver,
+    DriverBackend,
+    DriverInterface,
+    DriverManifest,
+    IResult,
+    InterfaceError,
+    LibraryBackend,
+    LibraryFile,
+    ValthrunLibrary,
+};
+
+/// Semantic version of a driver as reported by the driver upon initialization.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DriverVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl DriverVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl From<&VersionInfo> for DriverVersion {
+    fn from(value: &VersionInfo) -> Self {
+        Self::new(
+            value.version_major,
+            value.version_minor,
+            value.version_patch,
+        )
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Information about a driver gathered while probing it.
+#[derive(Debug, Clone)]
+pub struct DriverInfo {
+    pub name: String,
+    pub version: DriverVersion,
+    pub features: DriverFeature,
+}
+
+impl DriverInfo {
+    pub fn from_interface(interface: &DriverInterface) -> Self {
+        let version = interface.driver_version();
+        Self {
+            name: version
+                .get_application_name()
+                .unwrap_or("unknown")
+                .to_string(),
+            version: DriverVersion::from(version),
+            features: interface.driver_features(),
+        }
+    }
+}
+
+/// Requirements a driver must fulfill in order to be selected.
+#[derive(Debug, Default, Clone)]
+pub struct DriverRequirements {
+    /// Features the driver must support
+    pub features: DriverFeature,
+
+    /// Features which are not required but preferred.
+    /// Drivers supporting more of these features will be preferred over others.
+    pub preferred_features: DriverFeature,
+
+    /// Minimum driver version (inclusive)
+    pub min_version: Option<DriverVersion>,
+
+    /// Maximum driver version (inclusive)
+    pub max_version: Option<DriverVersion>,
+
+    /// Name of the driver as reported by the driver itself
+    pub name: Option<String>,
+}
+
+impl DriverRequirements {
+    pub fn with_features(mut self, features: DriverFeature) -> Self {
+        self.features |= features;
+        self
+    }
+
+    pub fn with_preferred_features(mut self, features: DriverFeature) -> Self {
+        self.preferred_features |= features;
+        self
+    }
+
+    pub fn with_min_version(mut self, version: DriverVersion) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    pub fn with_max_version(mut self, version: DriverVersion) -> Self {
+        self.max_version = Some(version);
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Returns true if the requirements can only be checked by knowing the drivers capabilities.
+    pub fn requires_probing(&self) -> bool {
+        !self.features.is_empty()
+            || !self.preferred_features.is_empty()
+            || self.min_version.is_some()
+            || self.max_version.is_some()
+            || self.name.is_some()
+    }
+
+    pub fn matches(&self, info: &DriverInfo) -> bool {
+        if !info.features.contains(self.features) {
+            return false;
+        }
+
+        if self.min_version.is_some_and(|min| info.version < min) {
+            return false;
+        }
+
+        if self.max_version.is_some_and(|max| info.version > max) {
+            return false;
+        }
+
+        if self.name.as_ref().is_some_and(|name| *name != info.name) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// A driver library which has been found during the driver discovery.
+pub struct DriverCandidate {
+    pub path: PathBuf,
+
+    /// The sidecar manifest of the driver library if present
+    pub manifest: Option<DriverManifest>,
+
+    /// Result of probing the driver.
+    /// `None` if the driver has not been probed.
+    pub info: Option<IResult<DriverInfo>>,
+
+    /// The driver interface created while probing.
+    /// Kept so the preferred driver does not need to be loaded and initialized twice.
+    instance: Option<DriverInterface>,
+}
+
+impl fmt::Debug for DriverCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DriverCandidate")
+            .field("path", &self.path)
+            .field("manifest", &self.manifest)
+            .field("info", &self.info)
+            .field("loaded", &self.instance.is_some())
+            .finish()
+    }
+}
+
+impl DriverCandidate {
+    /// Create a new candidate for the given library path.
+    /// The libraries manifest will be loaded if present.
+    pub fn new(path: PathBuf) -> Self {
+        match DriverManifest::load_for_library(&path) {
+            Ok(manifest) => Self {
+                path,
+                manifest,
+                info: None,
+                instance: None,
+            },
+            Err(err) => {
+                log::warn!("Ignoring driver {}: {}", path.display(), err);
+                Self {
+                    path,
+                    manifest: None,
+                    info: Some(Err(err)),
+                    instance: None,
+                }
+            }
+        }
+    }
+
+    fn probe(&mut self, loader: &DriverLoader) {
+        if let Some(manifest) = &self.manifest {
+            log::debug!("Using manifest for driver {}", self.path.display());
+            self.info = Some(manifest.driver_info());
+            return;
+        }
+
+        log::debug!("Probing driver {}", self.path.display());
+        let result = loader(self).and_then(DriverInterface::with_backend);
+        let info = match result {
+            Ok(interface) => {
+                let info = DriverInfo::from_interface(&interface);
+                log::debug!(
+                    "    -> {} (version: {}, features: {:?})",
+                    info.name,
+                    info.version,
+                    info.features
+                );
+
+                self.instance = Some(interface);
+                Ok(info)
+            }
+            Err(err) => {
+                log::debug!("    -> failed: {}", err);
+                Err(err)
+            }
+        };
+
+        self.info = Some(info);
+    }
+
+    /// Verify the library matches the hash specified within the manifest (if present).
+    pub fn verify(&self) -> IResult<()> {
+        if let Some(manifest) = &self.manifest {
+            manifest.verify_library(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the driver library.
+    /// If the driver has a manifest the library must match the hash specified within the manifest.
+    pub fn load(&self) -> IResult<ValthrunLibrary> {
+        /* verify and load the same opened file so it can not be swapped in between */
+        ValthrunLibrary::load_file(&self.open_verified()?)
+    }
+
+    /// Open the library and verify it matches the hash specified within the manifest (if present).
+    pub fn open_verified(&self) -> IResult<LibraryFile> {
+        let file = LibraryFile::open(&self.path)?;
+        if let Some(manifest) = &self.manifest {
+            manifest.verify_hash(file.sha256())?;
+        }
+
+        Ok(file)
+    }
+
+    /// Load the driver library within a separate host process.
+    /// If the driver has a manifest the library must match the hash specified within the manifest.
+    pub fn load_hosted(&self) -> IResult<DriverInterface> {
+        DriverInterface::with_backend(self.spawn_host()?)
+    }
+
+    /// Spawn a host process for the driver library without initializing the driver.
+    /// If the driver has a manifest the library must match the hash specified within the manifest.
+    pub fn spawn_host(&self) -> IResult<HostedDriver> {
+        HostedDriver::spawn_file(&self.open_verified()?)
+    }
+
+    /// Create the backend for the driver without initializing it.
+    /// If `isolated` is true, the driver will be loaded within a separate host process.
+    pub fn create_backend(&self, isolated: bool) -> IResult<Box<dyn DriverBackend>> {
+        if isolated {
+            Ok(Box::new(self.spawn_host()?))
+        } else {
+            Ok(Box::new(LibraryBackend::new(self.load()?)?))
+        }
+    }
+
+    pub fn driver_info(&self) -> Option<&DriverInfo> {
+        self.info.as_ref().and_then(|info| info.as_ref().ok())
+    }
+}
+
+/// Creates the (not yet initialized) backend for a driver candidate
+pub type DriverLoader = dyn Fn(&DriverCandidate) -> IResult<Box<dyn DriverBackend>> + Send + Sync;
+
+/// Selects the driver to be used based on the given [DriverRequirements].
+///
+/// All matching drivers are kept in an ordered fallback chain.
+/// Calling [DriverSelection::create] again after the selected driver failed
+/// will continue with the next driver in the chain.
+pub struct DriverSelection {
+    requirements: DriverRequirements,
+    candidates: Vec<DriverCandidate>,
+    chain: VecDeque<usize>,
+
+    /// Load drivers within a separate host process
+    isolated: bool,
+    loader: Box<DriverLoader>,
+
+    /// Trace file recording all commands of the created driver interface
+    record: Option<PathBuf>,
+
+    /// Drivers of the chain which failed to load or initialize
+    failures: Vec<(PathBuf, String)>,
+}
+
+impl DriverSelection {
+    /// Discover all driver libraries from the environment.
+    /// Drivers will only be loaded for probing if the requirements demand it.
+    ///
+    /// If `VT_DRIVER_ISOLATE` is set, drivers will be loaded within a separate host process.
+    /// If `VT_DRIVER_RECORD` is set, all commands of the created driver interface will be
+    /// recorded into the given trace file.
+    pub fn from_env(requirements: DriverRequirements) -> Self {
+        let candidates = self::populate_library_paths()
+            .into_iter()
+            .map(DriverCandidate::new)
+            .collect::<Vec<_>>();
+
+        let isolated = env::var(obfstr!("VT_DRIVER_ISOLATE"))
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        let mut selection =
+            Self::from_candidates_with_isolation(requirements, candidates, isolated);
+        selection.record = env::var_os(obfstr!("VT_DRIVER_RECORD")).map(PathBuf::from);
+        selection
+    }
+
+    pub fn from_candidates(
+        requirements: DriverRequirements,
+        candidates: Vec<DriverCandidate>,
+    ) -> Self {
+        Self::from_candidates_with_isolation(requirements, candidates, false)
+    }
+
+    /// Create a new selection.
+    /// If `isolated` is true, drivers will be loaded within a separate host process.
+    pub fn from_candidates_with_isolation(
+        requirements: DriverRequirements,
+        candidates: Vec<DriverCandidate>,
+        isolated: bool,
+    ) -> Self {
+        let mut selection = Self::from_candidates_with_loader(
+            requirements,
+            candidates,
+            move |candidate: &DriverCandidate| candidate.create_backend(isolated),
+        );
+        selection.isolated = isolated;
+        selection
+    }
+
+    /// Create a new selection which uses the given loader to create the driver backends.
+    pub fn from_candidates_with_loader(
+        requirements: DriverRequirements,
+        candidates: Vec<DriverCandidate>,
+        loader: impl Fn(&DriverCandidate) -> IResult<Box<dyn DriverBackend>> + Send + Sync + 'static,
+    ) -> Self {
+        let mut selection = Self {
+            requirements,
+            candidates,
+            chain: Default::default(),
+            isolated: false,
+            loader: Box::new(loader),
+            record: None,
+            failures: Vec::new(),
+        };
+
+        if selection.requirements.requires_probing() {
+            selection.probe();
+        } else {
+            selection.build_chain();
+        }
+
+        selection
+    }
+
+    /// Probe all drivers which have not yet been probed.
+    /// This loads and initializes every driver library once.
+    /// Only the instance of the preferred driver is kept loaded
+    /// and reused by [DriverSelection::create].
+    pub fn probe(&mut self) {
+        let mut preferred: Option<(usize, u32)> = self
+            .chain
+            .front()
+            .filter(|index| self.candidates[**index].instance.is_some())
+            .map(|index| (*index, self.preference(&self.candidates[*index])));
+
+        for index in 0..self.candidates.len() {
+            if self.candidates[index].info.is_some() {
+                continue;
+            }
+
+            self.candidates[index].probe(&self.loader);
+            if self.candidates[index].instance.is_none() {
+                continue;
+            }
+
+            let candidate = &self.candidates[index];
+            let matches = candidate
+                .driver_info()
+                .is_some_and(|info| self.requirements.matches(info));
+            let preference = self.preference(candidate);
+
+            /* keep at most one driver loaded, ties are resolved by the discovery order */
+            if matches && preferred.is_none_or(|(_, current)| preference > current) {
+                if let Some((current, _)) = preferred.replace((index, preference)) {
+                    self.candidates[current].instance = None;
+                }
+            } else {
+                self.candidates[index].instance = None;
+            }
+        }
+
+        self.build_chain();
+    }
+
+    /// Number of preferred features the candidate supports
+    fn preference(&self, candidate: &DriverCandidate) -> u32 {
+        candidate
+            .driver_info()
+            .map(|info| {
+                (info.features & self.requirements.preferred_features)
+                    .bits()
+                    .count_ones()
+            })
+            .unwrap_or(0)
+    }
+
+    fn build_chain(&mut self) {
+        let mut chain = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| match &candidate.info {
+                None => true,
+                Some(Ok(info)) => self.requirements.matches(info),
+                Some(Err(_)) => false,
+            })
+            .map(|(index, candidate)| (index, self.preference(candidate)))
+            .collect::<Vec<_>>();
+
+        /* stable sort: drivers with the same amount of preferred features keep their discovery order */
+        chain.sort_by_key(|(_, preferred)| Reverse(*preferred));
+        self.chain = chain.into_iter().map(|(index, _)| index).collect();
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.isolated
+    }
+
+    /// Record all commands of the created driver interface into the given trace file.
+    /// Drivers loaded while probing will be loaded again so the trace is complete.
+    pub fn set_recording(&mut self, trace: Option<PathBuf>) {
+        self.record = trace;
+    }
+
+    fn create_interface(&self, backend: impl DriverBackend + 'static) -> IResult<DriverInterface> {
+        match &self.record {
+            Some(trace) => DriverInterface::with_recording(backend, trace),
+            None => DriverInterface::with_backend(backend),
+        }
+    }
+
+    pub fn requirements(&self) -> &DriverRequirements {
+        &self.requirements
+    }
+
+    /// All discovered drivers in discovery order
+    pub fn candidates(&self) -> &[DriverCandidate] {
+        &self.candidates
+    }
+
+    /// Remaining drivers which match the requirements, ordered by preference
+    pub fn chain(&self) -> impl Iterator<Item = &DriverCandidate> {
+        self.chain.iter().map(|index| &self.candidates[*index])
+    }
+
+    /// Create a driver interface for the best remaining driver in the fallback chain.
+    /// Drivers which fail to load or report to be unavailable will be skipped.
+    pub fn create(&mut self) -> IResult<DriverInterface> {
+        if self.candidates.is_empty() {
+            return Err(InterfaceError::NoDriverFound);
+        }
+
+        let mut last_error = None;
+        while let Some(index) = self.chain.pop_front() {
+            /* reuse the instance created while probing (unless it would be missing in the trace) */
+            let instance = self.candidates[index]
+                .instance
+                .take()
+                .filter(|_| self.record.is_none());
+
+            let path = self.candidates[index].path.clone();
+            log::debug!("Trying to load driver from {}", path.display());
+
+            let result = match instance {
+                Some(interface) => Ok(interface),
+                None => (self.loader)(&self.candidates[index])
+                    .and_then(|backend| self.create_interface(backend)),
+            };
+
+            let interface = match result {
+                Ok(interface) => interface,
+                Err(err) => {
+                    if matches!(
+                        err,
+                        InterfaceError::DriverHashMismatch { .. }
+                            | InterfaceError::DriverIntegrityRejected { .. }
+                    ) {
+                        log::error!("Refusing to load driver {}: {}", path.display(), err);
+                    } else {
+                        log::warn!(
+                            "Failed to load driver {}: {}. Trying next driver.",
+                            path.display(),
+                            err
+                        );
+                    }
+
+                    self.failures.push((path, err.to_string()));
+                    last_error = Some(err);
+                    continue;
+                }
+            };
+
+            let info = DriverInfo::from_interface(&interface);
+            if !self.requirements.matches(&info) {
+                /* the driver has not been probed before and does not fulfill our requirements */
+                log::debug!("Driver {} does not match the requirements", path.display());
+                self.candidates[index].info = Some(Ok(info));
+                continue;
+            }
+
+            log::debug!("    -> success.");
+            return Ok(interface);
+        }
+
+        if let Some(err) = last_error {
+            return Err(err);
+        }
+
+        /* the chain is exhausted, report why the remaining drivers could not be used */
+        if self
+            .candidates
+            .iter()
+            .any(|candidate| candidate.driver_info().is_some())
+        {
+            return Err(InterfaceError::NoMatchingDriver);
+        }
+
+        let probe_failures = self
+            .candidates
+            .iter()
+            .filter_map(|candidate| match &candidate.info {
+                Some(Err(err)) => Some((candidate.path.clone(), err)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if self.failures.is_empty()
+            && !probe_failures.is_empty()
+            && probe_failures
+                .iter()
+                .all(|(_, err)| matches!(err, InterfaceError::InitializeDriverUnavailable))
+        {
+            return Err(InterfaceError::InitializeDriverUnavailable);
+        }
+
+        let failures = probe_failures
+            .into_iter()
+            .map(|(path, err)| (path, err.to_string()))
+            .chain(self.failures.iter().cloned())
+            .collect::<Vec<_>>();
+
+        if failures.is_empty() {
+            Err(InterfaceError::NoDriverFound)
+        } else {
+            Err(InterfaceError::DriversFailed { failures })
+        }
+    }
+}
+
+fn is_library_candidate(filename: &str) -> bool {
+    #[cfg(unix)]
+    {
+        if !filename.ends_with(".so") {
+            /* all files must end with .so */
+            return false;
+        }
+
+        let filename = filename.strip_prefix("lib").unwrap_or(filename);
+
+        if filename == "driver.so"
+            || filename.starts_with("driver_")
+            || filename.starts_with(obfstr!("valthrun_driver_"))
+        {
+            return true;
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        if !filename.ends_with(".dll") {
+            /* all files must end with .dll */
+            return false;
+        }
+
+        if filename == "driver.dll"
+            || filename.starts_with("driver_")
+            || filename.starts_with(obfstr!("valthrun_driver_"))
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn add_library_path(result: &mut Vec<PathBuf>, path: PathBuf) {
+    if result.contains(&path) {
+        return;
+    }
+
+    result.push(path);
+}
+
+pub(crate) fn populate_library_paths() -> Vec<PathBuf> {
+    let mut result = Vec::with_capacity(64);
+    if let Ok(path) = env::var(obfstr!("VT_DRIVER_PATH")) {
+        log::debug!("Adding env driver path: {}", path);
+        self::add_library_path(&mut result, PathBuf::from(path));
+    }
+
+    for directory in [
+        env::var(obfstr!("VT_DRIVER_DIR")).map(PathBuf::from).ok(),
+        env::current_exe()
+            .ok()
+            .and_then(|v| v.parent().map(|v| v.to_owned())),
+    ] {
+        let Some(directory) = directory else { continue };
+
+        if let Ok(driver_name) = env::var(obfstr!("VT_DRIVER_NAME")) {
+            self::add_library_path(&mut result, directory.join(driver_name));
+        }
+
+        match fs::read_dir(&directory) {
+            Ok(dir) => {
+                log::debug!("Adding drivers from {}", directory.display());
+                /*
+                 * Add all dlls which start with driver_/valthrun_driver_ to the candidate list.
+                 * Starting the driver which has been least recently modified.
+                 */
+                let mut candidates = dir
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .filter(|file_name| self::is_library_candidate(file_name))
+                    .map(|file_name| directory.join(file_name))
+                    .filter_map(|file| Some((file.metadata().ok()?.modified().ok()?, file)))
+                    .collect::<Vec<_>>();
+
+                candidates.sort_by_key(|(timestamp, _file)| *timestamp);
+                for (_, file) in candidates.into_iter().rev() {
+                    self::add_library_path(&mut result, file);
+                }
+            }
+            Err(err) => {
+                log::debug!(
+                    "Skipping looking for driver in {}: {}",
+                    directory.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        io,
+        path::PathBuf,
+        sync::{
+            Arc,
+            Mutex,
+        },
+    };
+
+    use vtd_protocol::types::DriverFeature;
+
+    use super::{
+        DriverCandidate,
+        DriverRequirements,
+        DriverSelection,
+    };
+    use crate::{
+        mock::MockDriver,
+        DriverBackend,
+        InterfaceError,
+    };
+
+    type LoadCounter = Arc<Mutex<HashMap<String, usize>>>;
+
+    /// Create a selection for the given drivers.
+    /// Drivers without a mock fail to load.
+    fn create_selection(
+        requirements: DriverRequirements,
+        drivers: Vec<(&str, Option<MockDriver>)>,
+    ) -> (DriverSelection, LoadCounter) {
+        let loads = LoadCounter::default();
+        let candidates = drivers
+            .iter()
+            .map(|(name, _)| DriverCandidate::new(PathBuf::from(format!("/nonexistent/{}", name))))
+            .collect::<Vec<_>>();
+
+        let drivers = drivers
+            .into_iter()
+            .map(|(name, driver)| (name.to_string(), driver))
+            .collect::<HashMap<_, _>>();
+
+        let loader_loads = loads.clone();
+        let selection = DriverSelection::from_candidates_with_loader(
+            requirements,
+            candidates,
+            move |candidate| {
+                let name = candidate.path.file_name().unwrap().to_string_lossy();
+                *loader_loads
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_default() += 1;
+
+                match &drivers[name.as_ref()] {
+                    Some(driver) => Ok(Box::new(driver.clone()) as Box<dyn DriverBackend>),
+                    None => Err(InterfaceError::DriverFileAccess {
+                        path: candidate.path.clone(),
+                        error: io::Error::from(io::ErrorKind::NotFound),
+                    }),
+                }
+            },
+        );
+
+        (selection, loads)
+    }
+
+    fn chain_names(selection: &DriverSelection) -> Vec<String> {
+        selection
+            .chain()
+            .map(|candidate| {
+                candidate
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn preferred_order() {
+        let (mut selection, loads) = self::create_selection(
+            DriverRequirements::default()
+                .with_features(DriverFeature::MemoryRead)
+                .with_preferred_features(
+                    DriverFeature::MemoryWrite | DriverFeature::ProcessModules,
+                ),
+            vec![
+                (
+                    "a",
+                    Some(
+                        MockDriver::new()
+                            .with_features(DriverFeature::MemoryRead | DriverFeature::MemoryWrite),
+                    ),
+                ),
+                (
+                    "b",
+                    Some(MockDriver::new().with_features(DriverFeature::ProcessList)),
+                ),
+                ("c", None),
+                (
+                    "d",
+                    Some(MockDriver::new().with_features(
+                        DriverFeature::MemoryRead
+                            | DriverFeature::MemoryWrite
+                            | DriverFeature::ProcessModules,
+                    )),
+                ),
+                (
+                    "e",
+                    Some(
+                        MockDriver::new().with_features(
+                            DriverFeature::MemoryRead | DriverFeature::ProcessModules,
+                        ),
+                    ),
+                ),
+            ],
+        );
+
+        /* drivers not matching the requirements or failing to load are excluded */
+        assert_eq!(self::chain_names(&selection), ["d", "a", "e"]);
+        assert!(matches!(
+            selection.candidates()[2].info,
+            Some(Err(InterfaceError::DriverFileAccess { .. }))
+        ));
+
+        /* the probed instance of the preferred driver is reused */
+        let interface = selection.create().unwrap();
+        assert!(interface
+            .driver_features()
+            .contains(DriverFeature::ProcessModules | DriverFeature::MemoryWrite));
+        assert!(loads.lock().unwrap().values().all(|count| *count == 1));
+
+        /* the other drivers have been released after probing and will be loaded again */
+        let interface = selection.create().unwrap();
+        assert!(!interface
+            .driver_features()
+            .contains(DriverFeature::ProcessModules));
+        assert_eq!(loads.lock().unwrap()["a"], 2);
+    }
+
+    #[test]
+    fn fallback() {
+        let unavailable = MockDriver::new();
+        unavailable.set_available(false);
+
+        let (mut selection, loads) = self::create_selection(
+            DriverRequirements::default(),
+            vec![
+                ("a", None),
+                ("b", Some(unavailable)),
+                ("c", Some(MockDriver::new())),
+                ("d", Some(MockDriver::new())),
+            ],
+        );
+        assert_eq!(self::chain_names(&selection), ["a", "b", "c", "d"]);
+
+        selection.create().unwrap();
+        assert_eq!(self::chain_names(&selection), ["d"]);
+
+        /* continue with the next driver once the selected driver failed */
+        selection.create().unwrap();
+        assert!(matches!(
+            selection.create(),
+            Err(InterfaceError::DriversFailed { failures }) if failures.len() == 2
+        ));
+        assert!(loads.lock().unwrap().values().all(|count| *count == 1));
+    }
+
+    #[test]
+    fn fallback_exhausted() {
+        let unavailable = MockDriver::new();
+        unavailable.set_available(false);
+
+        let (mut selection, _) = self::create_selection(
+            DriverRequirements::default(),
+            vec![("a", Some(unavailable.clone())), ("b", None)],
+        );
+        assert!(matches!(
+            selection.create(),
+            Err(InterfaceError::DriverFileAccess { .. })
+        ));
+
+        let (mut selection, _) = self::create_selection(
+            DriverRequirements::default(),
+            vec![("a", None), ("b", Some(unavailable.clone()))],
+        );
+        assert!(matches!(
+            selection.create(),
+            Err(InterfaceError::InitializeDriverUnavailable)
+        ));
+
+        /* errors of drivers which failed while probing are reported as well */
+        let requirements = DriverRequirements::default().with_features(DriverFeature::MemoryRead);
+        let (mut selection, _) = self::create_selection(
+            requirements.clone(),
+            vec![("a", Some(unavailable.clone())), ("b", None)],
+        );
+        let Err(InterfaceError::DriversFailed { failures }) = selection.create() else {
+            panic!("expected the probing failures");
+        };
+        assert_eq!(failures.len(), 2);
+        assert!(failures[1].0.ends_with("b"));
+
+        let (mut selection, _) =
+            self::create_selection(requirements, vec![("a", Some(unavailable))]);
+        assert!(matches!(
+            selection.create(),
+            Err(InterfaceError::InitializeDriverUnavailable)
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    path::Path,
+    sync::Arc,
+};
+
+use vtd_protocol::{
+    command::{
+        DriverCommandInitialize,
+        DriverCommandMemoryRead,
+        DriverCommandProcessList,
+        DriverCommandProcessModules,
+        InitializeResult,
+    },
+    types::{
+        DriverFeature,
+        MemoryAccessResult,
+        ProcessId,
+        ProcessInfo,
+        ProcessModuleInfo,
+    },
+    PROTOCOL_VERSION,
+};
+
+use super::MemoryDump;
+use crate::{
+    DriverBackend,
+    HandlerBackend,
+    IResult,
+};
+
+/// Process id reported if the dump does not contain the original process id
+const FALLBACK_PROCESS_ID: ProcessId = 1;
+
+/// Offline driver serving the process list, the module list and memory reads from a [MemoryDump].
+///
+/// The dump is exposed as the only running process.
+/// All other commands are not supported.
+pub struct DumpDriver {
+    dump: Arc<MemoryDump>,
+    handler: HandlerBackend,
+}
+
+impl DumpDriver {
+    pub fn open(path: &Path) -> IResult<Self> {
+        Ok(Self::new(MemoryDump::open(path)?))
+    }
+
+    pub fn new(dump: MemoryDump) -> Self {
+        let dump = Arc::new(dump);
+        let handler = Self::create_handler(&dump);
+        Self { dump, handler }
+    }
+
+    pub fn dump(&self) -> &MemoryDump {
+        &self.dump
+    }
+
+    /// Process id under which the dumped process is exposed
+    pub fn process_id(&self) -> ProcessId {
+        self::dump_process_id(&self.dump)
+    }
+
+    fn create_handler(dump: &Arc<MemoryDump>) -> HandlerBackend {
+        let mut handler = HandlerBackend::new();
+
+        handler.register(|command: &mut DriverCommandInitialize| {
+            command.driver_protocol_version = PROTOCOL_VERSION;
+            if command.client_protocol_version != PROTOCOL_VERSION {
+                return Ok::<_, String>(());
+            }
+
+            command.driver_version.set_application_name("dump");
+            command.driver_version.version_major = 1;
+            command.driver_features = DriverFeature::ProcessList
+                | DriverFeature::ProcessModules
+                | DriverFeature::MemoryRead;
+            command.result = InitializeResult::Success;
+            Ok(())
+        });
+
+        let handler_dump = dump.clone();
+        handler.register(move |command: &mut DriverCommandProcessList| {
+            if command.buffer_capacity >= 1 && !command.buffer.is_null() {
+                let mut info = ProcessInfo {
+                    process_id: self::dump_process_id(&handler_dump),
+                    ..Default::default()
+                };
+                info.set_image_base_name(handler_dump.process_name().unwrap_or("unknown"));
+                unsafe { command.buffer.write(info) };
+            }
+
+            command.process_count = 1;
+            Ok::<_, String>(())
+        });
+
+        let handler_dump = dump.clone();
+        handler.register(move |command: &mut DriverCommandProcessModules| {
+            if command.process_id != self::dump_process_id(&handler_dump) {
+                command.process_unknown = true;
+                return Ok::<_, String>(());
+            }
+
+            let modules = handler_dump.modules();
+            if !command.buffer.is_null() {
+                for (index, module) in modules.iter().take(command.buffer_capacity).enumerate() {
+                    let mut info = ProcessModuleInfo {
+                        base_address: module.base_address,
+                        module_size: module.size,
+                        ..Default::default()
+                    };
+                    info.set_base_dll_name(&module.name);
+                    unsafe { command.buffer.add(index).write(info) };
+                }
+            }
+
+            command.process_unknown = false;
+            command.module_count = modules.len();
+            Ok(())
+        });
+
+        let handler_dump = dump.clone();
+        handler.register(move |command: &mut DriverCommandMemoryRead| {
+            if command.process_id != self::dump_process_id(&handler_dump) {
+                command.result = MemoryAccessResult::ProcessUnknown;
+                return Ok::<_, String>(());
+            }
+
+            let buffer = if command.count > 0 {
+                unsafe { core::slice::from_raw_parts_mut(command.buffer, command.count) }
+            } else {
+                &mut []
+            };
+
+            command.result = handler_dump.read_memory(command.address, buffer);
+            Ok(())
+        });
+
+        handler
+    }
+}
+
+fn dump_process_id(dump: &MemoryDump) -> ProcessId {
+    dump.process_id().unwrap_or(FALLBACK_PROCESS_ID)
+}
+
+impl DriverBackend for DumpDriver {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        self.handler
+            .execute_command(command_id, payload, error_message)
+    }
+}
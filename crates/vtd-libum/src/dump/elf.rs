@@ -0,0 +1,333 @@
+//! ELF core file parsing and writing.
+//! Only 64 bit little endian core files are supported.
+use std::{
+    collections::BTreeMap,
+    io::{
+        self,
+        Write,
+    },
+};
+
+use vtd_protocol::codec::{
+    Codec,
+    CodecResult,
+    Decoder,
+    Encoder,
+};
+
+use super::{
+    DumpFile,
+    DumpFormat,
+    DumpModule,
+    DumpRegion,
+    MemoryDump,
+    ProcessSnapshot,
+};
+use crate::IResult;
+
+pub const ELF_MAGIC: &[u8; 4] = b"\x7FELF";
+
+pub const ELF_CLASS_64: u8 = 2;
+pub const ELF_DATA_LSB: u8 = 1;
+pub const ELF_VERSION_CURRENT: u8 = 1;
+pub const ELF_TYPE_CORE: u16 = 4;
+pub const ELF_MACHINE_X86_64: u16 = 62;
+
+pub const ELF_HEADER_SIZE: usize = 0x40;
+pub const ELF_PROGRAM_HEADER_SIZE: usize = 0x38;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_NOTE: u32 = 4;
+
+pub const PF_R: u32 = 4;
+
+pub const NT_PRPSINFO: u32 = 3;
+pub const NT_FILE: u32 = 0x46494C45;
+
+/// Offsets within the x86_64 `elf_prpsinfo` structure
+pub const PRPSINFO_SIZE: usize = 136;
+pub const PRPSINFO_PID_OFFSET: usize = 24;
+pub const PRPSINFO_FNAME_OFFSET: usize = 40;
+pub const PRPSINFO_FNAME_SIZE: usize = 16;
+
+/// Upper limit for the size of the note segment
+const MAX_NOTE_SIZE: u64 = 0x4000000;
+
+fn parse_file_note(dump: &mut MemoryDump, desc: &[u8]) -> IResult<()> {
+    let mut reader = Decoder::new(desc);
+    let count = super::check_count(u64::decode(&mut reader).map_err(super::truncated)?, "files")?;
+    let _page_size = u64::decode(&mut reader).map_err(super::truncated)?;
+
+    let mut ranges = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = u64::decode(&mut reader).map_err(super::truncated)?;
+        let end = u64::decode(&mut reader).map_err(super::truncated)?;
+        let _file_offset = u64::decode(&mut reader).map_err(super::truncated)?;
+        ranges.push((start, end));
+    }
+
+    /* the file names follow the ranges as NUL terminated strings */
+    let names = reader
+        .read_bytes(reader.remaining())
+        .map_err(super::truncated)?;
+    let mut names = names.split(|v| *v == 0);
+
+    /* a mapped file might be split into multiple ranges */
+    let mut modules = BTreeMap::<String, (u64, u64)>::new();
+    for (start, end) in ranges {
+        let name = names
+            .next()
+            .ok_or_else(|| super::dump_error("missing file name"))?;
+        let name = String::from_utf8_lossy(name).to_string();
+
+        let entry = modules.entry(name).or_insert((start, end));
+        entry.0 = entry.0.min(start);
+        entry.1 = entry.1.max(end);
+    }
+
+    dump.modules = modules
+        .into_iter()
+        .map(|(name, (start, end))| DumpModule {
+            name: super::module_file_name(&name).to_string(),
+            base_address: start,
+            size: end.saturating_sub(start),
+        })
+        .collect();
+    dump.modules.sort_by_key(|module| module.base_address);
+    Ok(())
+}
+
+fn parse_notes(dump: &mut MemoryDump, notes: &[u8]) -> IResult<()> {
+    let mut reader = Decoder::new(notes);
+    while reader.remaining() >= 12 {
+        let name_size = u32::decode(&mut reader).map_err(super::truncated)? as usize;
+        let desc_size = u32::decode(&mut reader).map_err(super::truncated)? as usize;
+        let note_type = u32::decode(&mut reader).map_err(super::truncated)?;
+
+        let name = reader
+            .read_bytes(name_size.next_multiple_of(4))
+            .map_err(super::truncated)?;
+        let desc = reader
+            .read_bytes(desc_size.next_multiple_of(4))
+            .map_err(super::truncated)?;
+        let desc = &desc[..desc_size];
+
+        if !name.starts_with(b"CORE") {
+            continue;
+        }
+
+        match note_type {
+            NT_PRPSINFO if desc.len() >= PRPSINFO_SIZE => {
+                let process_id = u32::from_le_bytes(
+                    desc[PRPSINFO_PID_OFFSET..PRPSINFO_PID_OFFSET + 4]
+                        .try_into()
+                        .unwrap(),
+                );
+                dump.process_id = Some(process_id);
+                dump.process_name = super::fixed_str(
+                    &desc[PRPSINFO_FNAME_OFFSET..PRPSINFO_FNAME_OFFSET + PRPSINFO_FNAME_SIZE],
+                );
+            }
+            NT_FILE => self::parse_file_note(dump, desc)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse(file: DumpFile) -> IResult<MemoryDump> {
+    let header = file.read_vec(0, ELF_HEADER_SIZE)?;
+    if header[4] != ELF_CLASS_64 || header[5] != ELF_DATA_LSB {
+        return Err(super::dump_error(
+            "only 64 bit little endian core files are supported",
+        ));
+    }
+
+    let mut reader = Decoder::new(&header[0x10..]);
+    let elf_type = u16::decode(&mut reader).map_err(super::truncated)?;
+    if elf_type != ELF_TYPE_CORE {
+        return Err(super::dump_error(format!(
+            "elf file is not a core file (type {})",
+            elf_type
+        )));
+    }
+
+    let _machine = u16::decode(&mut reader).map_err(super::truncated)?;
+    let _version = u32::decode(&mut reader).map_err(super::truncated)?;
+    let _entry = u64::decode(&mut reader).map_err(super::truncated)?;
+    let program_header_offset = u64::decode(&mut reader).map_err(super::truncated)?;
+    let _section_header_offset = u64::decode(&mut reader).map_err(super::truncated)?;
+    let _flags = u32::decode(&mut reader).map_err(super::truncated)?;
+    let _header_size = u16::decode(&mut reader).map_err(super::truncated)?;
+    let program_header_size = u16::decode(&mut reader).map_err(super::truncated)? as usize;
+    let program_header_count = u16::decode(&mut reader).map_err(super::truncated)? as usize;
+    if program_header_size < ELF_PROGRAM_HEADER_SIZE {
+        return Err(super::dump_error("invalid program header size"));
+    }
+
+    let program_headers = file.read_vec(
+        program_header_offset,
+        program_header_size * program_header_count,
+    )?;
+
+    let mut dump = MemoryDump {
+        format: DumpFormat::Core,
+        file,
+
+        process_id: None,
+        process_name: None,
+
+        modules: Vec::new(),
+        regions: Vec::new(),
+    };
+
+    for entry in program_headers.chunks_exact(program_header_size) {
+        let mut reader = Decoder::new(entry);
+        let segment_type = u32::decode(&mut reader).map_err(super::truncated)?;
+        let _flags = u32::decode(&mut reader).map_err(super::truncated)?;
+        let offset = u64::decode(&mut reader).map_err(super::truncated)?;
+        let virtual_address = u64::decode(&mut reader).map_err(super::truncated)?;
+        let _physical_address = u64::decode(&mut reader).map_err(super::truncated)?;
+        let file_size = u64::decode(&mut reader).map_err(super::truncated)?;
+
+        match segment_type {
+            /* segments not contained in the core file have a file size of zero */
+            PT_LOAD if file_size > 0 => dump.regions.push(DumpRegion {
+                address: virtual_address,
+                size: file_size,
+                file_offset: offset,
+            }),
+            PT_NOTE => {
+                if file_size > MAX_NOTE_SIZE {
+                    return Err(super::dump_error("note segment too large"));
+                }
+
+                let notes = dump.file.read_vec(offset, file_size as usize)?;
+                self::parse_notes(&mut dump, &notes)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(dump)
+}
+
+fn write_note(encoder: &mut impl Encoder, note_type: u32, desc: &[u8]) -> CodecResult<()> {
+    5u32.encode(encoder)?;
+    (desc.len() as u32).encode(encoder)?;
+    note_type.encode(encoder)?;
+    encoder.write_bytes(b"CORE\0\0\0\0")?;
+    encoder.write_bytes(desc)?;
+    encoder.write_bytes(&[0; 4][..desc.len().next_multiple_of(4) - desc.len()])
+}
+
+fn create_notes(snapshot: &ProcessSnapshot, page_size: u64) -> CodecResult<Vec<u8>> {
+    let mut prpsinfo = [0u8; PRPSINFO_SIZE];
+    prpsinfo[PRPSINFO_PID_OFFSET..PRPSINFO_PID_OFFSET + 4]
+        .copy_from_slice(&snapshot.process_id.to_le_bytes());
+    if let Some(name) = &snapshot.process_name {
+        /* the name must be NUL terminated */
+        let name = &name.as_bytes()[..name.len().min(PRPSINFO_FNAME_SIZE - 1)];
+        prpsinfo[PRPSINFO_FNAME_OFFSET..PRPSINFO_FNAME_OFFSET + name.len()].copy_from_slice(name);
+    }
+
+    let mut file_note = Vec::new();
+    (snapshot.modules.len() as u64).encode(&mut file_note)?;
+    page_size.encode(&mut file_note)?;
+    for module in snapshot.modules.iter() {
+        module.base_address.encode(&mut file_note)?;
+        module
+            .base_address
+            .saturating_add(module.size)
+            .encode(&mut file_note)?;
+        0u64.encode(&mut file_note)?;
+    }
+    for module in snapshot.modules.iter() {
+        file_note.write_bytes(module.name.as_bytes())?;
+        0u8.encode(&mut file_note)?;
+    }
+
+    let mut notes = Vec::new();
+    self::write_note(&mut notes, NT_PRPSINFO, &prpsinfo)?;
+    self::write_note(&mut notes, NT_FILE, &file_note)?;
+    Ok(notes)
+}
+
+/// A program header entry (type, flags, offset, address, size, alignment)
+type Segment = (u32, u32, u64, u64, u64, u64);
+
+fn create_header(segments: &[Segment], notes: &[u8]) -> CodecResult<Vec<u8>> {
+    let mut encoder = Vec::new();
+    encoder.write_bytes(ELF_MAGIC)?;
+    encoder.write_bytes(&[ELF_CLASS_64, ELF_DATA_LSB, ELF_VERSION_CURRENT])?;
+    encoder.write_bytes(&[0; 9])?;
+    ELF_TYPE_CORE.encode(&mut encoder)?;
+    ELF_MACHINE_X86_64.encode(&mut encoder)?;
+    (ELF_VERSION_CURRENT as u32).encode(&mut encoder)?;
+    0u64.encode(&mut encoder)?;
+    (ELF_HEADER_SIZE as u64).encode(&mut encoder)?;
+    0u64.encode(&mut encoder)?;
+    0u32.encode(&mut encoder)?;
+    (ELF_HEADER_SIZE as u16).encode(&mut encoder)?;
+    (ELF_PROGRAM_HEADER_SIZE as u16).encode(&mut encoder)?;
+    (segments.len() as u16).encode(&mut encoder)?;
+    0u16.encode(&mut encoder)?;
+    0u16.encode(&mut encoder)?;
+    0u16.encode(&mut encoder)?;
+
+    for (segment_type, flags, offset, address, size, align) in segments.iter() {
+        segment_type.encode(&mut encoder)?;
+        flags.encode(&mut encoder)?;
+        offset.encode(&mut encoder)?;
+        address.encode(&mut encoder)?;
+        0u64.encode(&mut encoder)?;
+        size.encode(&mut encoder)?;
+        size.encode(&mut encoder)?;
+        align.encode(&mut encoder)?;
+    }
+
+    encoder.write_bytes(notes)?;
+    Ok(encoder)
+}
+
+/// Write the snapshot as an ELF core file.
+/// Every captured region is stored as a page aligned `PT_LOAD` segment.
+pub fn write(snapshot: &ProcessSnapshot, output: &mut impl Write) -> io::Result<()> {
+    const PAGE_SIZE: u64 = 0x1000;
+
+    let notes = self::create_notes(snapshot, PAGE_SIZE).map_err(super::encode_error)?;
+    let program_header_count = 1 + snapshot.regions.len();
+    if program_header_count > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too many memory regions for an ELF core file",
+        ));
+    }
+
+    let notes_offset = (ELF_HEADER_SIZE + program_header_count * ELF_PROGRAM_HEADER_SIZE) as u64;
+
+    let mut segments: Vec<Segment> = vec![(PT_NOTE, 0, notes_offset, 0, notes.len() as u64, 0)];
+    let mut data_offset = notes_offset + notes.len() as u64;
+    let mut region_offsets = Vec::with_capacity(snapshot.regions.len());
+    for region in snapshot.regions.iter() {
+        data_offset = data_offset.next_multiple_of(PAGE_SIZE);
+        region_offsets.push(data_offset);
+
+        let size = region.data.len() as u64;
+        segments.push((PT_LOAD, PF_R, data_offset, region.address, size, PAGE_SIZE));
+        data_offset += size;
+    }
+
+    let header = self::create_header(&segments, &notes).map_err(super::encode_error)?;
+    output.write_all(&header)?;
+
+    let mut position = header.len() as u64;
+    for (region, offset) in snapshot.regions.iter().zip(region_offsets) {
+        output.write_all(&vec![0; (offset - position) as usize])?;
+        output.write_all(&region.data)?;
+        position = offset + region.data.len() as u64;
+    }
+
+    Ok(())
+}
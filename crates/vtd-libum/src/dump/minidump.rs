@@ -0,0 +1,342 @@
+//! Windows minidump parsing and writing.
+//! Only the streams required to serve the driver commands are evaluated.
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use vtd_protocol::codec::{
+    Codec,
+    CodecResult,
+    Decoder,
+    Encoder,
+};
+
+use super::{
+    DumpFile,
+    DumpFormat,
+    DumpModule,
+    DumpRegion,
+    MemoryDump,
+    ProcessSnapshot,
+};
+use crate::IResult;
+
+pub const MINIDUMP_SIGNATURE: &[u8; 4] = b"MDMP";
+pub const MINIDUMP_VERSION: u32 = 0xA793;
+
+pub const STREAM_MODULE_LIST: u32 = 4;
+pub const STREAM_MEMORY_LIST: u32 = 5;
+pub const STREAM_SYSTEM_INFO: u32 = 7;
+pub const STREAM_MEMORY64_LIST: u32 = 9;
+pub const STREAM_MISC_INFO: u32 = 15;
+
+pub const MINIDUMP_HEADER_SIZE: usize = 0x20;
+pub const MINIDUMP_DIRECTORY_SIZE: usize = 0x0C;
+pub const MINIDUMP_MODULE_SIZE: usize = 108;
+pub const MINIDUMP_MEMORY64_DESCRIPTOR_SIZE: usize = 0x10;
+pub const MINIDUMP_SYSTEM_INFO_SIZE: usize = 56;
+pub const MINIDUMP_MISC_INFO_SIZE: usize = 24;
+
+pub const MISC1_PROCESS_ID: u32 = 0x01;
+
+pub const PROCESSOR_ARCHITECTURE_AMD64: u16 = 9;
+pub const VER_PLATFORM_WIN32_NT: u32 = 2;
+
+/// Upper limit for the length of a module name
+const MAX_STRING_LENGTH: u32 = 0x1000;
+
+fn read_string(file: &DumpFile, rva: u32) -> IResult<String> {
+    let mut length = [0u8; 4];
+    file.read_at(rva as u64, &mut length)?;
+
+    let length = u32::from_le_bytes(length);
+    if length > MAX_STRING_LENGTH {
+        return Err(super::dump_error(format!("string at {:X} too long", rva)));
+    }
+
+    let buffer = file.read_vec(rva as u64 + 4, length as usize)?;
+    let characters = buffer
+        .chunks_exact(2)
+        .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+        .collect::<Vec<_>>();
+
+    Ok(String::from_utf16_lossy(&characters))
+}
+
+fn parse_modules(file: &DumpFile, data: &[u8]) -> IResult<Vec<DumpModule>> {
+    let mut reader = Decoder::new(data);
+    let count = super::check_count(
+        u32::decode(&mut reader).map_err(super::truncated)? as u64,
+        "modules",
+    )?;
+
+    let mut modules = Vec::with_capacity(count);
+    for _ in 0..count {
+        let entry = reader
+            .read_bytes(MINIDUMP_MODULE_SIZE)
+            .map_err(super::truncated)?;
+
+        let mut entry = Decoder::new(entry);
+        let base_address = u64::decode(&mut entry).map_err(super::truncated)?;
+        let size = u32::decode(&mut entry).map_err(super::truncated)? as u64;
+        let _checksum = u32::decode(&mut entry).map_err(super::truncated)?;
+        let _timestamp = u32::decode(&mut entry).map_err(super::truncated)?;
+        let name_rva = u32::decode(&mut entry).map_err(super::truncated)?;
+
+        let name = self::read_string(file, name_rva)?;
+        modules.push(DumpModule {
+            name: super::module_file_name(&name).to_string(),
+            base_address,
+            size,
+        });
+    }
+
+    Ok(modules)
+}
+
+fn parse_memory_list(data: &[u8]) -> IResult<Vec<DumpRegion>> {
+    let mut reader = Decoder::new(data);
+    let count = super::check_count(
+        u32::decode(&mut reader).map_err(super::truncated)? as u64,
+        "memory ranges",
+    )?;
+
+    let mut regions = Vec::with_capacity(count);
+    for _ in 0..count {
+        let address = u64::decode(&mut reader).map_err(super::truncated)?;
+        let size = u32::decode(&mut reader).map_err(super::truncated)? as u64;
+        let file_offset = u32::decode(&mut reader).map_err(super::truncated)? as u64;
+        regions.push(DumpRegion {
+            address,
+            size,
+            file_offset,
+        });
+    }
+
+    Ok(regions)
+}
+
+fn parse_memory64_list(data: &[u8]) -> IResult<Vec<DumpRegion>> {
+    let mut reader = Decoder::new(data);
+    let count = super::check_count(
+        u64::decode(&mut reader).map_err(super::truncated)?,
+        "memory ranges",
+    )?;
+
+    /* the contents of all ranges are stored consecutively */
+    let mut file_offset = u64::decode(&mut reader).map_err(super::truncated)?;
+    let mut regions = Vec::with_capacity(count);
+    for _ in 0..count {
+        let address = u64::decode(&mut reader).map_err(super::truncated)?;
+        let size = u64::decode(&mut reader).map_err(super::truncated)?;
+        regions.push(DumpRegion {
+            address,
+            size,
+            file_offset,
+        });
+
+        file_offset = file_offset
+            .checked_add(size)
+            .ok_or_else(|| super::dump_error("memory range exceeds the file"))?;
+    }
+
+    Ok(regions)
+}
+
+pub fn parse(file: DumpFile) -> IResult<MemoryDump> {
+    let header = file.read_vec(0, MINIDUMP_HEADER_SIZE)?;
+    let mut reader = Decoder::new(&header);
+    let _signature = u32::decode(&mut reader).map_err(super::truncated)?;
+    let _version = u32::decode(&mut reader).map_err(super::truncated)?;
+    let stream_count = super::check_count(
+        u32::decode(&mut reader).map_err(super::truncated)? as u64,
+        "streams",
+    )?;
+    let directory_rva = u32::decode(&mut reader).map_err(super::truncated)?;
+
+    let directory = file.read_vec(directory_rva as u64, stream_count * MINIDUMP_DIRECTORY_SIZE)?;
+
+    let mut dump = MemoryDump {
+        format: DumpFormat::Minidump,
+        file,
+
+        process_id: None,
+        process_name: None,
+
+        modules: Vec::new(),
+        regions: Vec::new(),
+    };
+
+    let mut reader = Decoder::new(&directory);
+    for _ in 0..stream_count {
+        let stream_type = u32::decode(&mut reader).map_err(super::truncated)?;
+        let data_size = u32::decode(&mut reader).map_err(super::truncated)?;
+        let rva = u32::decode(&mut reader).map_err(super::truncated)?;
+
+        match stream_type {
+            STREAM_MODULE_LIST => {
+                let data = dump.file.read_vec(rva as u64, data_size as usize)?;
+                dump.modules = self::parse_modules(&dump.file, &data)?;
+            }
+            STREAM_MEMORY_LIST => {
+                let data = dump.file.read_vec(rva as u64, data_size as usize)?;
+                dump.regions.extend(self::parse_memory_list(&data)?);
+            }
+            STREAM_MEMORY64_LIST => {
+                let data = dump.file.read_vec(rva as u64, data_size as usize)?;
+                dump.regions.extend(self::parse_memory64_list(&data)?);
+            }
+            STREAM_MISC_INFO if data_size >= 12 => {
+                let data = dump.file.read_vec(rva as u64, 12)?;
+                let mut reader = Decoder::new(&data);
+                let _size = u32::decode(&mut reader).map_err(super::truncated)?;
+                let flags = u32::decode(&mut reader).map_err(super::truncated)?;
+                let process_id = u32::decode(&mut reader).map_err(super::truncated)?;
+                if flags & MISC1_PROCESS_ID != 0 {
+                    dump.process_id = Some(process_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /* the main executable is the first module */
+    dump.process_name = dump.modules.first().map(|module| module.name.clone());
+    Ok(dump)
+}
+
+fn encode_string(value: &str, encoder: &mut impl Encoder) -> CodecResult<()> {
+    let characters = value
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+
+    (characters.len() as u32).encode(encoder)?;
+    encoder.write_bytes(&characters)?;
+    0u16.encode(encoder)
+}
+
+/// Encode all streams except the memory contents which follow the returned metadata
+fn create_metadata(snapshot: &ProcessSnapshot) -> CodecResult<Vec<u8>> {
+    const STREAM_COUNT: usize = 4;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or(0);
+
+    let directory_rva = MINIDUMP_HEADER_SIZE;
+    let system_info_rva = directory_rva + STREAM_COUNT * MINIDUMP_DIRECTORY_SIZE;
+    let module_list_rva = system_info_rva + MINIDUMP_SYSTEM_INFO_SIZE;
+    let module_list_size = 4 + snapshot.modules.len() * MINIDUMP_MODULE_SIZE;
+    let misc_info_rva = module_list_rva + module_list_size;
+    let memory_list_rva = misc_info_rva + MINIDUMP_MISC_INFO_SIZE;
+    let memory_list_size = 16 + snapshot.regions.len() * MINIDUMP_MEMORY64_DESCRIPTOR_SIZE;
+
+    /* the CSD version is followed by the module names */
+    let mut strings = Vec::new();
+    self::encode_string("", &mut strings)?;
+    let csd_version_rva = memory_list_rva + memory_list_size;
+    let mut module_name_rvas = Vec::with_capacity(snapshot.modules.len());
+    for module in snapshot.modules.iter() {
+        module_name_rvas.push(csd_version_rva + strings.len());
+        self::encode_string(&module.name, &mut strings)?;
+    }
+
+    let memory_rva = csd_version_rva + strings.len();
+    let mut writer = Vec::new();
+    writer.write_bytes(MINIDUMP_SIGNATURE)?;
+    MINIDUMP_VERSION.encode(&mut writer)?;
+    (STREAM_COUNT as u32).encode(&mut writer)?;
+    (directory_rva as u32).encode(&mut writer)?;
+    0u32.encode(&mut writer)?;
+    timestamp.encode(&mut writer)?;
+    0u64.encode(&mut writer)?;
+
+    for (stream_type, size, rva) in [
+        (
+            STREAM_SYSTEM_INFO,
+            MINIDUMP_SYSTEM_INFO_SIZE,
+            system_info_rva,
+        ),
+        (STREAM_MODULE_LIST, module_list_size, module_list_rva),
+        (STREAM_MISC_INFO, MINIDUMP_MISC_INFO_SIZE, misc_info_rva),
+        (STREAM_MEMORY64_LIST, memory_list_size, memory_list_rva),
+    ] {
+        stream_type.encode(&mut writer)?;
+        (size as u32).encode(&mut writer)?;
+        (rva as u32).encode(&mut writer)?;
+    }
+
+    /* system info */
+    PROCESSOR_ARCHITECTURE_AMD64.encode(&mut writer)?;
+    0u16.encode(&mut writer)?;
+    0u16.encode(&mut writer)?;
+    0u8.encode(&mut writer)?;
+    0u8.encode(&mut writer)?;
+    0u32.encode(&mut writer)?;
+    0u32.encode(&mut writer)?;
+    0u32.encode(&mut writer)?;
+    VER_PLATFORM_WIN32_NT.encode(&mut writer)?;
+    (csd_version_rva as u32).encode(&mut writer)?;
+    0u16.encode(&mut writer)?;
+    0u16.encode(&mut writer)?;
+    writer.write_bytes(&[0; 24])?;
+
+    /* module list */
+    (snapshot.modules.len() as u32).encode(&mut writer)?;
+    for (module, name_rva) in snapshot.modules.iter().zip(module_name_rvas) {
+        module.base_address.encode(&mut writer)?;
+        u32::try_from(module.size)
+            .unwrap_or(u32::MAX)
+            .encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        (name_rva as u32).encode(&mut writer)?;
+        writer.write_bytes(&[0; MINIDUMP_MODULE_SIZE - 24])?;
+    }
+
+    /* misc info */
+    (MINIDUMP_MISC_INFO_SIZE as u32).encode(&mut writer)?;
+    MISC1_PROCESS_ID.encode(&mut writer)?;
+    snapshot.process_id.encode(&mut writer)?;
+    writer.write_bytes(&[0; MINIDUMP_MISC_INFO_SIZE - 12])?;
+
+    /* memory64 list, the region contents are stored consecutively */
+    (snapshot.regions.len() as u64).encode(&mut writer)?;
+    (memory_rva as u64).encode(&mut writer)?;
+    for region in snapshot.regions.iter() {
+        region.address.encode(&mut writer)?;
+        (region.data.len() as u64).encode(&mut writer)?;
+    }
+
+    writer.write_bytes(&strings)?;
+    Ok(writer)
+}
+
+/// Write the snapshot as a full memory minidump containing the
+/// system info, module list, misc info and memory64 list streams.
+pub fn write(snapshot: &ProcessSnapshot, output: &mut impl Write) -> io::Result<()> {
+    let metadata = self::create_metadata(snapshot).map_err(super::encode_error)?;
+    if u32::try_from(metadata.len()).is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "minidump metadata too large",
+        ));
+    }
+
+    output.write_all(&metadata)?;
+
+    for region in snapshot.regions.iter() {
+        output.write_all(&region.data)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,623 @@
+//! Memory dumps (Windows minidumps and ELF core files).
+//!
+//! A [MemoryDump] can be served by the [DumpDriver] so any driver interface client
+//! can be executed against a saved memory snapshot instead of a live process.
+//! Snapshots of live processes can be captured and saved via [ProcessSnapshot].
+//! Snapshots only contain module memory and explicitly requested ranges
+//! as the driver protocol can not enumerate the memory regions of a process.
+use std::{
+    fs::File,
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    path::Path,
+    sync::Mutex,
+};
+
+use vtd_protocol::{
+    codec::CodecError,
+    types::{
+        MemoryAccessResult,
+        ProcessId,
+    },
+};
+
+use crate::{
+    IResult,
+    InterfaceError,
+};
+
+mod driver;
+pub use driver::*;
+
+mod snapshot;
+pub use snapshot::*;
+
+mod elf;
+mod minidump;
+
+/// Upper limit for the number of modules and memory regions within a dump
+const MAX_DUMP_ENTRIES: usize = 0x100000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Windows minidump
+    Minidump,
+
+    /// ELF core file
+    Core,
+}
+
+impl DumpFormat {
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(minidump::MINIDUMP_SIGNATURE) {
+            Some(Self::Minidump)
+        } else if header.starts_with(elf::ELF_MAGIC) {
+            Some(Self::Core)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpModule {
+    pub name: String,
+    pub base_address: u64,
+    pub size: u64,
+}
+
+/// A memory region contained within the dump file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpRegion {
+    pub address: u64,
+    pub size: u64,
+
+    /// Offset of the region contents within the dump file
+    pub file_offset: u64,
+}
+
+fn dump_error(message: impl Into<String>) -> InterfaceError {
+    InterfaceError::DumpInvalid {
+        message: message.into(),
+    }
+}
+
+fn truncated(_: CodecError) -> InterfaceError {
+    self::dump_error("unexpected end of data")
+}
+
+fn encode_error(error: CodecError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error.to_string())
+}
+
+/// Random access reader for the dump file
+struct DumpFile {
+    file: Mutex<File>,
+    length: u64,
+}
+
+impl DumpFile {
+    fn check_range(&self, offset: u64, length: usize) -> IResult<()> {
+        if offset
+            .checked_add(length as u64)
+            .map(|end| end > self.length)
+            .unwrap_or(true)
+        {
+            return Err(self::dump_error(format!(
+                "read of {:X} bytes at {:X} exceeds the file",
+                length, offset
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> IResult<()> {
+        self.check_range(offset, buffer.len())?;
+
+        let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(buffer))
+            .map_err(|err| self::dump_error(format!("read at {:X}: {}", offset, err)))
+    }
+
+    fn read_vec(&self, offset: u64, length: usize) -> IResult<Vec<u8>> {
+        /* validate the range before allocating the buffer */
+        self.check_range(offset, length)?;
+
+        let mut buffer = vec![0u8; length];
+        self.read_at(offset, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// A parsed memory dump of a single process.
+/// The memory contents are read from the dump file on demand.
+pub struct MemoryDump {
+    format: DumpFormat,
+    file: DumpFile,
+
+    process_id: Option<ProcessId>,
+    process_name: Option<String>,
+
+    modules: Vec<DumpModule>,
+
+    /// Memory regions sorted by their address
+    regions: Vec<DumpRegion>,
+}
+
+impl MemoryDump {
+    pub fn open(path: &Path) -> IResult<Self> {
+        let file = File::open(path).map_err(|error| InterfaceError::DriverFileAccess {
+            path: path.to_owned(),
+            error,
+        })?;
+
+        let length = file
+            .metadata()
+            .map_err(|error| InterfaceError::DriverFileAccess {
+                path: path.to_owned(),
+                error,
+            })?
+            .len();
+
+        let file = DumpFile {
+            file: Mutex::new(file),
+            length,
+        };
+
+        let mut header = [0u8; 4];
+        file.read_at(0, &mut header)?;
+
+        let mut dump = match DumpFormat::detect(&header) {
+            Some(DumpFormat::Minidump) => minidump::parse(file)?,
+            Some(DumpFormat::Core) => elf::parse(file)?,
+            None => return Err(self::dump_error("unknown dump format")),
+        };
+
+        if dump
+            .regions
+            .iter()
+            .any(|region| region.address.checked_add(region.size).is_none())
+        {
+            return Err(self::dump_error("memory region exceeds the address space"));
+        }
+
+        dump.regions.sort_by_key(|region| region.address);
+        if dump
+            .regions
+            .windows(2)
+            .any(|regions| regions[0].address + regions[0].size > regions[1].address)
+        {
+            return Err(self::dump_error("memory regions overlap"));
+        }
+
+        log::debug!(
+            "Loaded {:?} {} with {} modules and {} memory regions",
+            dump.format,
+            path.display(),
+            dump.modules.len(),
+            dump.regions.len()
+        );
+        Ok(dump)
+    }
+
+    pub fn format(&self) -> DumpFormat {
+        self.format
+    }
+
+    /// Process id as recorded by the dump (if any)
+    pub fn process_id(&self) -> Option<ProcessId> {
+        self.process_id
+    }
+
+    /// Name of the dumped process.
+    /// Falls back to the name of the first module if the dump does not contain the process name.
+    pub fn process_name(&self) -> Option<&str> {
+        self.process_name
+            .as_deref()
+            .or_else(|| self.modules.first().map(|module| module.name.as_str()))
+    }
+
+    pub fn modules(&self) -> &[DumpModule] {
+        &self.modules
+    }
+
+    pub fn regions(&self) -> &[DumpRegion] {
+        &self.regions
+    }
+
+    fn find_region(&self, address: u64) -> Option<&DumpRegion> {
+        let index = self
+            .regions
+            .partition_point(|region| region.address + region.size <= address);
+
+        self.regions
+            .get(index)
+            .filter(|region| region.address <= address)
+    }
+
+    /// Read memory from the dump.
+    /// Reads spanning multiple adjacent regions are supported.
+    pub fn read_memory(&self, address: u64, buffer: &mut [u8]) -> MemoryAccessResult {
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let current_address = address + offset as u64;
+            let Some(region) = self.find_region(current_address) else {
+                return MemoryAccessResult::PartialSuccess {
+                    bytes_copied: offset,
+                };
+            };
+
+            let region_offset = current_address - region.address;
+            let chunk_length = ((region.size - region_offset) as usize).min(buffer.len() - offset);
+
+            if let Err(err) = self.file.read_at(
+                region.file_offset + region_offset,
+                &mut buffer[offset..offset + chunk_length],
+            ) {
+                log::warn!(
+                    "Failed to read dump memory at {:X}: {}",
+                    current_address,
+                    err
+                );
+                return MemoryAccessResult::PartialSuccess {
+                    bytes_copied: offset,
+                };
+            }
+
+            offset += chunk_length;
+        }
+
+        MemoryAccessResult::Success
+    }
+}
+
+/// Decode a fixed size, NUL terminated string
+fn fixed_str(bytes: &[u8]) -> Option<String> {
+    let length = bytes.iter().position(|v| *v == 0).unwrap_or(bytes.len());
+    if length == 0 {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&bytes[..length]).to_string())
+}
+
+/// Name of the module without its directory
+fn module_file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn check_count(count: u64, name: &str) -> IResult<usize> {
+    if count > MAX_DUMP_ENTRIES as u64 {
+        return Err(self::dump_error(format!("too many {} ({})", name, count)));
+    }
+
+    Ok(count as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+        path::PathBuf,
+    };
+
+    use vtd_protocol::{
+        codec::{
+            Codec,
+            CodecResult,
+            Encoder,
+        },
+        types::DirectoryTableType,
+    };
+
+    use super::{
+        elf,
+        minidump,
+        DumpDriver,
+        DumpFormat,
+        MemoryDump,
+        ProcessSnapshot,
+        SnapshotConfig,
+    };
+    use crate::{
+        mock::{
+            MockDriver,
+            MockProcess,
+        },
+        DriverInterface,
+        InterfaceError,
+    };
+
+    fn write_dump(name: &str, contents: Vec<u8>) -> PathBuf {
+        let path = env::temp_dir().join(format!("vtd-dump-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn create_minidump() -> CodecResult<Vec<u8>> {
+        let module_name = "C:\\game\\cs2.exe"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+
+        let directory_rva = minidump::MINIDUMP_HEADER_SIZE as u32;
+        let module_list_rva = directory_rva + 3 * minidump::MINIDUMP_DIRECTORY_SIZE as u32;
+        let module_list_size = 4 + minidump::MINIDUMP_MODULE_SIZE as u32;
+        let misc_info_rva = module_list_rva + module_list_size;
+        let memory_list_rva = misc_info_rva + 24;
+        let memory_list_size = 16 + 2 * 16;
+        let module_name_rva = memory_list_rva + memory_list_size;
+        let memory_rva = module_name_rva + 4 + module_name.len() as u32;
+
+        let mut writer = Vec::new();
+        writer.write_bytes(minidump::MINIDUMP_SIGNATURE)?;
+        minidump::MINIDUMP_VERSION.encode(&mut writer)?;
+        3u32.encode(&mut writer)?;
+        directory_rva.encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        0u64.encode(&mut writer)?;
+
+        for (stream_type, size, rva) in [
+            (
+                minidump::STREAM_MODULE_LIST,
+                module_list_size,
+                module_list_rva,
+            ),
+            (minidump::STREAM_MISC_INFO, 24, misc_info_rva),
+            (
+                minidump::STREAM_MEMORY64_LIST,
+                memory_list_size,
+                memory_list_rva,
+            ),
+        ] {
+            stream_type.encode(&mut writer)?;
+            size.encode(&mut writer)?;
+            rva.encode(&mut writer)?;
+        }
+
+        1u32.encode(&mut writer)?;
+        0x140000000u64.encode(&mut writer)?;
+        0x2000u32.encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        module_name_rva.encode(&mut writer)?;
+        writer.write_bytes(&[0; minidump::MINIDUMP_MODULE_SIZE - 24])?;
+
+        24u32.encode(&mut writer)?;
+        minidump::MISC1_PROCESS_ID.encode(&mut writer)?;
+        1337u32.encode(&mut writer)?;
+        writer.write_bytes(&[0; 12])?;
+
+        2u64.encode(&mut writer)?;
+        (memory_rva as u64).encode(&mut writer)?;
+        0x140000000u64.encode(&mut writer)?;
+        0x10u64.encode(&mut writer)?;
+        0x140000010u64.encode(&mut writer)?;
+        0x10u64.encode(&mut writer)?;
+
+        (module_name.len() as u32).encode(&mut writer)?;
+        writer.write_bytes(&module_name)?;
+        writer.write_bytes(&(0..0x20).collect::<Vec<u8>>())?;
+        Ok(writer)
+    }
+
+    fn create_core() -> CodecResult<Vec<u8>> {
+        let mut notes = Vec::new();
+        let mut prpsinfo = [0u8; elf::PRPSINFO_SIZE];
+        prpsinfo[elf::PRPSINFO_PID_OFFSET..elf::PRPSINFO_PID_OFFSET + 4]
+            .copy_from_slice(&4242u32.to_le_bytes());
+        prpsinfo[elf::PRPSINFO_FNAME_OFFSET..elf::PRPSINFO_FNAME_OFFSET + 4]
+            .copy_from_slice(b"game");
+
+        let mut file_note = Vec::new();
+        2u64.encode(&mut file_note)?;
+        0x1000u64.encode(&mut file_note)?;
+        for (start, end) in [(0x400000u64, 0x401000u64), (0x401000, 0x403000)] {
+            start.encode(&mut file_note)?;
+            end.encode(&mut file_note)?;
+            0u64.encode(&mut file_note)?;
+        }
+        file_note.write_bytes(b"/opt/game/game\0/opt/game/game\0")?;
+        file_note.resize(file_note.len().next_multiple_of(4), 0);
+
+        for (note_type, desc) in [
+            (elf::NT_PRPSINFO, &prpsinfo[..]),
+            (elf::NT_FILE, &file_note[..]),
+        ] {
+            5u32.encode(&mut notes)?;
+            (desc.len() as u32).encode(&mut notes)?;
+            note_type.encode(&mut notes)?;
+            notes.write_bytes(b"CORE\0\0\0\0")?;
+            notes.write_bytes(desc)?;
+        }
+
+        let notes_offset = (elf::ELF_HEADER_SIZE + 2 * elf::ELF_PROGRAM_HEADER_SIZE) as u64;
+        let memory_offset = notes_offset + notes.len() as u64;
+
+        let mut writer = Vec::new();
+        writer.write_bytes(elf::ELF_MAGIC)?;
+        writer.write_bytes(&[elf::ELF_CLASS_64, elf::ELF_DATA_LSB, 1, 0, 0, 0, 0, 0])?;
+        writer.write_bytes(&[0; 4])?;
+        elf::ELF_TYPE_CORE.encode(&mut writer)?;
+        62u16.encode(&mut writer)?;
+        1u32.encode(&mut writer)?;
+        0u64.encode(&mut writer)?;
+        (elf::ELF_HEADER_SIZE as u64).encode(&mut writer)?;
+        0u64.encode(&mut writer)?;
+        0u32.encode(&mut writer)?;
+        (elf::ELF_HEADER_SIZE as u16).encode(&mut writer)?;
+        (elf::ELF_PROGRAM_HEADER_SIZE as u16).encode(&mut writer)?;
+        2u16.encode(&mut writer)?;
+        0u16.encode(&mut writer)?;
+        0u16.encode(&mut writer)?;
+        0u16.encode(&mut writer)?;
+
+        for (segment_type, offset, address, size) in [
+            (elf::PT_NOTE, notes_offset, 0u64, notes.len() as u64),
+            (elf::PT_LOAD, memory_offset, 0x400000, 0x10),
+        ] {
+            segment_type.encode(&mut writer)?;
+            4u32.encode(&mut writer)?;
+            offset.encode(&mut writer)?;
+            address.encode(&mut writer)?;
+            0u64.encode(&mut writer)?;
+            size.encode(&mut writer)?;
+            size.encode(&mut writer)?;
+            0u64.encode(&mut writer)?;
+        }
+
+        writer.write_bytes(&notes)?;
+        writer.write_bytes(&[0xAB; 0x10])?;
+        Ok(writer)
+    }
+
+    #[test]
+    fn minidump() {
+        let path = self::write_dump("minidump.dmp", self::create_minidump().unwrap());
+        let interface = DriverInterface::with_backend(DumpDriver::open(&path).unwrap()).unwrap();
+
+        let processes = interface.list_processes().unwrap();
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].process_id, 1337);
+        assert_eq!(processes[0].get_image_base_name(), Some("cs2.exe"));
+
+        let modules = interface
+            .list_modules(1337, DirectoryTableType::Default)
+            .unwrap();
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].get_base_dll_name(), Some("cs2.exe"));
+        assert_eq!(modules[0].base_address, 0x140000000);
+
+        /* read across both memory ranges */
+        let mut buffer = [0u8; 4];
+        interface
+            .read_slice(1337, DirectoryTableType::Default, 0x14000000E, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [0x0E, 0x0F, 0x10, 0x11]);
+
+        assert!(matches!(
+            interface.read_slice(1337, DirectoryTableType::Default, 0x14000001E, &mut buffer),
+            Err(InterfaceError::MemoryAccessFailed)
+        ));
+        assert!(matches!(
+            interface.read_slice(1, DirectoryTableType::Default, 0x140000000, &mut buffer),
+            Err(InterfaceError::ProcessUnknown)
+        ));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn core() {
+        let path = self::write_dump("core", self::create_core().unwrap());
+        let dump = MemoryDump::open(&path).unwrap();
+        assert_eq!(dump.format(), DumpFormat::Core);
+        assert_eq!(dump.process_id(), Some(4242));
+        assert_eq!(dump.process_name(), Some("game"));
+        assert_eq!(dump.modules().len(), 1);
+        assert_eq!(dump.modules()[0].name, "game");
+        assert_eq!(dump.modules()[0].base_address, 0x400000);
+        assert_eq!(dump.modules()[0].size, 0x3000);
+
+        let mut buffer = [0u8; 0x10];
+        assert!(matches!(
+            dump.read_memory(0x400000, &mut buffer),
+            vtd_protocol::types::MemoryAccessResult::Success
+        ));
+        assert_eq!(buffer, [0xAB; 0x10]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let path = self::write_dump("invalid", b"MDMP\x93\xA7\0\0\xFF\xFF\xFF\xFF".to_vec());
+        assert!(matches!(
+            MemoryDump::open(&path),
+            Err(InterfaceError::DumpInvalid { .. })
+        ));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let image = (0..0x3000)
+            .map(|value| (value / 7) as u8)
+            .collect::<Vec<_>>();
+        let driver = MockDriver::new().with_process(
+            MockProcess::new(42, "game.exe")
+                .with_module_image("game.exe", 0x140000000, image.clone())
+                .with_paged_out(0x140001000..0x140002000)
+                .with_memory(0x200000, vec![0xCC; 0x1000]),
+        );
+        let interface = DriverInterface::with_backend(driver).unwrap();
+
+        let config = SnapshotConfig::default().with_range(0x200000..0x200800);
+        let snapshot = ProcessSnapshot::capture(&interface, 42, &config).unwrap();
+        assert_eq!(snapshot.process_name.as_deref(), Some("game.exe"));
+        assert_eq!(snapshot.skipped_pages, 1);
+        assert_eq!(
+            snapshot
+                .regions
+                .iter()
+                .map(|region| (region.address, region.data.len()))
+                .collect::<Vec<_>>(),
+            [
+                (0x200000, 0x1000),
+                (0x140000000, 0x1000),
+                (0x140002000, 0x1000)
+            ]
+        );
+
+        for (format, name) in [
+            (DumpFormat::Minidump, "snapshot.dmp"),
+            (DumpFormat::Core, "snapshot.core"),
+        ] {
+            let path = env::temp_dir().join(format!("vtd-dump-{}-{}", std::process::id(), name));
+            snapshot.save(format, &path).unwrap();
+
+            let driver = DumpDriver::open(&path).unwrap();
+            assert_eq!(driver.dump().format(), format);
+            let interface = DriverInterface::with_backend(driver).unwrap();
+
+            let processes = interface.list_processes().unwrap();
+            assert_eq!(processes[0].process_id, 42);
+            assert_eq!(processes[0].get_image_base_name(), Some("game.exe"));
+
+            let modules = interface
+                .list_modules(42, DirectoryTableType::Default)
+                .unwrap();
+            assert_eq!(modules.len(), 1);
+            assert_eq!(modules[0].get_base_dll_name(), Some("game.exe"));
+            assert_eq!(modules[0].base_address, 0x140000000);
+            assert_eq!(modules[0].module_size, 0x3000);
+
+            let mut buffer = [0u8; 0x10];
+            interface
+                .read_slice(42, DirectoryTableType::Default, 0x140002FF0, &mut buffer)
+                .unwrap();
+            assert_eq!(buffer, image[0x2FF0..0x3000]);
+            interface
+                .read_slice(42, DirectoryTableType::Default, 0x200100, &mut buffer)
+                .unwrap();
+            assert_eq!(buffer, [0xCC; 0x10]);
+
+            /* the paged out page has been skipped */
+            assert!(matches!(
+                interface.read_slice(42, DirectoryTableType::Default, 0x140001000, &mut buffer),
+                Err(InterfaceError::MemoryAccessFailed)
+            ));
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
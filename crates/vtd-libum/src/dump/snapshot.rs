@@ -0,0 +1,286 @@
+use std::{
+    fs::File,
+    io::{
+        self,
+        BufWriter,
+        Write,
+    },
+    ops::Range,
+    path::Path,
+};
+
+use vtd_protocol::types::{
+    DirectoryTableType,
+    ProcessId,
+};
+
+use super::{
+    elf,
+    minidump,
+    DumpFormat,
+    DumpModule,
+};
+use crate::{
+    DriverInterface,
+    IResult,
+    InterfaceError,
+};
+
+/// Options for capturing a [ProcessSnapshot]
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    directory_table_type: DirectoryTableType,
+    page_size: u64,
+    chunk_size: u64,
+    additional_ranges: Vec<Range<u64>>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            directory_table_type: DirectoryTableType::Default,
+            page_size: 0x1000,
+            chunk_size: 0x100000,
+            additional_ranges: Vec::new(),
+        }
+    }
+}
+
+impl SnapshotConfig {
+    pub fn with_directory_table_type(mut self, directory_table_type: DirectoryTableType) -> Self {
+        self.directory_table_type = directory_table_type;
+        self
+    }
+
+    /// Granularity in which unreadable memory will be skipped
+    pub fn with_page_size(mut self, page_size: u64) -> Self {
+        assert!(page_size > 0, "page size must not be zero");
+        self.page_size = page_size;
+        self
+    }
+
+    /// Maximum number of bytes read with a single command.
+    /// Chunks which can not be read at once are read page by page.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Capture an additional memory range which is not part of any module.
+    /// This is the only way to include heap or stack memory within a snapshot.
+    pub fn with_range(mut self, range: Range<u64>) -> Self {
+        self.additional_ranges.push(range);
+        self
+    }
+}
+
+/// A captured memory region
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotRegion {
+    pub address: u64,
+    pub data: Vec<u8>,
+}
+
+/// Memory snapshot of a process captured through the driver interface.
+///
+/// The driver protocol does not provide a way to enumerate the memory regions of a process.
+/// Therefore a snapshot only contains the memory of the loaded modules and the ranges
+/// explicitly added via [SnapshotConfig::with_range]. Heaps, stacks and any other
+/// dynamically allocated memory will be missing unless their ranges are specified.
+///
+/// The snapshot can be saved as a Windows minidump or an ELF core file
+/// and later be loaded again by the [DumpDriver](super::DumpDriver).
+#[derive(Debug, Clone)]
+pub struct ProcessSnapshot {
+    pub process_id: ProcessId,
+    pub process_name: Option<String>,
+
+    pub modules: Vec<DumpModule>,
+
+    /// Captured memory regions sorted by their address
+    pub regions: Vec<SnapshotRegion>,
+
+    /// Number of pages which could not be read
+    pub skipped_pages: usize,
+}
+
+impl ProcessSnapshot {
+    /// Capture all modules (and the additionally configured ranges) of the target process.
+    /// Pages which can not be read are skipped.
+    ///
+    /// Memory outside of these ranges is not captured (see [ProcessSnapshot]).
+    pub fn capture(
+        interface: &DriverInterface,
+        process_id: ProcessId,
+        config: &SnapshotConfig,
+    ) -> IResult<Self> {
+        let process_name = match interface.list_processes() {
+            Ok(processes) => processes
+                .iter()
+                .find(|process| process.process_id == process_id)
+                .and_then(|process| process.get_image_base_name())
+                .map(str::to_string),
+            Err(err) => {
+                log::debug!("Failed to resolve the process name: {}", err);
+                None
+            }
+        };
+
+        let modules = interface
+            .list_modules(process_id, config.directory_table_type)?
+            .into_iter()
+            .map(|module| DumpModule {
+                name: module.get_base_dll_name().unwrap_or("unknown").to_string(),
+                base_address: module.base_address,
+                size: module.module_size,
+            })
+            .collect::<Vec<_>>();
+
+        let mut snapshot = Self {
+            process_id,
+            process_name,
+
+            modules,
+
+            regions: Vec::new(),
+            skipped_pages: 0,
+        };
+
+        let ranges = snapshot
+            .modules
+            .iter()
+            .map(|module| module.base_address..module.base_address.saturating_add(module.size))
+            .chain(config.additional_ranges.iter().cloned());
+
+        for range in self::merge_ranges(ranges, config.page_size) {
+            snapshot.capture_range(interface, config, range)?;
+        }
+
+        log::debug!(
+            "Captured {} regions of process {} ({} pages skipped)",
+            snapshot.regions.len(),
+            process_id,
+            snapshot.skipped_pages
+        );
+        Ok(snapshot)
+    }
+
+    fn capture_range(
+        &mut self,
+        interface: &DriverInterface,
+        config: &SnapshotConfig,
+        range: Range<u64>,
+    ) -> IResult<()> {
+        let chunk_size = config.chunk_size.max(config.page_size);
+
+        let mut current = range.start;
+        while current < range.end {
+            let chunk_end = current.saturating_add(chunk_size).min(range.end);
+            let mut chunk = vec![0u8; (chunk_end - current) as usize];
+
+            match interface.read_slice(
+                self.process_id,
+                config.directory_table_type,
+                current,
+                &mut chunk,
+            ) {
+                Ok(_) => self.push_memory(current, &chunk),
+                Err(InterfaceError::MemoryAccessFailed | InterfaceError::MemoryAccessPagedOut) => {
+                    self.capture_pages(interface, config, current, &mut chunk)?
+                }
+                Err(err) => return Err(err),
+            }
+
+            current = chunk_end;
+        }
+
+        Ok(())
+    }
+
+    fn capture_pages(
+        &mut self,
+        interface: &DriverInterface,
+        config: &SnapshotConfig,
+        address: u64,
+        buffer: &mut [u8],
+    ) -> IResult<()> {
+        for (index, page) in buffer.chunks_mut(config.page_size as usize).enumerate() {
+            let page_address = address + index as u64 * config.page_size;
+            match interface.read_slice(
+                self.process_id,
+                config.directory_table_type,
+                page_address,
+                page,
+            ) {
+                Ok(_) => self.push_memory(page_address, page),
+                Err(InterfaceError::MemoryAccessFailed | InterfaceError::MemoryAccessPagedOut) => {
+                    log::trace!("Skipping unreadable page {:X}", page_address);
+                    self.skipped_pages += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Append memory and merge it with the previous region if both are adjacent
+    fn push_memory(&mut self, address: u64, data: &[u8]) {
+        if let Some(region) = self.regions.last_mut() {
+            if region.address + region.data.len() as u64 == address {
+                region.data.extend_from_slice(data);
+                return;
+            }
+        }
+
+        self.regions.push(SnapshotRegion {
+            address,
+            data: data.to_vec(),
+        });
+    }
+
+    pub fn write(&self, format: DumpFormat, output: &mut impl Write) -> io::Result<()> {
+        match format {
+            DumpFormat::Minidump => minidump::write(self, output),
+            DumpFormat::Core => elf::write(self, output),
+        }
+    }
+
+    pub fn save(&self, format: DumpFormat, path: &Path) -> IResult<()> {
+        let file_error = |error| InterfaceError::DriverFileAccess {
+            path: path.to_owned(),
+            error,
+        };
+
+        let mut output = BufWriter::new(File::create(path).map_err(file_error)?);
+        self.write(format, &mut output)
+            .and_then(|_| output.flush())
+            .map_err(file_error)
+    }
+}
+
+/// Align the ranges to the page size, sort them and merge overlapping ranges
+fn merge_ranges(ranges: impl Iterator<Item = Range<u64>>, page_size: u64) -> Vec<Range<u64>> {
+    let mut ranges = ranges
+        .map(|range| {
+            let start = range.start - range.start % page_size;
+            let end = range
+                .end
+                .checked_next_multiple_of(page_size)
+                .unwrap_or(range.end);
+            start..end
+        })
+        .filter(|range| range.start < range.end)
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut result: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match result.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => result.push(range),
+        }
+    }
+
+    result
+}
@@ -1,3 +1,8 @@
+use std::{
+    path::PathBuf,
+    time::Duration,
+};
+
 use obfstr::obfstr;
 use thiserror::Error;
 
@@ -6,9 +11,63 @@ pub enum InterfaceError {
     #[error("failed to find any memory driver")]
     NoDriverFound,
 
+    #[error("none of the available drivers matches the requirements")]
+    NoMatchingDriver,
+
+    #[error("all drivers failed to load ({})", self::format_failures(.failures))]
+    DriversFailed {
+        /// Path of every driver with the reason it could not be used
+        failures: Vec<(PathBuf, String)>,
+    },
+
     #[error("failed to load driver: {0}")]
     DriverLoadingError(#[from] libloading::Error),
 
+    #[error("failed to access driver file {path}: {error}")]
+    DriverFileAccess {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    #[error("invalid driver manifest: {message}")]
+    DriverManifestInvalid { message: String },
+
+    #[error("driver hash does not match (expected {expected}, actual {actual})")]
+    DriverHashMismatch { expected: String, actual: String },
+
+    #[error("driver {path} has been rejected: {reason}")]
+    DriverIntegrityRejected { path: PathBuf, reason: String },
+
+    #[error("invalid integrity policy: {message}")]
+    IntegrityPolicyInvalid { message: String },
+
+    #[error("driver host unavailable: {message}")]
+    DriverHostUnavailable { message: String },
+
+    #[error("driver host died: {message}")]
+    DriverHostDied { message: String },
+
+    #[error("command {command_id:X} did not complete within {timeout:?}")]
+    Timeout { command_id: u32, timeout: Duration },
+
+    #[error("the driver is unhealthy as a previous command did not complete")]
+    DriverUnhealthy,
+
+    #[error("invalid trace: {message}")]
+    TraceInvalid { message: String },
+
+    #[error("replay diverged from the trace at entry {index}: {message}")]
+    ReplayDivergence { index: usize, message: String },
+
+    #[error("invalid memory dump: {message}")]
+    DumpInvalid { message: String },
+
+    #[error("remote driver unavailable: {message}")]
+    RemoteUnavailable { message: String },
+
+    #[error("remote driver authentication failed: {message}")]
+    RemoteAuthenticationFailed { message: String },
+
     #[error("missing command handler execute export")]
     DriverMissingExecuterExport,
 
@@ -29,6 +88,9 @@ pub enum InterfaceError {
     #[error("feature is not supported")]
     FeatureUnsupported,
 
+    #[error("access denied: {message}")]
+    AccessDenied { message: String },
+
     #[error("the driver is unavailable")]
     InitializeDriverUnavailable,
 
@@ -44,6 +106,25 @@ pub enum InterfaceError {
     #[error("failed to access memory because the target memory has been paged out")]
     MemoryAccessPagedOut,
 
+    #[error("string is not terminated within {max_length} characters")]
+    StringUnterminated { max_length: usize },
+
+    #[error("string is not valid {encoding}")]
+    StringInvalidEncoding { encoding: &'static str },
+
+    #[error("null pointer")]
+    NullPointer,
+
+    #[error("pointer chain failed at hop {hop} ({address:X}): {error}")]
+    PointerChainFailed {
+        hop: usize,
+        address: u64,
+        error: Box<InterfaceError>,
+    },
+
+    #[error("index {index} is out of bounds for length {length}")]
+    IndexOutOfBounds { index: usize, length: usize },
+
     #[error("metrics report type too long")]
     ReportTypeTooLong,
 
@@ -53,6 +134,14 @@ pub enum InterfaceError {
 
 pub type IResult<T> = std::result::Result<T, InterfaceError>;
 
+fn format_failures(failures: &[(PathBuf, String)]) -> String {
+    failures
+        .iter()
+        .map(|(path, reason)| format!("{}: {}", path.display(), reason))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 impl InterfaceError {
     pub fn detailed_message(&self) -> Option<String> {
         Some(match self {
@@ -69,6 +158,21 @@ impl InterfaceError {
                     obfstr!("https://wiki.valth.run/link/3"),
                 ].join("\n")
             },
+            &InterfaceError::NoMatchingDriver => {
+                [
+                    obfstr!("** PLEASE READ CAREFULLY **"),
+                    obfstr!("None of the available drivers supports the features required by this application."),
+                    obfstr!("Please update your driver or use a different driver."),
+                ].join("\n")
+            },
+            InterfaceError::DriverIntegrityRejected { path, reason } => {
+                [
+                    obfstr!("** PLEASE READ CAREFULLY **"),
+                    &format!("{} {}", obfstr!("Refused to load the driver"), path.display()),
+                    &format!("{}: {}", obfstr!("Reason"), reason),
+                    obfstr!("The driver does not match the configured integrity policy and might have been tampered with."),
+                ].join("\n")
+            },
             &InterfaceError::InitializeDriverUnavailable => {
                 [
                     obfstr!("** PLEASE READ CAREFULLY **"),
@@ -0,0 +1,564 @@
+//! Out-of-process hosting of driver libraries.
+//!
+//! The driver library gets loaded by a separate host process (`vtd-driver-host`) which serves
+//! the driver commands over a local IPC channel (unix socket or named pipe).
+//! If the driver crashes, only the host process dies and the interface reports an error
+//! (or restarts the host) instead of taking down the whole application.
+use std::{
+    env,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::{
+        Child,
+        Command,
+        Stdio,
+    },
+    sync::Mutex,
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use obfstr::obfstr;
+use vtd_protocol::{
+    command::{
+        DriverCommand,
+        DriverCommandInitialize,
+    },
+    CommandResult,
+};
+
+use crate::{
+    integrity,
+    marshal::{
+        CommandRequest,
+        CommandResponse,
+        MAX_BUFFER_SIZE,
+    },
+    DriverBackend,
+    IResult,
+    InterfaceError,
+    LibraryFile,
+};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub use unix::*;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use self::windows::*;
+
+/// Version of the protocol spoken between the interface and the driver host
+pub const HOST_PROTOCOL_VERSION: u32 = 0x02;
+const HOST_HANDSHAKE_MAGIC: &[u8; 4] = b"VTDH";
+
+const MAX_FRAME_SIZE: usize = MAX_BUFFER_SIZE + 0x10000;
+const HOST_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HOST_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[cfg(windows)]
+const HOST_BINARY_NAME: &str = "vtd-driver-host.exe";
+#[cfg(not(windows))]
+const HOST_BINARY_NAME: &str = "vtd-driver-host";
+
+/// Write a length prefixed frame
+pub fn write_frame(stream: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    stream.write_all(&(payload.len() as u32).to_le_bytes())?;
+    stream.write_all(payload)?;
+    stream.flush()
+}
+
+/// Read a length prefixed frame.
+/// Returns `None` if the stream has been closed.
+pub fn read_frame(stream: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    self::read_frame_limited(stream, MAX_FRAME_SIZE)
+}
+
+/// Read a length prefixed frame which must not exceed `max_length` bytes.
+/// Returns `None` if the stream has been closed.
+pub fn read_frame_limited(
+    stream: &mut impl Read,
+    max_length: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match stream.read_exact(&mut length) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let length = u32::from_le_bytes(length) as usize;
+    if length > max_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame too large ({} bytes)", length),
+        ));
+    }
+
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+pub fn write_handshake(stream: &mut impl Write) -> io::Result<()> {
+    let mut payload = HOST_HANDSHAKE_MAGIC.to_vec();
+    payload.extend_from_slice(&HOST_PROTOCOL_VERSION.to_le_bytes());
+    self::write_frame(stream, &payload)
+}
+
+pub fn read_handshake(stream: &mut impl Read) -> io::Result<()> {
+    let payload = self::read_frame(stream)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "missing handshake"))?;
+
+    if payload.len() != 8 || &payload[0..4] != HOST_HANDSHAKE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid handshake",
+        ));
+    }
+
+    let version = u32::from_le_bytes(payload[4..8].try_into().unwrap());
+    if version != HOST_PROTOCOL_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "host protocol version miss match (expected {}, received {})",
+                HOST_PROTOCOL_VERSION, version
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decode an encoded [CommandRequest], execute it and return the response.
+/// Invalid requests are answered with an error response.
+pub fn execute_encoded_request(backend: &dyn DriverBackend, request: &[u8]) -> CommandResponse {
+    let request = match CommandRequest::decode(request) {
+        Ok(request) => request,
+        Err(err) => {
+            log::warn!("Received invalid request: {}", err);
+            return CommandResponse::from_error(
+                CommandResult::CommandParameterInvalid,
+                &format!("invalid request: {}", err),
+            );
+        }
+    };
+
+    match backend.execute_request(&request) {
+        Ok(response) => response,
+        Err(err) => CommandResponse::from_error(
+            CommandResult::CommandParameterInvalid,
+            &format!("invalid request: {}", err),
+        ),
+    }
+}
+
+/// Locate the driver host binary.
+/// The host can be specified via `VT_DRIVER_HOST` or must be located next to the current executable.
+pub fn find_host_binary() -> Option<PathBuf> {
+    if let Ok(path) = env::var(obfstr!("VT_DRIVER_HOST")) {
+        return Some(PathBuf::from(path));
+    }
+
+    let directory = env::current_exe().ok()?.parent()?.to_owned();
+    let host = directory.join(HOST_BINARY_NAME);
+    if host.exists() {
+        Some(host)
+    } else {
+        None
+    }
+}
+
+struct HostProcess {
+    child: Child,
+    stream: Option<HostStream>,
+    endpoint: HostEndpoint,
+}
+
+impl HostProcess {
+    /// Spawn a host for the library.
+    /// The host only loads the library if it matches the given SHA-256 hash.
+    fn spawn(host_binary: &Path, library: &Path, sha256: &[u8; 0x20]) -> IResult<Self> {
+        let endpoint =
+            self::create_endpoint().map_err(|err| InterfaceError::DriverHostUnavailable {
+                message: format!("create endpoint: {}", err),
+            })?;
+        log::debug!(
+            "Spawning driver host {} for {} on {}",
+            host_binary.display(),
+            library.display(),
+            endpoint.name()
+        );
+
+        let child = Command::new(host_binary)
+            .arg("--library")
+            .arg(library)
+            .arg("--sha256")
+            .arg(hex::encode(sha256))
+            .arg("--endpoint")
+            .arg(endpoint.name())
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|err| InterfaceError::DriverHostUnavailable {
+                message: format!("spawn {}: {}", host_binary.display(), err),
+            })?;
+
+        let mut process = Self {
+            child,
+            stream: None,
+            endpoint,
+        };
+
+        let mut stream = process.connect()?;
+        self::read_handshake(&mut stream).map_err(|err| InterfaceError::DriverHostUnavailable {
+            message: format!("handshake: {}", err),
+        })?;
+
+        process.stream = Some(stream);
+        Ok(process)
+    }
+
+    fn connect(&mut self) -> IResult<HostStream> {
+        let timeout = Instant::now() + HOST_CONNECT_TIMEOUT;
+        loop {
+            match self::connect(self.endpoint.name()) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => {
+                    if let Ok(Some(status)) = self.child.try_wait() {
+                        return Err(InterfaceError::DriverHostUnavailable {
+                            message: format!("host exited with {}", status),
+                        });
+                    }
+
+                    if Instant::now() > timeout {
+                        return Err(InterfaceError::DriverHostUnavailable {
+                            message: format!("connect: {}", err),
+                        });
+                    }
+
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+        }
+    }
+
+    fn transact(&mut self, request: &CommandRequest) -> io::Result<CommandResponse> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "not connected"))?;
+
+        self::write_frame(stream, &request.encode())?;
+        let response = self::read_frame(stream)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "host closed the connection")
+        })?;
+
+        CommandResponse::decode(&response)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl Drop for HostProcess {
+    fn drop(&mut self) {
+        /* closing the connection causes the host to shut down */
+        self.stream = None;
+
+        let timeout = Instant::now() + HOST_SHUTDOWN_TIMEOUT;
+        while Instant::now() < timeout {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        log::warn!("Driver host did not exit. Killing it.");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct HostState {
+    process: Option<HostProcess>,
+
+    /// The initialize request used to re-initialize the driver after a host restart
+    init_request: Option<CommandRequest>,
+}
+
+/// A driver library loaded within a separate host process.
+///
+/// The library is verified against the configured [IntegrityPolicy](crate::IntegrityPolicy)
+/// before spawning the host. The host only loads the library if it still matches the verified hash.
+pub struct HostedDriver {
+    host_binary: PathBuf,
+    library: PathBuf,
+    sha256: [u8; 0x20],
+    restart: bool,
+
+    state: Mutex<HostState>,
+}
+
+impl HostedDriver {
+    /// Spawn a new driver host for the given driver library.
+    pub fn spawn(library: &Path) -> IResult<Self> {
+        Self::spawn_file(&LibraryFile::open(library)?)
+    }
+
+    /// Spawn a new driver host for an opened driver library.
+    pub fn spawn_file(library: &LibraryFile) -> IResult<Self> {
+        let host_binary =
+            self::find_host_binary().ok_or_else(|| InterfaceError::DriverHostUnavailable {
+                message: format!("could not find {}", HOST_BINARY_NAME),
+            })?;
+
+        Self::spawn_with_host(&host_binary, library)
+    }
+
+    pub fn spawn_with_host(host_binary: &Path, library: &LibraryFile) -> IResult<Self> {
+        integrity::verify_library(library)?;
+
+        let process = HostProcess::spawn(host_binary, library.path(), library.sha256())?;
+        Ok(Self {
+            host_binary: host_binary.to_owned(),
+            library: library.path().to_owned(),
+            sha256: *library.sha256(),
+            restart: true,
+
+            state: Mutex::new(HostState {
+                process: Some(process),
+                init_request: None,
+            }),
+        })
+    }
+
+    /// Restart the host automatically if it died.
+    /// The driver will be re-initialized with the last initialize command.
+    pub fn set_restart(&mut self, restart: bool) {
+        self.restart = restart;
+    }
+
+    pub fn library(&self) -> &Path {
+        &self.library
+    }
+
+    fn restart_host(&self, state: &mut HostState) -> IResult<()> {
+        let Some(init_request) = &state.init_request else {
+            return Err(InterfaceError::DriverHostDied {
+                message: "driver has not been initialized".to_string(),
+            });
+        };
+
+        log::info!("Restarting driver host for {}", self.library.display());
+        let mut process = HostProcess::spawn(&self.host_binary, &self.library, &self.sha256)?;
+        process
+            .transact(init_request)
+            .map_err(|err| InterfaceError::DriverHostDied {
+                message: format!("re-initialize: {}", err),
+            })?;
+
+        state.process = Some(process);
+        Ok(())
+    }
+
+    /// Execute a captured command request within the host process.
+    pub fn transact(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if state.process.is_none() {
+            if !self.restart {
+                return Err(InterfaceError::DriverHostDied {
+                    message: "host is not running".to_string(),
+                });
+            }
+
+            self.restart_host(&mut state)?;
+        }
+
+        let process = state.process.as_mut().unwrap();
+        let response = match process.transact(request) {
+            Ok(response) => response,
+            Err(err) => {
+                log::error!("Driver host failed: {}", err);
+                state.process = None;
+                return Err(InterfaceError::DriverHostDied {
+                    message: err.to_string(),
+                });
+            }
+        };
+
+        if request.command_id == DriverCommandInitialize::COMMAND_ID {
+            state.init_request = Some(request.clone());
+        }
+
+        Ok(response)
+    }
+}
+
+impl DriverBackend for HostedDriver {
+    /// Execute a command within the host process.
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        let request =
+            CommandRequest::capture(command_id, payload, error_message.len()).map_err(|err| {
+                InterfaceError::CommandGenericError {
+                    message: format!("marshal: {}", err),
+                }
+            })?;
+
+        let response = self.transact(&request)?;
+        response
+            .apply(command_id, payload, error_message)
+            .map_err(|err| InterfaceError::CommandGenericError {
+                message: format!("unmarshal: {}", err),
+            })
+    }
+
+    fn execute_request(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        self.transact(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{
+            self,
+            Cursor,
+        },
+        thread,
+    };
+
+    use vtd_protocol::{
+        command::DriverCommandProcessList,
+        types::ProcessInfo,
+        CommandResult,
+    };
+
+    use super::{
+        HostListener,
+        MAX_FRAME_SIZE,
+    };
+    use crate::{
+        marshal::{
+            CommandRequest,
+            CommandResponse,
+        },
+        mock::{
+            MockDriver,
+            MockProcess,
+        },
+    };
+
+    #[test]
+    fn frames() {
+        let mut stream = Vec::new();
+        super::write_frame(&mut stream, b"hello").unwrap();
+        super::write_frame(&mut stream, b"").unwrap();
+        assert_eq!(&stream[0..4], &5u32.to_le_bytes());
+
+        let mut reader = Cursor::new(stream);
+        assert_eq!(super::read_frame(&mut reader).unwrap().unwrap(), b"hello");
+        assert_eq!(super::read_frame(&mut reader).unwrap().unwrap(), b"");
+        assert!(super::read_frame(&mut reader).unwrap().is_none());
+
+        /* truncated payload */
+        let mut reader = Cursor::new([8, 0, 0, 0, 1, 2]);
+        assert_eq!(
+            super::read_frame(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        /* frames exceeding the limit are rejected before allocating their payload */
+        let mut reader = Cursor::new(((MAX_FRAME_SIZE + 1) as u32).to_le_bytes());
+        assert_eq!(
+            super::read_frame(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut stream = Vec::new();
+        super::write_frame(&mut stream, &[0u8; 0x10]).unwrap();
+        assert!(super::read_frame_limited(&mut Cursor::new(&stream), 0x0F).is_err());
+        assert!(super::read_frame_limited(&mut Cursor::new(&stream), 0x10).is_ok());
+    }
+
+    #[test]
+    fn handshake() {
+        let mut stream = Vec::new();
+        super::write_handshake(&mut stream).unwrap();
+        super::read_handshake(&mut Cursor::new(&stream)).unwrap();
+
+        /* different protocol version */
+        stream[8] ^= 0xFF;
+        assert!(super::read_handshake(&mut Cursor::new(&stream)).is_err());
+        assert!(super::read_handshake(&mut Cursor::new([])).is_err());
+    }
+
+    #[test]
+    fn host_round_trip() {
+        let endpoint = super::create_endpoint().unwrap();
+        let listener = HostListener::bind(endpoint.name()).unwrap();
+
+        let host = thread::spawn(move || {
+            let driver = MockDriver::new().with_process(MockProcess::new(42, "cs2.exe"));
+            let mut stream = listener.accept().unwrap();
+            super::write_handshake(&mut stream).unwrap();
+
+            while let Some(request) = super::read_frame(&mut stream).unwrap() {
+                let response = super::execute_encoded_request(&driver, &request);
+                super::write_frame(&mut stream, &response.encode()).unwrap();
+            }
+        });
+
+        let mut stream = super::connect(endpoint.name()).unwrap();
+        super::read_handshake(&mut stream).unwrap();
+
+        let mut processes = [ProcessInfo::default(); 4];
+        let mut command = DriverCommandProcessList {
+            buffer: processes.as_mut_ptr(),
+            buffer_capacity: processes.len(),
+            ..Default::default()
+        };
+        let request = unsafe { CommandRequest::capture_command(&command, 0x100) }.unwrap();
+        super::write_frame(&mut stream, &request.encode()).unwrap();
+
+        let response = super::read_frame(&mut stream).unwrap().unwrap();
+        let response = CommandResponse::decode(&response).unwrap();
+        let payload = unsafe {
+            core::slice::from_raw_parts_mut(
+                &mut command as *mut _ as *mut u8,
+                core::mem::size_of_val(&command),
+            )
+        };
+        let status = unsafe { response.apply(request.command_id, payload, &mut [0u8; 0x100]) };
+        assert_eq!(status, Ok(CommandResult::Success.bits()));
+        assert_eq!(command.process_count, 1);
+        assert_eq!(processes[0].process_id, 42);
+
+        /* invalid requests are answered with an error */
+        super::write_frame(&mut stream, b"invalid").unwrap();
+        let response = super::read_frame(&mut stream).unwrap().unwrap();
+        assert_eq!(
+            CommandResponse::decode(&response).unwrap().status,
+            CommandResult::CommandParameterInvalid.bits()
+        );
+
+        drop(stream);
+        host.join().unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    env,
+    fs::{
+        self,
+        DirBuilder,
+    },
+    io,
+    os::unix::{
+        fs::DirBuilderExt,
+        net::{
+            UnixListener,
+            UnixStream,
+        },
+    },
+    path::PathBuf,
+};
+
+pub type HostStream = UnixStream;
+
+/// Endpoint of a driver host.
+///
+/// The socket is located within a private directory (mode 0700) with a random name,
+/// so other users can neither connect to the host nor place their own socket in advance.
+/// The directory will be removed when the endpoint is dropped.
+pub struct HostEndpoint {
+    directory: PathBuf,
+    name: String,
+}
+
+impl HostEndpoint {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for HostEndpoint {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+/// Create a new unique endpoint for a driver host
+pub fn create_endpoint() -> io::Result<HostEndpoint> {
+    let mut random = [0u8; 0x10];
+    getrandom::getrandom(&mut random).map_err(io::Error::other)?;
+
+    /* creating the directory fails if it already exists */
+    let directory = env::temp_dir().join(format!("vtd-host-{}", hex::encode(random)));
+    DirBuilder::new().mode(0o700).create(&directory)?;
+
+    Ok(HostEndpoint {
+        name: directory.join("host.sock").to_string_lossy().to_string(),
+        directory,
+    })
+}
+
+pub fn connect(endpoint: &str) -> io::Result<HostStream> {
+    UnixStream::connect(endpoint)
+}
+
+pub struct HostListener {
+    path: PathBuf,
+    inner: UnixListener,
+}
+
+impl HostListener {
+    pub fn bind(endpoint: &str) -> io::Result<Self> {
+        /* never remove an existing file as the endpoint may be controlled by someone else */
+        let path = PathBuf::from(endpoint);
+        Ok(Self {
+            inner: UnixListener::bind(&path)?,
+            path,
+        })
+    }
+
+    /// Accept a single client connection
+    pub fn accept(self) -> io::Result<HostStream> {
+        let (stream, _) = self.inner.accept()?;
+        Ok(stream)
+    }
+}
+
+impl Drop for HostListener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
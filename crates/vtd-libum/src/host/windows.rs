@@ -0,0 +1,111 @@
+use std::{
+    fs::{
+        File,
+        OpenOptions,
+    },
+    io,
+    os::windows::io::FromRawHandle,
+};
+
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{
+            CloseHandle,
+            GetLastError,
+            ERROR_PIPE_CONNECTED,
+            HANDLE,
+            INVALID_HANDLE_VALUE,
+        },
+        Storage::FileSystem::{
+            FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_ACCESS_DUPLEX,
+        },
+        System::Pipes::{
+            ConnectNamedPipe,
+            CreateNamedPipeW,
+            PIPE_READMODE_BYTE,
+            PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE,
+            PIPE_WAIT,
+        },
+    },
+};
+
+pub type HostStream = File;
+
+/// Endpoint of a driver host.
+///
+/// The pipe name is random and the host creates the pipe as its first instance,
+/// so the host fails to start if someone else already created a pipe with that name.
+pub struct HostEndpoint {
+    name: String,
+}
+
+impl HostEndpoint {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Create a new unique endpoint for a driver host
+pub fn create_endpoint() -> io::Result<HostEndpoint> {
+    let mut random = [0u8; 0x10];
+    getrandom::getrandom(&mut random).map_err(io::Error::other)?;
+
+    Ok(HostEndpoint {
+        name: format!(r"\\.\pipe\vtd-host-{}", hex::encode(random)),
+    })
+}
+
+pub fn connect(endpoint: &str) -> io::Result<HostStream> {
+    OpenOptions::new().read(true).write(true).open(endpoint)
+}
+
+pub struct HostListener {
+    handle: HANDLE,
+}
+
+impl HostListener {
+    pub fn bind(endpoint: &str) -> io::Result<Self> {
+        let name = endpoint.encode_utf16().chain(Some(0)).collect::<Vec<_>>();
+
+        let handle = unsafe {
+            CreateNamedPipeW(
+                PCWSTR::from_raw(name.as_ptr()),
+                PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                0x10000,
+                0x10000,
+                0,
+                None,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { handle })
+    }
+
+    /// Accept a single client connection
+    pub fn accept(self) -> io::Result<HostStream> {
+        let connected = unsafe { ConnectNamedPipe(self.handle, None) };
+        if !connected.as_bool() && unsafe { GetLastError() } != ERROR_PIPE_CONNECTED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let handle = self.handle;
+        std::mem::forget(self);
+        Ok(unsafe { File::from_raw_handle(handle.0 as _) })
+    }
+}
+
+impl Drop for HostListener {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.handle);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::RwLock,
+};
+
+use ed25519_dalek::{
+    Signature,
+    VerifyingKey,
+};
+
+use crate::{
+    manifest,
+    IResult,
+    InterfaceError,
+};
+
+static INTEGRITY_POLICY: RwLock<Option<IntegrityPolicy>> = RwLock::new(None);
+
+/// Policy which driver libraries must satisfy before they get loaded.
+///
+/// A library is accepted if its SHA-256 hash is part of the allowlist or if it has been signed
+/// by one of the trusted public keys. The signature is expected in a sidecar file next to the library
+/// (e.g. `driver_um.dll.sig`) containing the hex encoded ed25519 signature of the libraries SHA-256 hash.
+#[derive(Debug, Default, Clone)]
+pub struct IntegrityPolicy {
+    allowed_hashes: Vec<[u8; 0x20]>,
+    trusted_keys: Vec<VerifyingKey>,
+}
+
+impl IntegrityPolicy {
+    pub fn with_allowed_hash(mut self, sha256: [u8; 0x20]) -> Self {
+        self.allowed_hashes.push(sha256);
+        self
+    }
+
+    /// Add a hex encoded SHA-256 hash to the allowlist
+    pub fn with_allowed_hash_hex(self, sha256: &str) -> IResult<Self> {
+        let mut hash = [0u8; 0x20];
+        hex::decode_to_slice(sha256, &mut hash).map_err(|_| {
+            InterfaceError::IntegrityPolicyInvalid {
+                message: format!("invalid sha256 \"{}\"", sha256),
+            }
+        })?;
+
+        Ok(self.with_allowed_hash(hash))
+    }
+
+    pub fn with_trusted_key(mut self, key: VerifyingKey) -> Self {
+        self.trusted_keys.push(key);
+        self
+    }
+
+    /// Add a hex encoded ed25519 public key to the trusted keys
+    pub fn with_trusted_key_hex(self, key: &str) -> IResult<Self> {
+        let mut bytes = [0u8; 0x20];
+        hex::decode_to_slice(key, &mut bytes).map_err(|_| {
+            InterfaceError::IntegrityPolicyInvalid {
+                message: format!("invalid public key \"{}\"", key),
+            }
+        })?;
+
+        let key = VerifyingKey::from_bytes(&bytes).map_err(|err| {
+            InterfaceError::IntegrityPolicyInvalid {
+                message: format!("invalid public key \"{}\": {}", key, err),
+            }
+        })?;
+
+        Ok(self.with_trusted_key(key))
+    }
+
+    /// Returns true if the policy does not restrict which libraries can be loaded
+    pub fn is_unrestricted(&self) -> bool {
+        self.allowed_hashes.is_empty() && self.trusted_keys.is_empty()
+    }
+
+    /// Path of the detached signature for the given driver library
+    pub fn signature_path(library: &Path) -> PathBuf {
+        let mut file_name = library.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".sig");
+        library.with_file_name(file_name)
+    }
+
+    fn read_signature(library: &Path) -> Result<Option<Signature>, String> {
+        let path = Self::signature_path(library);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(format!("read {}: {}", path.display(), err)),
+        };
+
+        let mut signature = [0u8; Signature::BYTE_SIZE];
+        hex::decode_to_slice(contents.trim(), &mut signature)
+            .map_err(|_| format!("invalid signature in {}", path.display()))?;
+
+        Ok(Some(Signature::from_bytes(&signature)))
+    }
+
+    /// Verify the library against this policy
+    pub fn verify(&self, library: &Path) -> IResult<()> {
+        if self.is_unrestricted() {
+            return Ok(());
+        }
+
+        self.verify_file(&LibraryFile::open(library)?)
+    }
+
+    /// Verify an opened library against this policy
+    pub fn verify_file(&self, file: &LibraryFile) -> IResult<()> {
+        if self.is_unrestricted() {
+            return Ok(());
+        }
+
+        let library = file.path();
+        let rejected = |reason: String| InterfaceError::DriverIntegrityRejected {
+            path: library.to_owned(),
+            reason,
+        };
+
+        let hash = *file.sha256();
+        if self.allowed_hashes.contains(&hash) {
+            log::debug!("Driver {} is on the allowlist", library.display());
+            return Ok(());
+        }
+
+        if self.trusted_keys.is_empty() {
+            return Err(rejected(format!(
+                "hash {} is not on the allowlist",
+                hex::encode(hash)
+            )));
+        }
+
+        let signature = match Self::read_signature(library).map_err(rejected)? {
+            Some(signature) => signature,
+            None => {
+                return Err(rejected(format!(
+                    "hash {} is not on the allowlist and the library is not signed",
+                    hex::encode(hash)
+                )))
+            }
+        };
+
+        if self
+            .trusted_keys
+            .iter()
+            .any(|key| key.verify_strict(&hash, &signature).is_ok())
+        {
+            log::debug!("Driver {} has a trusted signature", library.display());
+            return Ok(());
+        }
+
+        Err(rejected("signature is not trusted".to_string()))
+    }
+}
+
+/// A driver library file which has been opened and hashed.
+///
+/// The file stays open until the library has been loaded from it, so the verified contents
+/// are the contents which get loaded. On Linux the library is loaded through the opened file
+/// descriptor (`/proc/self/fd/<fd>`) which prevents replacing the file in the meantime.
+/// On Windows the file is opened without write and delete sharing which prevents any modification
+/// as long as the file is open.
+pub struct LibraryFile {
+    path: PathBuf,
+    file: File,
+    sha256: [u8; 0x20],
+}
+
+impl LibraryFile {
+    pub fn open(path: &Path) -> IResult<Self> {
+        let file_error = |error| InterfaceError::DriverFileAccess {
+            path: path.to_owned(),
+            error,
+        };
+
+        let mut options = OpenOptions::new();
+        options.read(true);
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            options.share_mode(windows::Win32::Storage::FileSystem::FILE_SHARE_READ.0);
+        }
+
+        let mut file = options.open(path).map_err(file_error)?;
+        let sha256 = manifest::hash_opened_file(&mut file, path)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            sha256,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// SHA-256 hash of the opened file contents
+    pub fn sha256(&self) -> &[u8; 0x20] {
+        &self.sha256
+    }
+
+    /// Path the library should be loaded from
+    pub fn load_path(&self) -> PathBuf {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+            PathBuf::from(format!("/proc/self/fd/{}", self.file.as_raw_fd()))
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            self.path.clone()
+        }
+    }
+}
+
+/// Set the integrity policy which will be enforced for every driver library loaded.
+pub fn set_integrity_policy(policy: IntegrityPolicy) {
+    let mut current = INTEGRITY_POLICY
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    *current = Some(policy);
+}
+
+/// Verify the opened library against the currently configured integrity policy
+pub(crate) fn verify_library(file: &LibraryFile) -> IResult<()> {
+    let policy = INTEGRITY_POLICY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    match &*policy {
+        Some(policy) => policy.verify_file(file),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+        path::PathBuf,
+    };
+
+    use ed25519_dalek::{
+        Signer,
+        SigningKey,
+    };
+
+    use super::{
+        IntegrityPolicy,
+        LibraryFile,
+    };
+    use crate::{
+        manifest,
+        InterfaceError,
+    };
+
+    fn create_library(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("vtd-integrity-{}-{}", std::process::id(), name));
+        fs::write(&path, b"not really a driver library").unwrap();
+        path
+    }
+
+    #[test]
+    fn allowlist() {
+        let library = self::create_library("allowlist");
+        let hash = manifest::hash_file(&library).unwrap();
+
+        assert!(IntegrityPolicy::default().verify(&library).is_ok());
+        assert!(IntegrityPolicy::default()
+            .with_allowed_hash(hash)
+            .verify(&library)
+            .is_ok());
+        assert!(matches!(
+            IntegrityPolicy::default()
+                .with_allowed_hash([0xAA; 0x20])
+                .verify(&library),
+            Err(InterfaceError::DriverIntegrityRejected { .. })
+        ));
+
+        fs::remove_file(library).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn replaced_library() {
+        let library = self::create_library("replaced");
+        let hash = manifest::hash_file(&library).unwrap();
+        let policy = IntegrityPolicy::default().with_allowed_hash(hash);
+
+        let file = LibraryFile::open(&library).unwrap();
+        assert_eq!(file.sha256(), &hash);
+
+        /* replace the library after it has been opened */
+        let replacement = self::create_library("replacement");
+        fs::write(&replacement, b"malicious driver library").unwrap();
+        fs::rename(&replacement, &library).unwrap();
+
+        assert!(policy.verify(&library).is_err());
+        assert!(policy.verify_file(&file).is_ok());
+        assert_eq!(
+            fs::read(file.load_path()).unwrap(),
+            b"not really a driver library"
+        );
+
+        fs::remove_file(library).unwrap();
+    }
+
+    #[test]
+    fn signature() {
+        let library = self::create_library("signature");
+        let hash = manifest::hash_file(&library).unwrap();
+
+        let trusted_key = SigningKey::from_bytes(&[0x01; 0x20]);
+        let untrusted_key = SigningKey::from_bytes(&[0x02; 0x20]);
+        let policy = IntegrityPolicy::default().with_trusted_key(trusted_key.verifying_key());
+
+        /* library is not signed */
+        assert!(policy.verify(&library).is_err());
+
+        let signature_path = IntegrityPolicy::signature_path(&library);
+        fs::write(
+            &signature_path,
+            hex::encode(untrusted_key.sign(&hash).to_bytes()),
+        )
+        .unwrap();
+        assert!(policy.verify(&library).is_err());
+
+        fs::write(
+            &signature_path,
+            hex::encode(trusted_key.sign(&hash).to_bytes()),
+        )
+        .unwrap();
+        assert!(policy.verify(&library).is_ok());
+
+        fs::remove_file(signature_path).unwrap();
+        fs::remove_file(library).unwrap();
+    }
+}
@@ -1,22 +1,19 @@
 use core::{
     mem,
+    slice,
     sync::atomic::{
+        AtomicBool,
         AtomicUsize,
         Ordering,
     },
 };
 use std::{
     env,
-    error::Error,
-    fs,
-    ops::Deref,
-    path::{
-        Path,
-        PathBuf,
-    },
+    path::Path,
+    sync::Arc,
+    time::Duration,
 };
 
-use libloading::Library;
 use obfstr::obfstr;
 use vtd_protocol::{
     command::{
@@ -48,226 +45,196 @@ use vtd_protocol::{
         ProcessModuleInfo,
     },
     CommandResult,
-    FnCommandHandler,
     PROTOCOL_VERSION,
 };
 
 use crate::{
+    dump::DumpDriver,
+    host::HostedDriver,
+    marshal::CommandRequest,
+    pod,
+    watchdog::Watchdog,
+    DriverBackend,
+    DriverRequirements,
+    DriverSelection,
     IResult,
     InterfaceError,
+    LibraryBackend,
+    Pod,
+    RecordingBackend,
+    ReplayBackend,
+    ValthrunLibrary,
+    WatchdogConfig,
 };
 
-type LibraryStartup = unsafe extern "C" fn() -> ();
-type LibraryTeardown = unsafe extern "C" fn() -> ();
-
-pub struct ValthrunLibrary {
-    inner: Library,
-
-    fn_teardown: Option<LibraryTeardown>,
-}
-
-impl ValthrunLibrary {
-    pub fn load(target: &Path) -> IResult<Self> {
-        let library = unsafe { Library::new(&target) }?;
-
-        let fn_startup = unsafe {
-            library
-                .get::<LibraryStartup>(c"startup".to_bytes_with_nul())
-                .ok()
-        };
-
-        let fn_teardown = unsafe {
-            library
-                .get::<LibraryTeardown>(c"teardown".to_bytes_with_nul())
-                .ok()
-                .map(|v| *v)
-        };
-
-        if let Some(startup) = fn_startup {
-            unsafe {
-                startup();
-            }
-        }
-
-        Ok(Self {
-            inner: library,
-            fn_teardown,
-        })
-    }
-}
-
-impl Deref for ValthrunLibrary {
-    type Target = Library;
-
-    fn deref(&self) -> &Self::Target {
-        &self.inner
-    }
-}
-
-impl Drop for ValthrunLibrary {
-    fn drop(&mut self) {
-        if let Some(teardown) = self.fn_teardown {
-            unsafe {
-                teardown();
-            }
-        }
-    }
-}
-
 /// Interface for a Valthrun memory driver
 pub struct DriverInterface {
-    _library: ValthrunLibrary,
-    fn_command_handler: FnCommandHandler,
+    backend: Arc<dyn DriverBackend>,
+    watchdog: Option<Watchdog>,
 
     driver_version: VersionInfo,
     driver_features: DriverFeature,
 
     read_calls: AtomicUsize,
+    dtt_fallback_reported: AtomicBool,
+
+    /// Remaining drivers if this interface has been created from the environment
+    fallback: Option<DriverSelection>,
 }
 
 impl DriverInterface {
-    fn is_library_candidate(filename: &String) -> bool {
-        #[cfg(unix)]
-        {
-            if !filename.ends_with(".so") {
-                /* all files must end with .so */
-                return false;
-            }
+    /// Create a driver interface using the first driver found in the environment which can be initialized.
+    pub fn create_from_env() -> IResult<Self> {
+        Self::create_from_env_matching(DriverRequirements::default())
+    }
 
-            let filename = if filename.starts_with("lib") {
-                &filename[3..]
-            } else {
-                filename
-            };
-
-            if filename == "driver.so"
-                || filename.starts_with("driver_")
-                || filename.starts_with(obfstr!("valthrun_driver_"))
-            {
-                return true;
-            }
+    /// Create a driver interface using the best driver found in the environment
+    /// which fulfills the given requirements.
+    ///
+    /// If `VT_DRIVER_REPLAY` is set, the commands will be served from the given trace file instead
+    /// (strict ordering can be enforced via `VT_DRIVER_REPLAY_STRICT`).
+    /// If `VT_DRIVER_DUMP` is set, the given minidump or core file will be served instead.
+    /// If `VT_DRIVER_RECORD` is set, all commands executed by the selected driver
+    /// will be recorded into the given trace file.
+    ///
+    /// The remaining matching drivers can be obtained using [DriverInterface::take_fallback].
+    pub fn create_from_env_matching(requirements: DriverRequirements) -> IResult<Self> {
+        if let Some(dump) = env::var_os(obfstr!("VT_DRIVER_DUMP")) {
+            return Self::with_backend(DumpDriver::open(Path::new(&dump))?);
         }
 
-        #[cfg(windows)]
-        {
-            if !filename.ends_with(".dll") {
-                /* all files must end with .dll */
-                return false;
-            }
+        if let Some(trace) = env::var_os(obfstr!("VT_DRIVER_REPLAY")) {
+            let strict = env::var(obfstr!("VT_DRIVER_REPLAY_STRICT"))
+                .map(|value| value == "1" || value == "true")
+                .unwrap_or(false);
 
-            if filename == "driver.dll"
-                || filename.starts_with("driver_")
-                || filename.starts_with(obfstr!("valthrun_driver_"))
-            {
-                return true;
-            }
+            return Self::with_backend(ReplayBackend::load(Path::new(&trace), strict)?);
         }
 
-        false
+        let mut selection = DriverSelection::from_env(requirements);
+        let mut interface = selection.create()?;
+        interface.fallback = Some(selection);
+        Ok(interface)
     }
 
-    fn populate_library_paths() -> Vec<PathBuf> {
-        let mut result = Vec::with_capacity(64);
-        if let Ok(path) = env::var(obfstr!("VT_DRIVER_PATH")) {
-            log::debug!("Adding env driver path: {}", path);
-            result.push(PathBuf::from(path));
-        }
-
-        for directory in [
-            env::var(obfstr!("VT_DRIVER_DIR")).map(PathBuf::from).ok(),
-            env::current_exe()
-                .ok()
-                .map(|v| v.parent().map(|v| v.to_owned()))
-                .flatten(),
-        ] {
-            let Some(directory) = directory else { continue };
-
-            if let Ok(driver_name) = env::var(obfstr!("VT_DRIVER_NAME")) {
-                result.push(directory.join(driver_name));
-            }
-
-            match fs::read_dir(&directory) {
-                Ok(dir) => {
-                    log::debug!("Adding drivers from {}", directory.display());
-                    /*
-                     * Add all dlls which start with driver_/valthrun_driver_ to the candidate list.
-                     * Starting the driver which has been least recently modified.
-                     */
-                    let mut candidates = dir
-                        .filter_map(|entry| entry.ok())
-                        .map(|entry| entry.file_name().to_string_lossy().to_string())
-                        .filter(Self::is_library_candidate)
-                        .map(|file_name| directory.join(file_name))
-                        .filter_map(|file| Some((file.metadata().ok()?.modified().ok()?, file)))
-                        .collect::<Vec<_>>();
-
-                    candidates.sort_by_key(|(timestamp, _file)| *timestamp);
-                    result.extend(candidates.into_iter().rev().map(|(_, file)| file));
-                }
-                Err(err) => {
-                    log::debug!(
-                        "Skipping looking for driver in {}: {}",
-                        directory.display(),
-                        err
-                    );
-                }
-            }
-        }
-
-        result
+    /// Take the driver selection this interface has been created from.
+    /// Calling [DriverSelection::create] will create an interface for the next driver in the fallback chain
+    /// (e.g. if this driver later reports [InterfaceError::InitializeDriverUnavailable]).
+    pub fn take_fallback(&mut self) -> Option<DriverSelection> {
+        self.fallback.take()
     }
 
-    pub fn create_from_env() -> IResult<Self> {
-        for path in Self::populate_library_paths() {
-            log::debug!("Trying to load driver from {}", path.display());
-            match ValthrunLibrary::load(&path) {
-                Ok(lib) => {
-                    log::debug!("    -> success.");
-                    log::debug!("Initialize driver interface.",);
-                    return Self::create(lib);
-                }
-                Err(err) => {
-                    if let Some(err) = err.source() {
-                        log::error!("Failed to load driver {}: {:#}", path.display(), err);
-                    } else {
-                        log::error!("Failed to load driver {}: {:#}", path.display(), err);
-                    }
-                }
-            }
-        }
+    pub fn create(library: ValthrunLibrary) -> IResult<Self> {
+        Self::with_backend(LibraryBackend::new(library)?)
+    }
 
-        Err(InterfaceError::NoDriverFound)
+    /// Create a driver interface for the given driver library.
+    /// The library will be loaded within a separate host process to isolate crashes of the driver.
+    pub fn create_hosted(library: &Path) -> IResult<Self> {
+        Self::with_backend(HostedDriver::spawn(library)?)
     }
 
-    pub fn create(library: ValthrunLibrary) -> IResult<Self> {
-        let fn_command_handler = unsafe { *library.get::<FnCommandHandler>(b"execute_command\0")? };
+    /// Create a driver interface using the given backend and record all executed commands
+    /// into a trace file which can be replayed using the [ReplayBackend].
+    pub fn with_recording(backend: impl DriverBackend + 'static, trace: &Path) -> IResult<Self> {
+        Self::with_backend(RecordingBackend::create(backend, trace)?)
+    }
 
+    /// Create a driver interface using the given backend and initialize the driver.
+    pub fn with_backend(backend: impl DriverBackend + 'static) -> IResult<Self> {
         let mut interface = Self {
-            _library: library,
-            fn_command_handler,
+            backend: Arc::new(backend),
+            watchdog: None,
 
             driver_version: VersionInfo::default(),
             driver_features: DriverFeature::empty(),
 
             read_calls: AtomicUsize::new(0),
+            dtt_fallback_reported: AtomicBool::new(false),
+
+            fallback: None,
         };
         interface.initialize()?;
         Ok(interface)
     }
 
+    /// Enforce deadlines for driver commands.
+    /// Commands with a deadline will be executed on a separate worker thread.
+    pub fn set_watchdog(&mut self, config: WatchdogConfig) -> IResult<()> {
+        if !config.is_enabled() {
+            self.watchdog = None;
+            return Ok(());
+        }
+
+        /* the worker keeps the backend alive even if a command never returns */
+        let backend = self.backend.clone();
+        self.watchdog = Some(Watchdog::new(
+            config,
+            Arc::new(move |request| backend.execute_request(request)),
+        )?);
+        Ok(())
+    }
+
+    /// Returns false if a driver command exceeded its deadline and has not yet completed.
+    /// While unhealthy all commands fail with [InterfaceError::DriverUnhealthy].
+    pub fn is_healthy(&self) -> bool {
+        self.watchdog
+            .as_ref()
+            .map(Watchdog::is_healthy)
+            .unwrap_or(true)
+    }
+
+    fn execute_command_watched<C: DriverCommand>(
+        watchdog: &Watchdog,
+        command: &mut C,
+        error_buffer: &mut [u8],
+        timeout: Duration,
+    ) -> IResult<u64> {
+        let payload =
+            unsafe { slice::from_raw_parts_mut(command as *mut _ as *mut u8, mem::size_of::<C>()) };
+        let request =
+            unsafe { CommandRequest::capture(C::COMMAND_ID, payload, error_buffer.len()) }
+                .map_err(|err| InterfaceError::CommandGenericError {
+                    message: format!("marshal: {}", err),
+                })?;
+
+        let response = watchdog.execute(request, timeout)?;
+        unsafe { response.apply(C::COMMAND_ID, payload, error_buffer) }.map_err(|err| {
+            InterfaceError::CommandGenericError {
+                message: format!("unmarshal: {}", err),
+            }
+        })
+    }
+
     #[must_use]
     fn execute_command<C: DriverCommand>(&self, command: &mut C) -> IResult<String> {
         let mut error_buffer = Vec::<u8>::with_capacity(0x500);
         error_buffer.resize(0x500, 0);
 
-        let status = unsafe {
-            (self.fn_command_handler)(
-                C::COMMAND_ID,
-                command as *mut _ as *mut u8,
-                mem::size_of::<C>(),
-                error_buffer.as_mut_ptr(),
-                error_buffer.len(),
-            )
+        let timeout = match &self.watchdog {
+            Some(watchdog) => {
+                if !watchdog.is_healthy() {
+                    return Err(InterfaceError::DriverUnhealthy);
+                }
+
+                watchdog
+                    .config()
+                    .timeout_for(C::COMMAND_ID)
+                    .map(|timeout| (watchdog, timeout))
+            }
+            None => None,
+        };
+
+        let status = match timeout {
+            Some((watchdog, timeout)) => {
+                Self::execute_command_watched(watchdog, command, &mut error_buffer, timeout)?
+            }
+            None => unsafe {
+                let payload =
+                    slice::from_raw_parts_mut(command as *mut _ as *mut u8, mem::size_of::<C>());
+                self.backend
+                    .execute_command(C::COMMAND_ID, payload, &mut error_buffer)?
+            },
         };
         let result = CommandResult::from_bits_retain(status);
 
@@ -293,6 +260,9 @@ impl DriverInterface {
                 message: format!("command invalid"),
             },
             CommandResult::CommandFeatureUnsupported => InterfaceError::FeatureUnsupported,
+            CommandResult::AccessDenied => InterfaceError::AccessDenied {
+                message: error.to_string(),
+            },
 
             _ => InterfaceError::CommandGenericError {
                 message: format!("invalid command result"),
@@ -352,25 +322,48 @@ impl DriverInterface {
         self.driver_features
     }
 
+    /// The directory table type used for commands requesting the given type.
+    /// Drivers which do not support explicit directory table bases ([DriverFeature::DttExplicit])
+    /// use the default directory table base of the process instead.
+    pub fn effective_directory_table_type(
+        &self,
+        directory_table_type: DirectoryTableType,
+    ) -> DirectoryTableType {
+        if !matches!(directory_table_type, DirectoryTableType::Explicit { .. })
+            || self.driver_features.contains(DriverFeature::DttExplicit)
+        {
+            return directory_table_type;
+        }
+
+        if !self.dtt_fallback_reported.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Driver does not support explicit directory table bases. Using the default directory table base instead."
+            );
+        }
+        DirectoryTableType::Default
+    }
+
     #[must_use]
     pub fn total_read_calls(&self) -> usize {
         self.read_calls.load(Ordering::Relaxed)
     }
 
+    /// Read the values at the given address
     #[must_use]
-    pub fn read_slice(
+    pub fn read_slice<T: Pod>(
         &self,
         process_id: ProcessId,
         directory_table_type: DirectoryTableType,
 
         address: u64,
-        buffer: &mut [u8],
+        buffer: &mut [T],
     ) -> IResult<()> {
+        let buffer = pod::bytes_of_slice_mut(buffer);
         self.read_calls.fetch_add(1, Ordering::Relaxed);
 
         let mut command = DriverCommandMemoryRead::default();
         command.process_id = process_id;
-        command.directory_table_type = directory_table_type;
+        command.directory_table_type = self.effective_directory_table_type(directory_table_type);
         command.address = address;
 
         command.buffer = buffer.as_mut_ptr();
@@ -396,18 +389,20 @@ impl DriverInterface {
         }
     }
 
+    /// Write the values to the given address
     #[must_use]
-    pub fn write_slice<T: Copy>(
+    pub fn write_slice<T: Pod>(
         &self,
         process_id: ProcessId,
         directory_table_type: DirectoryTableType,
 
         address: u64,
-        buffer: &[u8],
+        buffer: &[T],
     ) -> IResult<()> {
+        let buffer = pod::bytes_of_slice(buffer);
         let mut command = DriverCommandMemoryWrite::default();
         command.process_id = process_id;
-        command.directory_table_type = directory_table_type;
+        command.directory_table_type = self.effective_directory_table_type(directory_table_type);
         command.address = address;
 
         command.buffer = buffer.as_ptr();
@@ -424,6 +419,137 @@ impl DriverInterface {
         }
     }
 
+    /// Read a value at the given address
+    pub fn read<T: Pod>(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+    ) -> IResult<T> {
+        let mut value = T::zeroed();
+        self.read_slice(
+            process_id,
+            directory_table_type,
+            address,
+            slice::from_mut(&mut value),
+        )?;
+        Ok(value)
+    }
+
+    /// Read `N` consecutive values at the given address
+    pub fn read_array<T: Pod, const N: usize>(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+    ) -> IResult<[T; N]> {
+        self.read::<[T; N]>(process_id, directory_table_type, address)
+    }
+
+    /// Read `count` consecutive values at the given address
+    pub fn read_vec<T: Pod>(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+        count: usize,
+    ) -> IResult<Vec<T>> {
+        let mut values = vec![T::zeroed(); count];
+        self.read_slice(process_id, directory_table_type, address, &mut values)?;
+        Ok(values)
+    }
+
+    /// Write a value to the given address
+    pub fn write<T: Pod>(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+        value: &T,
+    ) -> IResult<()> {
+        self.write_slice(
+            process_id,
+            directory_table_type,
+            address,
+            slice::from_ref(value),
+        )
+    }
+
+    /// Read values until the first zero value (excluding the terminator).
+    /// Values are read in chunks which do not cross page boundaries, so the string
+    /// may end right before an inaccessible page.
+    fn read_terminated<T: Pod + PartialEq>(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+        max_length: usize,
+    ) -> IResult<Vec<T>> {
+        const PAGE_SIZE: u64 = 0x1000;
+        const MAX_CHUNK_SIZE: u64 = 0x100;
+
+        let terminator = T::zeroed();
+        let value_size = mem::size_of::<T>() as u64;
+
+        let mut values = Vec::new();
+        let mut chunk = Vec::new();
+        loop {
+            let chunk_address = address.wrapping_add(values.len() as u64 * value_size);
+            let page_remaining = PAGE_SIZE - chunk_address % PAGE_SIZE;
+
+            /* include the terminator */
+            let remaining = max_length.saturating_add(1) - values.len();
+            let chunk_length = (page_remaining.min(MAX_CHUNK_SIZE) / value_size).max(1) as usize;
+            chunk.resize(chunk_length.min(remaining), terminator);
+
+            self.read_slice(process_id, directory_table_type, chunk_address, &mut chunk)?;
+            if let Some(length) = chunk.iter().position(|value| *value == terminator) {
+                values.extend_from_slice(&chunk[0..length]);
+                return Ok(values);
+            }
+
+            values.extend_from_slice(&chunk);
+            if values.len() > max_length {
+                return Err(InterfaceError::StringUnterminated { max_length });
+            }
+        }
+    }
+
+    /// Read a null terminated UTF-8 string with at most `max_length` bytes (excluding the terminator)
+    pub fn read_string(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+        max_length: usize,
+    ) -> IResult<String> {
+        let value =
+            self.read_terminated::<u8>(process_id, directory_table_type, address, max_length)?;
+        String::from_utf8(value)
+            .map_err(|_| InterfaceError::StringInvalidEncoding { encoding: "UTF-8" })
+    }
+
+    /// Read a null terminated UTF-16 string with at most `max_length` code units (excluding the terminator)
+    pub fn read_string_utf16(
+        &self,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+
+        address: u64,
+        max_length: usize,
+    ) -> IResult<String> {
+        let value =
+            self.read_terminated::<u16>(process_id, directory_table_type, address, max_length)?;
+        String::from_utf16(&value)
+            .map_err(|_| InterfaceError::StringInvalidEncoding { encoding: "UTF-16" })
+    }
+
     pub fn add_metrics_record(&self, record_type: &str, record_payload: &str) -> IResult<()> {
         let mut command = DriverCommandMetricsReportSend::default();
         if !command.set_report_type(record_type) {
@@ -489,7 +615,7 @@ impl DriverInterface {
 
         let mut command = DriverCommandProcessModules::default();
         command.process_id = process_id;
-        command.directory_table_type = directory_table_type;
+        command.directory_table_type = self.effective_directory_table_type(directory_table_type);
 
         let mut retry = 0;
         while retry <= 3 {
@@ -3,5 +3,37 @@ pub use vtd_protocol as protocol;
 mod interface;
 pub use interface::*;
 
+mod backend;
+pub use backend::*;
+
 mod error;
 pub use error::*;
+
+mod discovery;
+pub use discovery::*;
+
+mod manifest;
+pub use manifest::*;
+
+mod integrity;
+pub use integrity::*;
+
+mod pod;
+pub use pod::Pod;
+
+mod pointer;
+pub use pointer::*;
+
+mod watchdog;
+pub use watchdog::WatchdogConfig;
+
+mod trace;
+pub use trace::*;
+
+pub mod dump;
+pub mod host;
+pub mod marshal;
+pub mod remote;
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
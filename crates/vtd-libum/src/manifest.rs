@@ -0,0 +1,341 @@
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        Read,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use vtd_protocol::{
+    types::DriverFeature,
+    PROTOCOL_VERSION,
+};
+
+use crate::{
+    DriverInfo,
+    DriverVersion,
+    IResult,
+    InterfaceError,
+};
+
+/// Range of protocol versions supported by a driver (both inclusive)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ManifestProtocolRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl ManifestProtocolRange {
+    pub fn contains(&self, version: u32) -> bool {
+        self.min <= version && version <= self.max
+    }
+}
+
+/// Sidecar manifest describing a driver library.
+///
+/// The manifest is stored next to the driver library (e.g. `driver_um.dll.json`)
+/// and allows the discovery to list and filter drivers without loading them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriverManifest {
+    pub name: String,
+    pub version: String,
+    pub protocol: ManifestProtocolRange,
+    pub features: Vec<String>,
+
+    /// Hex encoded SHA-256 of the driver library
+    pub sha256: String,
+}
+
+impl DriverManifest {
+    /// Path of the manifest for the given driver library
+    pub fn manifest_path(library: &Path) -> PathBuf {
+        let mut file_name = library.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".json");
+        library.with_file_name(file_name)
+    }
+
+    /// Load the manifest for the given driver library.
+    /// Returns `Ok(None)` if the library has no manifest.
+    pub fn load_for_library(library: &Path) -> IResult<Option<Self>> {
+        let path = Self::manifest_path(library);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(InterfaceError::DriverManifestInvalid {
+                    message: format!("read {}: {}", path.display(), err),
+                })
+            }
+        };
+
+        let manifest = serde_json::from_str::<Self>(&contents).map_err(|err| {
+            InterfaceError::DriverManifestInvalid {
+                message: format!("parse {}: {}", path.display(), err),
+            }
+        })?;
+
+        /* validate the manifest contents early */
+        manifest.parse_version()?;
+        manifest.parse_features()?;
+        manifest.parse_sha256()?;
+        Ok(Some(manifest))
+    }
+
+    /// Create a manifest for the given driver library based on the information the driver reported
+    pub fn create(library: &Path, info: &DriverInfo) -> IResult<Self> {
+        Ok(Self {
+            name: info.name.clone(),
+            version: info.version.to_string(),
+            protocol: ManifestProtocolRange {
+                min: PROTOCOL_VERSION,
+                max: PROTOCOL_VERSION,
+            },
+            features: info
+                .features
+                .iter_names()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            sha256: hex::encode(self::hash_file(library)?),
+        })
+    }
+
+    pub fn save_for_library(&self, library: &Path) -> IResult<()> {
+        let path = Self::manifest_path(library);
+        let contents = serde_json::to_string_pretty(self).map_err(|err| {
+            InterfaceError::DriverManifestInvalid {
+                message: format!("encode: {}", err),
+            }
+        })?;
+
+        fs::write(&path, contents).map_err(|err| InterfaceError::DriverManifestInvalid {
+            message: format!("write {}: {}", path.display(), err),
+        })
+    }
+
+    pub fn parse_version(&self) -> IResult<DriverVersion> {
+        let invalid = || InterfaceError::DriverManifestInvalid {
+            message: format!("invalid version \"{}\"", self.version),
+        };
+
+        let mut parts = self.version.split('.').map(str::parse::<u32>);
+        let mut next_part = || parts.next().ok_or_else(invalid)?.map_err(|_| invalid());
+        let version = DriverVersion::new(next_part()?, next_part()?, next_part()?);
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(version)
+    }
+
+    pub fn parse_features(&self) -> IResult<DriverFeature> {
+        let mut features = DriverFeature::empty();
+        for name in self.features.iter() {
+            features |= DriverFeature::from_name(name).ok_or_else(|| {
+                InterfaceError::DriverManifestInvalid {
+                    message: format!("unknown feature \"{}\"", name),
+                }
+            })?;
+        }
+
+        Ok(features)
+    }
+
+    pub fn parse_sha256(&self) -> IResult<[u8; 0x20]> {
+        let mut hash = [0u8; 0x20];
+        hex::decode_to_slice(&self.sha256, &mut hash).map_err(|_| {
+            InterfaceError::DriverManifestInvalid {
+                message: format!("invalid sha256 \"{}\"", self.sha256),
+            }
+        })?;
+
+        Ok(hash)
+    }
+
+    /// Driver information as specified by the manifest.
+    /// Fails if the driver does not support the protocol version of this interface.
+    pub fn driver_info(&self) -> IResult<DriverInfo> {
+        if !self.protocol.contains(PROTOCOL_VERSION) {
+            return Err(InterfaceError::DriverProtocolMismatch {
+                interface_protocol: PROTOCOL_VERSION,
+                driver_protocol: self.protocol.max,
+            });
+        }
+
+        Ok(DriverInfo {
+            name: self.name.clone(),
+            version: self.parse_version()?,
+            features: self.parse_features()?,
+        })
+    }
+
+    /// Verify that the library matches the hash specified by the manifest
+    pub fn verify_library(&self, library: &Path) -> IResult<()> {
+        self.verify_hash(&self::hash_file(library)?)
+    }
+
+    /// Verify that the SHA-256 hash of a library matches the hash specified by the manifest
+    pub fn verify_hash(&self, actual: &[u8; 0x20]) -> IResult<()> {
+        let expected = self.parse_sha256()?;
+        if expected != *actual {
+            return Err(InterfaceError::DriverHashMismatch {
+                expected: self.sha256.clone(),
+                actual: hex::encode(actual),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn hash_file(path: &Path) -> IResult<[u8; 0x20]> {
+    let mut file = File::open(path).map_err(|err| InterfaceError::DriverFileAccess {
+        path: path.to_owned(),
+        error: err,
+    })?;
+
+    self::hash_opened_file(&mut file, path)
+}
+
+/// Hash the contents of an already opened file.
+/// `path` is only used for error reporting.
+pub(crate) fn hash_opened_file(file: &mut File, path: &Path) -> IResult<[u8; 0x20]> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 0x4000];
+    loop {
+        let count = file
+            .read(&mut buffer)
+            .map_err(|err| InterfaceError::DriverFileAccess {
+                path: path.to_owned(),
+                error: err,
+            })?;
+        if count == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[0..count]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+    };
+
+    use vtd_protocol::{
+        types::DriverFeature,
+        PROTOCOL_VERSION,
+    };
+
+    use super::{
+        DriverManifest,
+        ManifestProtocolRange,
+    };
+    use crate::{
+        DriverInfo,
+        DriverRequirements,
+        DriverVersion,
+        InterfaceError,
+    };
+
+    fn create_manifest(version: &str) -> DriverManifest {
+        DriverManifest {
+            name: "test".to_string(),
+            version: version.to_string(),
+            protocol: ManifestProtocolRange {
+                min: PROTOCOL_VERSION,
+                max: PROTOCOL_VERSION,
+            },
+            features: vec!["ProcessList".to_string(), "MemoryRead".to_string()],
+            sha256: "00".repeat(0x20),
+        }
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(
+            self::create_manifest("1.2.3").parse_version().unwrap(),
+            DriverVersion::new(1, 2, 3)
+        );
+
+        for version in ["", "1", "1.2", "1.2.3.4", "1.2.x", "1..3", "-1.2.3"] {
+            assert!(
+                matches!(
+                    self::create_manifest(version).parse_version(),
+                    Err(InterfaceError::DriverManifestInvalid { .. })
+                ),
+                "{:?} should be rejected",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn version_comparison() {
+        assert!(DriverVersion::new(1, 2, 3) < DriverVersion::new(1, 2, 4));
+        assert!(DriverVersion::new(1, 2, 9) < DriverVersion::new(1, 3, 0));
+        assert!(DriverVersion::new(1, 9, 9) < DriverVersion::new(2, 0, 0));
+        assert!(DriverVersion::new(1, 10, 0) > DriverVersion::new(1, 9, 0));
+
+        let info = self::create_manifest("1.10.0").driver_info().unwrap();
+        assert_eq!(
+            info.features,
+            DriverFeature::ProcessList | DriverFeature::MemoryRead
+        );
+
+        let requirements = DriverRequirements::default()
+            .with_min_version(DriverVersion::new(1, 9, 0))
+            .with_max_version(DriverVersion::new(1, 10, 0));
+        assert!(requirements.matches(&info));
+        assert!(!requirements.matches(&DriverInfo {
+            version: DriverVersion::new(1, 10, 1),
+            ..info.clone()
+        }));
+        assert!(!requirements.matches(&DriverInfo {
+            version: DriverVersion::new(1, 8, 99),
+            ..info
+        }));
+    }
+
+    #[test]
+    fn hash_mismatch() {
+        let library = env::temp_dir().join(format!("vtd-manifest-{}.bin", std::process::id()));
+        fs::write(&library, b"driver library").unwrap();
+
+        let info = self::create_manifest("1.0.0").driver_info().unwrap();
+        let manifest = DriverManifest::create(&library, &info).unwrap();
+        manifest.verify_library(&library).unwrap();
+
+        /* the library has been replaced after the manifest has been created */
+        fs::write(&library, b"another library").unwrap();
+        let result = manifest.verify_library(&library);
+        fs::remove_file(&library).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(InterfaceError::DriverHashMismatch { expected, .. }) if expected == manifest.sha256
+        ));
+        assert!(matches!(
+            manifest.verify_hash(&[0u8; 0x20]),
+            Err(InterfaceError::DriverHashMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,498 @@
+//! Marshalling of driver commands into self-contained byte messages.
+//!
+//! Driver commands are plain structs which may reference additional buffers through raw pointers.
+//! To execute such a command outside of the current address space, the command and its
+//! referenced buffers are encoded field by field using the [vtd_protocol::codec].
+//!
+//! A [CommandRequest] contains the encoded command including the contents of its input buffer.
+//! The executing side recreates the buffers via [CommandRequest::execute] and returns a
+//! [CommandResponse] which gets applied to the original command via [CommandResponse::apply].
+use core::{
+    mem,
+    ptr,
+    slice,
+};
+
+/// Upper limit for the size of a single marshalled buffer
+pub use vtd_protocol::codec::MAX_BUFFER_SIZE;
+use vtd_protocol::{
+    codec::{
+        self,
+        Codec,
+        CodecError,
+        CodecResult,
+        CommandCodec,
+        CommandVisitor,
+        Decoder,
+        Encoder,
+    },
+    CommandResult,
+};
+
+/// Upper limit for the size of the error message buffer
+pub const MAX_ERROR_MESSAGE_SIZE: usize = 0x10000;
+
+/// Upper limit for the size of an encoded command (excluding its buffer)
+pub const MAX_PAYLOAD_SIZE: usize = 0x1000;
+
+/// Upper limit for the size of an encoded request or response
+pub const MAX_MESSAGE_SIZE: usize = MAX_BUFFER_SIZE + MAX_PAYLOAD_SIZE;
+
+struct DescribeMessageVisitor<'a> {
+    message: &'a [u8],
+}
+
+impl CommandVisitor for DescribeMessageVisitor<'_> {
+    type Output = Option<String>;
+
+    fn visit<C: CommandCodec>(self) -> Option<String> {
+        /* skip the message id */
+        let mut decoder = Decoder::new(self.message.get(4..)?);
+        let command = C::decode(&mut decoder).ok()?;
+        Some(format!("{:?}", command))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarshalError {
+    UnexpectedEof,
+    BufferTooLarge(usize),
+    ErrorMessageTooLarge(usize),
+    UnknownCommand(u32),
+    PayloadSizeMissmatch { expected: usize, received: usize },
+    Codec(CodecError),
+}
+
+impl core::fmt::Display for MarshalError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of message"),
+            Self::BufferTooLarge(size) => write!(f, "buffer too large ({} bytes)", size),
+            Self::ErrorMessageTooLarge(size) => {
+                write!(f, "error message buffer too large ({} bytes)", size)
+            }
+            Self::UnknownCommand(command_id) => write!(f, "unknown command {:X}", command_id),
+            Self::PayloadSizeMissmatch { expected, received } => write!(
+                f,
+                "payload size miss match (expected {}, received {})",
+                expected, received
+            ),
+            Self::Codec(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for MarshalError {}
+
+impl From<CodecError> for MarshalError {
+    fn from(value: CodecError) -> Self {
+        Self::Codec(value)
+    }
+}
+
+pub type MarshalResult<T> = Result<T, MarshalError>;
+
+/// Encode a length prefixed byte buffer
+fn encode_blob(value: &[u8], encoder: &mut impl Encoder) -> CodecResult<()> {
+    value.len().encode(encoder)?;
+    encoder.write_bytes(value)
+}
+
+/// Decode a length prefixed byte buffer with at most `limit` bytes
+fn decode_blob<'a>(decoder: &mut Decoder<'a>, limit: usize) -> MarshalResult<&'a [u8]> {
+    let length = usize::decode(decoder)?;
+    if length > limit {
+        return Err(MarshalError::BufferTooLarge(length));
+    }
+
+    Ok(decoder.read_bytes(length)?)
+}
+
+struct CaptureVisitor<'a> {
+    payload: &'a [u8],
+}
+
+impl CommandVisitor for CaptureVisitor<'_> {
+    type Output = MarshalResult<Vec<u8>>;
+
+    fn visit<C: CommandCodec>(self) -> Self::Output {
+        if self.payload.len() != mem::size_of::<C>() {
+            return Err(MarshalError::PayloadSizeMissmatch {
+                expected: mem::size_of::<C>(),
+                received: self.payload.len(),
+            });
+        }
+
+        let command = unsafe { ptr::read_unaligned(self.payload.as_ptr() as *const C) };
+        let mut message = Vec::new();
+        unsafe { codec::encode_request(&command, &mut message) }?;
+        Ok(message)
+    }
+}
+
+/// A command including the contents of its input buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandRequest {
+    pub command_id: u32,
+    pub error_capacity: usize,
+
+    /// The command encoded via [codec::encode_request]
+    pub message: Vec<u8>,
+}
+
+impl CommandRequest {
+    /// Capture the command and the buffer it references.
+    ///
+    /// # Safety
+    /// All buffers referenced by the command payload must be valid.
+    pub unsafe fn capture(
+        command_id: u32,
+        payload: &[u8],
+        error_capacity: usize,
+    ) -> MarshalResult<Self> {
+        let message = codec::visit_command(command_id, payload.len(), CaptureVisitor { payload })
+            .ok_or(MarshalError::UnknownCommand(command_id))??;
+
+        Ok(Self {
+            command_id,
+            error_capacity: error_capacity.min(MAX_ERROR_MESSAGE_SIZE),
+            message,
+        })
+    }
+
+    /// Capture a typed command.
+    ///
+    /// # Safety
+    /// All buffers referenced by the command must be valid.
+    pub unsafe fn capture_command<C: CommandCodec>(
+        command: &C,
+        error_capacity: usize,
+    ) -> MarshalResult<Self> {
+        let mut message = Vec::new();
+        codec::encode_request(command, &mut message)?;
+
+        Ok(Self {
+            command_id: C::COMMAND_ID,
+            error_capacity: error_capacity.min(MAX_ERROR_MESSAGE_SIZE),
+            message,
+        })
+    }
+
+    /// Human readable description of the command.
+    /// Falls back to the raw message for unknown commands.
+    pub fn describe(&self) -> String {
+        codec::message_id(&self.message)
+            .ok()
+            .and_then(|message_id| {
+                codec::visit_message(
+                    message_id,
+                    DescribeMessageVisitor {
+                        message: &self.message,
+                    },
+                )
+            })
+            .flatten()
+            .unwrap_or_else(|| format!("{:X}: {:02X?}", self.command_id, self.message))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Vec::new();
+        self.encode_into(&mut encoder)
+            .expect("encoding into a vector can not fail");
+        encoder
+    }
+
+    fn encode_into(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        self.command_id.encode(encoder)?;
+        self.error_capacity.encode(encoder)?;
+        self::encode_blob(&self.message, encoder)
+    }
+
+    pub fn decode(message: &[u8]) -> MarshalResult<Self> {
+        let mut decoder = Decoder::new(message);
+        let command_id = u32::decode(&mut decoder)?;
+
+        let error_capacity = usize::decode(&mut decoder)?;
+        if error_capacity > MAX_ERROR_MESSAGE_SIZE {
+            return Err(MarshalError::ErrorMessageTooLarge(error_capacity));
+        }
+
+        let message = self::decode_blob(&mut decoder, MAX_MESSAGE_SIZE)?.to_vec();
+        decoder.finish()?;
+        Ok(Self {
+            command_id,
+            error_capacity,
+            message,
+        })
+    }
+
+    /// Execute the request by recreating the command buffer locally and invoking the handler.
+    /// The handler receives the command id, the command payload and the error message buffer.
+    pub fn execute(
+        &self,
+        handler: impl FnOnce(u32, &mut [u8], &mut [u8]) -> u64,
+    ) -> MarshalResult<CommandResponse> {
+        let message_id = codec::message_id(&self.message)?;
+        codec::visit_message(
+            message_id,
+            ExecuteVisitor {
+                request: self,
+                handler,
+            },
+        )
+        .ok_or(MarshalError::UnknownCommand(self.command_id))?
+    }
+}
+
+struct ExecuteVisitor<'a, F> {
+    request: &'a CommandRequest,
+    handler: F,
+}
+
+impl<F: FnOnce(u32, &mut [u8], &mut [u8]) -> u64> CommandVisitor for ExecuteVisitor<'_, F> {
+    type Output = MarshalResult<CommandResponse>;
+
+    fn visit<C: CommandCodec>(self) -> Self::Output {
+        if self.request.command_id != C::COMMAND_ID {
+            return Err(CodecError::CommandMissmatch {
+                expected: self.request.command_id,
+                received: C::COMMAND_ID,
+            }
+            .into());
+        }
+
+        let mut command = codec::decode_request::<C>(&self.request.message)?;
+        let mut error_message = vec![0u8; self.request.error_capacity];
+
+        let payload = unsafe {
+            slice::from_raw_parts_mut(
+                command.command_mut() as *mut C as *mut u8,
+                mem::size_of::<C>(),
+            )
+        };
+        let status = (self.handler)(C::COMMAND_ID, payload, &mut error_message);
+
+        let error_length = error_message
+            .iter()
+            .position(|v| *v == 0)
+            .unwrap_or(error_message.len());
+        error_message.truncate(error_length);
+
+        let mut message = Vec::new();
+        command.encode_response(&mut message)?;
+
+        Ok(CommandResponse {
+            status,
+            error_message,
+            message,
+        })
+    }
+}
+
+/// The result of an executed [CommandRequest]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandResponse {
+    pub status: u64,
+    pub error_message: Vec<u8>,
+
+    /// The executed command encoded via [codec::encode_response].
+    /// Empty if the command has not been executed.
+    pub message: Vec<u8>,
+}
+
+impl CommandResponse {
+    /// Create a response which does not carry any command result
+    pub fn from_error(status: CommandResult, message: &str) -> Self {
+        Self {
+            status: status.bits(),
+            error_message: message.as_bytes().to_vec(),
+            message: Vec::new(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Vec::new();
+        self.encode_into(&mut encoder)
+            .expect("encoding into a vector can not fail");
+        encoder
+    }
+
+    fn encode_into(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        self.status.encode(encoder)?;
+        self::encode_blob(&self.error_message, encoder)?;
+        self::encode_blob(&self.message, encoder)
+    }
+
+    pub fn decode(message: &[u8]) -> MarshalResult<Self> {
+        let mut decoder = Decoder::new(message);
+        let status = u64::decode(&mut decoder)?;
+        let error_message = self::decode_blob(&mut decoder, MAX_ERROR_MESSAGE_SIZE)?.to_vec();
+        let message = self::decode_blob(&mut decoder, MAX_MESSAGE_SIZE)?.to_vec();
+        decoder.finish()?;
+
+        Ok(Self {
+            status,
+            error_message,
+            message,
+        })
+    }
+
+    /// Apply the response to the original command and its buffers.
+    /// Returns the command status.
+    ///
+    /// # Safety
+    /// All buffers referenced by the command payload must be valid.
+    pub unsafe fn apply(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> MarshalResult<u64> {
+        if !self.message.is_empty() {
+            codec::visit_command(
+                command_id,
+                payload.len(),
+                ApplyVisitor {
+                    payload,
+                    message: &self.message,
+                },
+            )
+            .ok_or(MarshalError::UnknownCommand(command_id))??;
+        } else if self.status == CommandResult::Success.bits() {
+            return Err(MarshalError::UnexpectedEof);
+        }
+
+        /* if the command has not been executed, only the error will be reported */
+        self.copy_error_message(error_message);
+        Ok(self.status)
+    }
+
+    fn copy_error_message(&self, error_message: &mut [u8]) {
+        let message_length = self
+            .error_message
+            .len()
+            .min(error_message.len().saturating_sub(1));
+        error_message[0..message_length].copy_from_slice(&self.error_message[0..message_length]);
+        if message_length < error_message.len() {
+            error_message[message_length] = 0;
+        }
+    }
+}
+
+struct ApplyVisitor<'a> {
+    payload: &'a mut [u8],
+    message: &'a [u8],
+}
+
+impl CommandVisitor for ApplyVisitor<'_> {
+    type Output = MarshalResult<()>;
+
+    fn visit<C: CommandCodec>(self) -> Self::Output {
+        if self.payload.len() != mem::size_of::<C>() {
+            return Err(MarshalError::PayloadSizeMissmatch {
+                expected: mem::size_of::<C>(),
+                received: self.payload.len(),
+            });
+        }
+
+        let target = self.payload.as_mut_ptr() as *mut C;
+        let mut command = unsafe { ptr::read_unaligned(target) };
+        unsafe { codec::decode_response_into(&mut command, self.message) }?;
+        unsafe { ptr::write_unaligned(target, command) };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use vtd_protocol::{
+        command::{
+            DriverCommand,
+            DriverCommandMemoryRead,
+        },
+        types::MemoryAccessResult,
+        CommandResult,
+    };
+
+    use super::{
+        CommandRequest,
+        CommandResponse,
+        MarshalError,
+        MAX_ERROR_MESSAGE_SIZE,
+    };
+
+    #[test]
+    fn memory_read_round_trip() {
+        let mut buffer = [0u8; 0x10];
+        let mut command = DriverCommandMemoryRead {
+            address: 0x1000,
+            buffer: buffer.as_mut_ptr(),
+            count: buffer.len(),
+            ..Default::default()
+        };
+
+        let request = unsafe { CommandRequest::capture_command(&command, 0x100) }.unwrap();
+        let request = CommandRequest::decode(&request.encode()).unwrap();
+
+        let response = request
+            .execute(|command_id, payload, _error| {
+                assert_eq!(command_id, DriverCommandMemoryRead::COMMAND_ID);
+                let command =
+                    unsafe { &mut *(payload.as_mut_ptr() as *mut DriverCommandMemoryRead) };
+                assert_eq!(command.address, 0x1000);
+                assert_eq!(command.count, 0x10);
+
+                let buffer =
+                    unsafe { core::slice::from_raw_parts_mut(command.buffer, command.count) };
+                buffer.fill(0xCC);
+                command.result = MemoryAccessResult::Success;
+                CommandResult::Success.bits()
+            })
+            .unwrap();
+        let response = CommandResponse::decode(&response.encode()).unwrap();
+
+        let original_buffer = command.buffer;
+        let payload = unsafe {
+            core::slice::from_raw_parts_mut(
+                &mut command as *mut _ as *mut u8,
+                core::mem::size_of::<DriverCommandMemoryRead>(),
+            )
+        };
+        let mut error_message = [0u8; 0x100];
+        let status = unsafe {
+            response.apply(
+                DriverCommandMemoryRead::COMMAND_ID,
+                payload,
+                &mut error_message,
+            )
+        }
+        .unwrap();
+
+        assert_eq!(status, CommandResult::Success.bits());
+        assert_eq!(command.buffer, original_buffer);
+        assert!(matches!(command.result, MemoryAccessResult::Success));
+        assert_eq!(buffer, [0xCC; 0x10]);
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let command = DriverCommandMemoryRead::default();
+        let request = unsafe { CommandRequest::capture_command(&command, 0x100) }.unwrap();
+        let message = request.encode();
+
+        assert!(CommandRequest::decode(&message[..message.len() - 1]).is_err());
+
+        let mut trailing = message.clone();
+        trailing.push(0x00);
+        assert!(CommandRequest::decode(&trailing).is_err());
+
+        let mut error_capacity = message.clone();
+        error_capacity[4..12].copy_from_slice(&(MAX_ERROR_MESSAGE_SIZE as u64 + 1).to_le_bytes());
+        assert_eq!(
+            CommandRequest::decode(&error_capacity),
+            Err(MarshalError::ErrorMessageTooLarge(
+                MAX_ERROR_MESSAGE_SIZE + 1
+            ))
+        );
+    }
+}
@@ -0,0 +1,714 @@
+//! In-memory driver simulating processes, modules and their address spaces.
+//!
+//! The [MockDriver] implements [DriverBackend] and can be used with [DriverInterface::with_backend](crate::DriverInterface::with_backend)
+//! to test code consuming the driver interface without a real driver or target process.
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{
+        Arc,
+        Mutex,
+        MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use vtd_protocol::{
+    command::{
+        DriverCommand,
+        DriverCommandInitialize,
+        DriverCommandMemoryRead,
+        DriverCommandMemoryWrite,
+        DriverCommandProcessList,
+        DriverCommandProcessModules,
+        InitializeResult,
+    },
+    types::{
+        DirectoryTableType,
+        DriverFeature,
+        MemoryAccessResult,
+        ProcessId,
+        ProcessInfo,
+        ProcessModuleInfo,
+    },
+    utils::str_to_fixed_buffer,
+    CommandResult,
+    PROTOCOL_VERSION,
+};
+
+use crate::{
+    DriverBackend,
+    HandlerBackend,
+    IResult,
+};
+
+#[derive(Debug, Clone)]
+pub struct MockModule {
+    pub name: String,
+    pub base_address: u64,
+    pub size: u64,
+}
+
+/// A simulated process with its own address space.
+#[derive(Debug, Clone)]
+pub struct MockProcess {
+    pub process_id: ProcessId,
+    pub name: String,
+    pub directory_table_base: u64,
+
+    pub modules: Vec<MockModule>,
+
+    /// Mapped memory regions by their start address
+    regions: BTreeMap<u64, Vec<u8>>,
+
+    /// Ranges which have been paged out and can not be accessed
+    paged_out: Vec<Range<u64>>,
+}
+
+impl MockProcess {
+    pub fn new(process_id: ProcessId, name: &str) -> Self {
+        Self {
+            process_id,
+            name: name.to_string(),
+            directory_table_base: 0x1000 * process_id as u64,
+
+            modules: Vec::new(),
+
+            regions: Default::default(),
+            paged_out: Vec::new(),
+        }
+    }
+
+    pub fn with_directory_table_base(mut self, directory_table_base: u64) -> Self {
+        self.directory_table_base = directory_table_base;
+        self
+    }
+
+    /// Add a module and map its memory initialized with zeros
+    pub fn with_module(self, name: &str, base_address: u64, size: u64) -> Self {
+        self.with_module_image(name, base_address, vec![0; size as usize])
+    }
+
+    /// Add a module and map its memory with the given image
+    pub fn with_module_image(mut self, name: &str, base_address: u64, image: Vec<u8>) -> Self {
+        self.modules.push(MockModule {
+            name: name.to_string(),
+            base_address,
+            size: image.len() as u64,
+        });
+        self.with_memory(base_address, image)
+    }
+
+    /// Map a memory region.
+    /// Regions must not overlap with previously mapped regions.
+    pub fn with_memory(mut self, address: u64, contents: Vec<u8>) -> Self {
+        self.map_memory(address, contents);
+        self
+    }
+
+    /// Mark the given memory range as paged out
+    pub fn with_paged_out(mut self, range: Range<u64>) -> Self {
+        self.paged_out.push(range);
+        self
+    }
+
+    pub fn map_memory(&mut self, address: u64, contents: Vec<u8>) {
+        let end = address
+            .checked_add(contents.len() as u64)
+            .expect("memory region exceeds the address space");
+        assert!(
+            self.regions.iter().all(|(start, region)| {
+                end <= *start || *start + region.len() as u64 <= address
+            }),
+            "memory region overlaps with existing region"
+        );
+
+        self.regions.insert(address, contents);
+    }
+
+    pub fn set_paged_out(&mut self, range: Range<u64>, paged_out: bool) {
+        if paged_out {
+            self.paged_out.push(range);
+        } else {
+            self.paged_out.retain(|entry| *entry != range);
+        }
+    }
+
+    /// Find the mapped chunk starting at address.
+    /// Returns the region start and the length available until the next page out or region end.
+    fn find_chunk(&self, address: u64, length: u64) -> Result<(u64, u64), MemoryAccessResult> {
+        if self.paged_out.iter().any(|range| range.contains(&address)) {
+            return Err(MemoryAccessResult::SourcePagedOut);
+        }
+
+        let (start, region) = self
+            .regions
+            .range(..=address)
+            .next_back()
+            .filter(|(start, region)| address < **start + region.len() as u64)
+            .ok_or(MemoryAccessResult::PartialSuccess { bytes_copied: 0 })?;
+
+        let request_end = address
+            .checked_add(length)
+            .ok_or(MemoryAccessResult::PartialSuccess { bytes_copied: 0 })?;
+        let mut end = (*start + region.len() as u64).min(request_end);
+        for range in self.paged_out.iter() {
+            if range.start > address {
+                end = end.min(range.start);
+            }
+        }
+
+        Ok((*start, end - address))
+    }
+
+    fn access_memory(
+        &mut self,
+        address: u64,
+        length: usize,
+        paged_out_result: MemoryAccessResult,
+        mut callback: impl FnMut(usize, &mut [u8]),
+    ) -> MemoryAccessResult {
+        let mut offset = 0;
+        while offset < length {
+            let Some(current_address) = address.checked_add(offset as u64) else {
+                return MemoryAccessResult::PartialSuccess {
+                    bytes_copied: offset,
+                };
+            };
+            let (start, chunk_length) = match self
+                .find_chunk(current_address, (length - offset) as u64)
+            {
+                Ok(chunk) => chunk,
+                Err(MemoryAccessResult::SourcePagedOut) if offset == 0 => return paged_out_result,
+                Err(_) => {
+                    return MemoryAccessResult::PartialSuccess {
+                        bytes_copied: offset,
+                    }
+                }
+            };
+
+            let region = self.regions.get_mut(&start).unwrap();
+            let region_offset = (current_address - start) as usize;
+            callback(
+                offset,
+                &mut region[region_offset..region_offset + chunk_length as usize],
+            );
+            offset += chunk_length as usize;
+        }
+
+        MemoryAccessResult::Success
+    }
+
+    /// Read memory from the simulated address space
+    pub fn read_memory(&mut self, address: u64, buffer: &mut [u8]) -> MemoryAccessResult {
+        self.access_memory(
+            address,
+            buffer.len(),
+            MemoryAccessResult::SourcePagedOut,
+            |offset, chunk| buffer[offset..offset + chunk.len()].copy_from_slice(chunk),
+        )
+    }
+
+    /// Write memory into the simulated address space
+    pub fn write_memory(&mut self, address: u64, buffer: &[u8]) -> MemoryAccessResult {
+        self.access_memory(
+            address,
+            buffer.len(),
+            MemoryAccessResult::DestinationPagedOut,
+            |offset, chunk| chunk.copy_from_slice(&buffer[offset..offset + chunk.len()]),
+        )
+    }
+}
+
+struct MockState {
+    features: DriverFeature,
+    available: bool,
+
+    processes: BTreeMap<ProcessId, MockProcess>,
+
+    latency: Option<Duration>,
+    command_failures: BTreeMap<u32, String>,
+}
+
+impl MockState {
+    fn process(
+        &mut self,
+        process_id: ProcessId,
+        directory_table_type: &DirectoryTableType,
+    ) -> Option<&mut MockProcess> {
+        let process = self.processes.get_mut(&process_id)?;
+        match directory_table_type {
+            DirectoryTableType::Explicit {
+                directory_table_base,
+            } if *directory_table_base != process.directory_table_base => None,
+            _ => Some(process),
+        }
+    }
+}
+
+/// A simulated driver.
+///
+/// The driver can be cloned and all clones share the same state,
+/// which allows altering the simulated processes and injecting failures while the driver is in use.
+#[derive(Clone)]
+pub struct MockDriver {
+    state: Arc<Mutex<MockState>>,
+    handler: Arc<HandlerBackend>,
+}
+
+impl Default for MockDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockDriver {
+    pub fn new() -> Self {
+        let state = Arc::new(Mutex::new(MockState {
+            features: DriverFeature::ProcessList
+                | DriverFeature::ProcessModules
+                | DriverFeature::MemoryRead
+                | DriverFeature::MemoryWrite
+                | DriverFeature::DttExplicit,
+            available: true,
+
+            processes: Default::default(),
+
+            latency: None,
+            command_failures: Default::default(),
+        }));
+
+        let handler = Arc::new(Self::create_handler(&state));
+        Self { state, handler }
+    }
+
+    fn create_handler(state: &Arc<Mutex<MockState>>) -> HandlerBackend {
+        let mut handler = HandlerBackend::new();
+
+        let handler_state = state.clone();
+        handler.register(move |command: &mut DriverCommandInitialize| {
+            let state = self::lock_state(&handler_state);
+            command.driver_protocol_version = PROTOCOL_VERSION;
+            if command.client_protocol_version != PROTOCOL_VERSION {
+                return Ok::<_, String>(());
+            }
+
+            command.driver_version.set_application_name("mock");
+            command.driver_version.version_major = 1;
+            command.driver_features = state.features;
+            command.result = if state.available {
+                InitializeResult::Success
+            } else {
+                InitializeResult::Unavailable
+            };
+            Ok(())
+        });
+
+        let handler_state = state.clone();
+        handler.register(move |command: &mut DriverCommandProcessList| {
+            let state = self::lock_state(&handler_state);
+            let buffer = unsafe { self::command_buffer(command.buffer, command.buffer_capacity) };
+            for (entry, process) in buffer.iter_mut().zip(state.processes.values()) {
+                *entry = ProcessInfo::default();
+                entry.process_id = process.process_id;
+                entry.directory_table_base = process.directory_table_base;
+                entry.set_image_base_name(&process.name);
+            }
+
+            command.process_count = state.processes.len();
+            Ok::<_, String>(())
+        });
+
+        let handler_state = state.clone();
+        handler.register(move |command: &mut DriverCommandProcessModules| {
+            let mut state = self::lock_state(&handler_state);
+            let Some(process) = state.process(command.process_id, &command.directory_table_type)
+            else {
+                command.process_unknown = true;
+                return Ok::<_, String>(());
+            };
+
+            let buffer = unsafe { self::command_buffer(command.buffer, command.buffer_capacity) };
+            for (entry, module) in buffer.iter_mut().zip(process.modules.iter()) {
+                *entry = ProcessModuleInfo::default();
+                entry.set_base_dll_name(&module.name);
+                entry.base_address = module.base_address;
+                entry.module_size = module.size;
+            }
+
+            command.process_unknown = false;
+            command.module_count = process.modules.len();
+            Ok(())
+        });
+
+        let handler_state = state.clone();
+        handler.register(move |command: &mut DriverCommandMemoryRead| {
+            let mut state = self::lock_state(&handler_state);
+            let buffer = unsafe { self::command_buffer(command.buffer, command.count) };
+            command.result = match state.process(command.process_id, &command.directory_table_type)
+            {
+                Some(process) => process.read_memory(command.address, buffer),
+                None => MemoryAccessResult::ProcessUnknown,
+            };
+            Ok::<_, String>(())
+        });
+
+        let handler_state = state.clone();
+        handler.register(move |command: &mut DriverCommandMemoryWrite| {
+            let mut state = self::lock_state(&handler_state);
+            let buffer: &[u8] = if command.count > 0 {
+                unsafe { core::slice::from_raw_parts(command.buffer, command.count) }
+            } else {
+                &[]
+            };
+            command.result = match state.process(command.process_id, &command.directory_table_type)
+            {
+                Some(process) => process.write_memory(command.address, buffer),
+                None => MemoryAccessResult::ProcessUnknown,
+            };
+            Ok::<_, String>(())
+        });
+
+        handler
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self::lock_state(&self.state)
+    }
+
+    pub fn with_process(self, process: MockProcess) -> Self {
+        self.add_process(process);
+        self
+    }
+
+    pub fn with_features(self, features: DriverFeature) -> Self {
+        self.state().features = features;
+        self
+    }
+
+    pub fn add_process(&self, process: MockProcess) {
+        self.state().processes.insert(process.process_id, process);
+    }
+
+    /// Remove a process. Further commands targeting this process report it as unknown.
+    pub fn remove_process(&self, process_id: ProcessId) -> Option<MockProcess> {
+        self.state().processes.remove(&process_id)
+    }
+
+    /// Access a simulated process e.g. to alter its memory
+    pub fn with_process_mut<R>(
+        &self,
+        process_id: ProcessId,
+        callback: impl FnOnce(&mut MockProcess) -> R,
+    ) -> Option<R> {
+        self.state().processes.get_mut(&process_id).map(callback)
+    }
+
+    /// Report the driver as unavailable on initialize
+    pub fn set_available(&self, available: bool) {
+        self.state().available = available;
+    }
+
+    /// Delay every command by the given duration
+    pub fn set_latency(&self, latency: Option<Duration>) {
+        self.state().latency = latency;
+    }
+
+    /// Let every execution of the command fail with the given error message
+    pub fn set_command_failure<C: DriverCommand>(&self, message: Option<&str>) {
+        let mut state = self.state();
+        match message {
+            Some(message) => state
+                .command_failures
+                .insert(C::COMMAND_ID, message.to_string()),
+            None => state.command_failures.remove(&C::COMMAND_ID),
+        };
+    }
+}
+
+impl DriverBackend for MockDriver {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        let (latency, failure) = {
+            let state = self.state();
+            (
+                state.latency,
+                state.command_failures.get(&command_id).cloned(),
+            )
+        };
+
+        if let Some(latency) = latency {
+            thread::sleep(latency);
+        }
+
+        if let Some(failure) = failure {
+            str_to_fixed_buffer(error_message, &failure);
+            return Ok(CommandResult::Error.bits());
+        }
+
+        self.handler
+            .execute_command(command_id, payload, error_message)
+    }
+}
+
+fn lock_state(state: &Mutex<MockState>) -> MutexGuard<'_, MockState> {
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+unsafe fn command_buffer<'a, T>(buffer: *mut T, count: usize) -> &'a mut [T] {
+    if buffer.is_null() || count == 0 {
+        &mut []
+    } else {
+        core::slice::from_raw_parts_mut(buffer, count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    use vtd_protocol::{
+        command::DriverCommandProcessList,
+        types::{
+            DirectoryTableType,
+            DriverFeature,
+        },
+    };
+
+    use super::{
+        MockDriver,
+        MockProcess,
+    };
+    use crate::{
+        DriverInterface,
+        InterfaceError,
+    };
+
+    fn create_driver() -> MockDriver {
+        MockDriver::new().with_process(
+            MockProcess::new(42, "cs2.exe")
+                .with_module_image("client.dll", 0x10000, (0..0x100).map(|v| v as u8).collect())
+                .with_memory(0x10100, vec![0xFF; 0x100])
+                .with_paged_out(0x10180..0x11000),
+        )
+    }
+
+    #[test]
+    fn processes_and_modules() {
+        let driver = self::create_driver();
+        let interface = DriverInterface::with_backend(driver.clone()).unwrap();
+
+        let processes = interface.list_processes().unwrap();
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].process_id, 42);
+        assert_eq!(processes[0].get_image_base_name(), Some("cs2.exe"));
+
+        let modules = interface
+            .list_modules(42, DirectoryTableType::Default)
+            .unwrap();
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].get_base_dll_name(), Some("client.dll"));
+        assert_eq!(modules[0].base_address, 0x10000);
+        assert_eq!(modules[0].module_size, 0x100);
+
+        driver.remove_process(42);
+        assert!(matches!(
+            interface.list_modules(42, DirectoryTableType::Default),
+            Err(InterfaceError::ProcessUnknown)
+        ));
+    }
+
+    #[test]
+    fn memory_access() {
+        let interface = DriverInterface::with_backend(self::create_driver()).unwrap();
+
+        /* read across two adjacent regions */
+        let mut buffer = [0u8; 4];
+        interface
+            .read_slice(42, DirectoryTableType::Default, 0x100FE, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [0xFE, 0xFF, 0xFF, 0xFF]);
+
+        interface
+            .write_slice::<u8>(42, DirectoryTableType::Default, 0x10010, &[1, 2, 3])
+            .unwrap();
+        interface
+            .read_slice(42, DirectoryTableType::Default, 0x10010, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [1, 2, 3, 0x13]);
+
+        assert!(matches!(
+            interface.read_slice(42, DirectoryTableType::Default, 0x10180, &mut buffer),
+            Err(InterfaceError::MemoryAccessPagedOut)
+        ));
+        assert!(matches!(
+            interface.read_slice(42, DirectoryTableType::Default, 0x1017E, &mut buffer),
+            Err(InterfaceError::MemoryAccessFailed)
+        ));
+        assert!(matches!(
+            interface.read_slice(42, DirectoryTableType::Default, 0x50000, &mut buffer),
+            Err(InterfaceError::MemoryAccessFailed)
+        ));
+        assert!(matches!(
+            interface.read_slice(7, DirectoryTableType::Default, 0x10000, &mut buffer),
+            Err(InterfaceError::ProcessUnknown)
+        ));
+    }
+
+    #[test]
+    fn memory_access_overflow() {
+        let driver = MockDriver::new().with_process(
+            MockProcess::new(42, "cs2.exe").with_memory(u64::MAX - 0x10, vec![0xCC; 0x10]),
+        );
+        let interface = DriverInterface::with_backend(driver).unwrap();
+
+        let mut buffer = [0u8; 4];
+        interface
+            .read_slice(
+                42,
+                DirectoryTableType::Default,
+                u64::MAX - 0x04,
+                &mut buffer,
+            )
+            .unwrap();
+        assert_eq!(buffer, [0xCC; 4]);
+
+        /* accesses wrapping around the address space must fail instead of overflowing */
+        for address in [u64::MAX - 0x02, u64::MAX] {
+            assert!(matches!(
+                interface.read_slice(42, DirectoryTableType::Default, address, &mut buffer),
+                Err(InterfaceError::MemoryAccessFailed)
+            ));
+            assert!(matches!(
+                interface.write_slice::<u8>(42, DirectoryTableType::Default, address, &buffer),
+                Err(InterfaceError::MemoryAccessFailed)
+            ));
+        }
+    }
+
+    #[test]
+    fn typed_access() {
+        let interface = DriverInterface::with_backend(self::create_driver()).unwrap();
+        let dtt = DirectoryTableType::Default;
+
+        assert_eq!(interface.read::<u32>(42, dtt, 0x10000).unwrap(), 0x03020100);
+        assert_eq!(
+            interface.read_array::<u16, 2>(42, dtt, 0x10004).unwrap(),
+            [0x0504, 0x0706]
+        );
+        assert_eq!(
+            interface.read_vec::<u8>(42, dtt, 0x100FE, 2).unwrap(),
+            [0xFE, 0xFF]
+        );
+
+        interface
+            .write::<u32>(42, dtt, 0x10010, &0xDEADBEEF)
+            .unwrap();
+        assert_eq!(interface.read::<u32>(42, dtt, 0x10010).unwrap(), 0xDEADBEEF);
+
+        interface
+            .write_slice(42, dtt, 0x10020, b"client.dll\0")
+            .unwrap();
+        assert_eq!(
+            interface.read_string(42, dtt, 0x10020, 32).unwrap(),
+            "client.dll"
+        );
+        assert_eq!(
+            interface.read_string(42, dtt, 0x10020, 10).unwrap(),
+            "client.dll"
+        );
+        assert!(matches!(
+            interface.read_string(42, dtt, 0x10020, 4),
+            Err(InterfaceError::StringUnterminated { max_length: 4 })
+        ));
+
+        let name = "cs2.exe\0".encode_utf16().collect::<Vec<_>>();
+        interface.write_slice(42, dtt, 0x10040, &name).unwrap();
+        assert_eq!(
+            interface.read_string_utf16(42, dtt, 0x10040, 32).unwrap(),
+            "cs2.exe"
+        );
+
+        interface
+            .write_slice::<u8>(42, dtt, 0x10060, &[0xC3, 0x28, 0x00])
+            .unwrap();
+        assert!(matches!(
+            interface.read_string(42, dtt, 0x10060, 32),
+            Err(InterfaceError::StringInvalidEncoding { .. })
+        ));
+
+        /* unterminated string running into paged out memory */
+        assert!(matches!(
+            interface.read_string(42, dtt, 0x10100, 0x1000),
+            Err(InterfaceError::MemoryAccessFailed)
+        ));
+    }
+
+    #[test]
+    fn directory_table_fallback() {
+        let explicit = DirectoryTableType::Explicit {
+            directory_table_base: 0xDEAD000,
+        };
+        let mut buffer = [0u8; 4];
+
+        let interface = DriverInterface::with_backend(self::create_driver()).unwrap();
+        assert!(matches!(
+            interface.effective_directory_table_type(explicit),
+            DirectoryTableType::Explicit { .. }
+        ));
+        assert!(matches!(
+            interface.read_slice(42, explicit, 0x10000, &mut buffer),
+            Err(InterfaceError::ProcessUnknown)
+        ));
+
+        /* without explicit directory table support the default directory table base is used */
+        let interface = DriverInterface::with_backend(
+            self::create_driver()
+                .with_features(DriverFeature::ProcessModules | DriverFeature::MemoryRead),
+        )
+        .unwrap();
+        assert!(matches!(
+            interface.effective_directory_table_type(explicit),
+            DirectoryTableType::Default
+        ));
+        interface
+            .read_slice(42, explicit, 0x10000, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(interface.list_modules(42, explicit).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failure_injection() {
+        let driver = self::create_driver();
+        let interface = DriverInterface::with_backend(driver.clone()).unwrap();
+
+        driver.set_command_failure::<DriverCommandProcessList>(Some("injected"));
+        assert!(matches!(
+            interface.list_processes(),
+            Err(InterfaceError::CommandGenericError { message }) if message == "injected"
+        ));
+
+        driver.set_command_failure::<DriverCommandProcessList>(None);
+        assert!(interface.list_processes().is_ok());
+
+        driver.set_latency(Some(Duration::from_millis(20)));
+        let start = Instant::now();
+        assert!(interface.list_processes().is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        driver.set_available(false);
+        assert!(matches!(
+            DriverInterface::with_backend(driver),
+            Err(InterfaceError::InitializeDriverUnavailable)
+        ));
+    }
+}
@@ -0,0 +1,63 @@
+//! Plain old data types which can be read from and written to remote memory.
+use core::{
+    mem,
+    slice,
+};
+
+/// A type which can be safely created from and converted into raw bytes.
+///
+/// # Safety
+/// Implementors must guarantee that
+/// - every bit pattern is a valid value of the type (this excludes `bool`, `char`, references and enums),
+/// - the type does not contain any padding bytes and
+/// - the type does not own any resources (it must not implement `Drop`).
+///
+/// Structs should be `#[repr(C)]` or `#[repr(transparent)]` and only contain `Pod` fields.
+pub unsafe trait Pod: Copy + 'static {
+    /// A value with all bytes set to zero
+    fn zeroed() -> Self {
+        unsafe { mem::zeroed() }
+    }
+}
+
+macro_rules! impl_pod {
+    ($($type:ty),*) => {
+        $(unsafe impl Pod for $type {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// View the values as raw bytes
+pub fn bytes_of_slice<T: Pod>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+}
+
+/// View the values as mutable raw bytes
+pub fn bytes_of_slice_mut<T: Pod>(values: &mut [T]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, mem::size_of_val(values)) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        bytes_of_slice,
+        bytes_of_slice_mut,
+        Pod,
+    };
+
+    #[test]
+    fn byte_views() {
+        let mut values = [0x11223344u32, 0x55667788];
+        assert_eq!(
+            bytes_of_slice(&values),
+            [0x44, 0x33, 0x22, 0x11, 0x88, 0x77, 0x66, 0x55]
+        );
+
+        bytes_of_slice_mut(&mut values)[4..].fill(0);
+        assert_eq!(values, [0x11223344, 0]);
+        assert_eq!(<[u16; 3]>::zeroed(), [0; 3]);
+    }
+}
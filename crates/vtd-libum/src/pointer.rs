@@ -0,0 +1,412 @@
+//! Typed pointers into the memory of a remote process.
+//!
+//! ```no_run
+//! # use vtd_libum::{DriverInterface, Ptr64, RemoteProcess};
+//! # use vtd_libum::protocol::types::DirectoryTableType;
+//! # fn example(interface: &DriverInterface) -> vtd_libum::IResult<()> {
+//! let process = RemoteProcess::new(interface, 1234, DirectoryTableType::Default);
+//!
+//! /* client.dll + 0x1000 -> entity list -> entity -> health */
+//! let health = process
+//!     .ptr::<Ptr64<Ptr64<[u8; 0x400]>>>(0x7FF6_0000_1000)
+//!     .deref()?
+//!     .deref()?
+//!     .offset(0x344)
+//!     .cast::<i32>()
+//!     .read()?;
+//! # Ok(())
+//! # }
+//! ```
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem,
+};
+
+use vtd_protocol::types::{
+    DirectoryTableType,
+    ProcessId,
+};
+
+use crate::{
+    DriverInterface,
+    IResult,
+    InterfaceError,
+    Pod,
+};
+
+/// A process whose memory is accessed through the driver interface
+#[derive(Clone, Copy)]
+pub struct RemoteProcess<'a> {
+    interface: &'a DriverInterface,
+    process_id: ProcessId,
+    directory_table_type: DirectoryTableType,
+}
+
+impl<'a> RemoteProcess<'a> {
+    pub fn new(
+        interface: &'a DriverInterface,
+        process_id: ProcessId,
+        directory_table_type: DirectoryTableType,
+    ) -> Self {
+        Self {
+            interface,
+            process_id,
+            directory_table_type,
+        }
+    }
+
+    pub fn interface(&self) -> &'a DriverInterface {
+        self.interface
+    }
+
+    pub fn process_id(&self) -> ProcessId {
+        self.process_id
+    }
+
+    pub fn directory_table_type(&self) -> DirectoryTableType {
+        self.directory_table_type
+    }
+
+    /// Pointer to a `T` at the given address
+    pub fn ptr<T>(&self, address: u64) -> RemotePtr<'a, T> {
+        RemotePtr {
+            process: *self,
+            address,
+            hop: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A 64 bit pointer to a `T` stored within the remote process memory.
+/// Use [RemotePtr::deref] to follow the pointer.
+#[repr(transparent)]
+pub struct Ptr64<T> {
+    pub address: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Ptr64<T> {
+    pub fn new(address: u64) -> Self {
+        Self {
+            address,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+}
+
+impl<T> Clone for Ptr64<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Ptr64<T> {}
+
+impl<T> fmt::Debug for Ptr64<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ptr64({:X})", self.address)
+    }
+}
+
+unsafe impl<T: 'static> Pod for Ptr64<T> {}
+
+/// Typed pointer into the memory of a [RemoteProcess].
+///
+/// The pointer keeps track of how many pointers have been followed to reach it.
+/// Failures are reported as [InterfaceError::PointerChainFailed] containing the failed hop.
+pub struct RemotePtr<'a, T> {
+    process: RemoteProcess<'a>,
+    address: u64,
+
+    /// Number of pointers followed to reach this pointer
+    hop: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for RemotePtr<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for RemotePtr<'_, T> {}
+
+impl<T> fmt::Debug for RemotePtr<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemotePtr")
+            .field("process_id", &self.process.process_id)
+            .field("address", &format_args!("{:X}", self.address))
+            .field("hop", &self.hop)
+            .finish()
+    }
+}
+
+impl<'a, T> RemotePtr<'a, T> {
+    pub fn process(&self) -> RemoteProcess<'a> {
+        self.process
+    }
+
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Number of pointers followed to reach this pointer
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+
+    fn chain_error(&self, error: InterfaceError) -> InterfaceError {
+        InterfaceError::PointerChainFailed {
+            hop: self.hop,
+            address: self.address,
+            error: Box::new(error),
+        }
+    }
+
+    /// Offset the pointer by `offset` bytes
+    pub fn offset(&self, offset: u64) -> Self {
+        Self {
+            address: self.address.wrapping_add(offset),
+            ..*self
+        }
+    }
+
+    /// Reinterpret the pointer as a pointer to `U`
+    pub fn cast<U>(&self) -> RemotePtr<'a, U> {
+        RemotePtr {
+            process: self.process,
+            address: self.address,
+            hop: self.hop,
+            _marker: PhantomData,
+        }
+    }
+
+    /// View `length` consecutive values starting at this pointer
+    pub fn array(&self, length: usize) -> RemoteArray<'a, T> {
+        RemoteArray {
+            start: *self,
+            length,
+        }
+    }
+}
+
+impl<T: Pod> RemotePtr<'_, T> {
+    pub fn read(&self) -> IResult<T> {
+        self.process
+            .interface
+            .read(
+                self.process.process_id,
+                self.process.directory_table_type,
+                self.address,
+            )
+            .map_err(|error| self.chain_error(error))
+    }
+}
+
+impl<'a, T: 'static> RemotePtr<'a, Ptr64<T>> {
+    /// Read the pointer stored at this address and follow it.
+    /// Fails with [InterfaceError::NullPointer] if the stored pointer is null.
+    pub fn deref(&self) -> IResult<RemotePtr<'a, T>> {
+        let target = self.read()?;
+        if target.is_null() {
+            return Err(self.chain_error(InterfaceError::NullPointer));
+        }
+
+        Ok(RemotePtr {
+            process: self.process,
+            address: target.address,
+            hop: self.hop + 1,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// A view of consecutive values within the memory of a [RemoteProcess]
+pub struct RemoteArray<'a, T> {
+    start: RemotePtr<'a, T>,
+    length: usize,
+}
+
+impl<T> Clone for RemoteArray<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for RemoteArray<'_, T> {}
+
+impl<T> fmt::Debug for RemoteArray<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteArray")
+            .field("start", &self.start)
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+impl<'a, T> RemoteArray<'a, T> {
+    pub fn start(&self) -> RemotePtr<'a, T> {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Pointer to the element at `index` or `None` if the index is out of bounds
+    pub fn get(&self, index: usize) -> Option<RemotePtr<'a, T>> {
+        if index >= self.length {
+            return None;
+        }
+
+        Some(
+            self.start
+                .offset((index as u64).wrapping_mul(mem::size_of::<T>() as u64)),
+        )
+    }
+
+    /// Pointers to all elements
+    pub fn iter(&self) -> impl Iterator<Item = RemotePtr<'a, T>> + 'a
+    where
+        T: 'a,
+    {
+        let array = *self;
+        (0..self.length).filter_map(move |index| array.get(index))
+    }
+}
+
+impl<T: Pod> RemoteArray<'_, T> {
+    /// Read the element at `index`
+    pub fn read(&self, index: usize) -> IResult<T> {
+        self.get(index)
+            .ok_or(InterfaceError::IndexOutOfBounds {
+                index,
+                length: self.length,
+            })?
+            .read()
+    }
+
+    /// Read all elements at once
+    pub fn read_all(&self) -> IResult<Vec<T>> {
+        let process = &self.start.process;
+        process
+            .interface
+            .read_vec(
+                process.process_id,
+                process.directory_table_type,
+                self.start.address,
+                self.length,
+            )
+            .map_err(|error| self.start.chain_error(error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use vtd_protocol::types::DirectoryTableType;
+
+    use super::{
+        Ptr64,
+        RemoteProcess,
+    };
+    use crate::{
+        mock::{
+            MockDriver,
+            MockProcess,
+        },
+        DriverInterface,
+        InterfaceError,
+    };
+
+    fn create_interface() -> DriverInterface {
+        let mut memory = vec![0u8; 0x100];
+
+        /* 0x10000 -> 0x10040 -> 0x10080 */
+        memory[0x00..0x08].copy_from_slice(&0x10040u64.to_le_bytes());
+        memory[0x40..0x48].copy_from_slice(&0x10080u64.to_le_bytes());
+        for (index, value) in [10u32, 20, 30, 40].iter().enumerate() {
+            memory[0x80 + index * 4..0x84 + index * 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        /* 0x10008 -> null, 0x10010 -> unmapped */
+        memory[0x10..0x18].copy_from_slice(&0x50000u64.to_le_bytes());
+
+        let driver = MockDriver::new()
+            .with_process(MockProcess::new(42, "cs2.exe").with_memory(0x10000, memory));
+        DriverInterface::with_backend(driver).unwrap()
+    }
+
+    #[test]
+    fn pointer_chain() {
+        let interface = self::create_interface();
+        let process = RemoteProcess::new(&interface, 42, DirectoryTableType::Default);
+
+        let values = process
+            .ptr::<Ptr64<Ptr64<u32>>>(0x10000)
+            .deref()
+            .unwrap()
+            .deref()
+            .unwrap();
+        assert_eq!(values.address(), 0x10080);
+        assert_eq!(values.hop(), 2);
+        assert_eq!(values.read().unwrap(), 10);
+        assert_eq!(values.offset(0x04).read().unwrap(), 20);
+        assert_eq!(values.cast::<u64>().read().unwrap(), 20 << 32 | 10);
+
+        let array = values.array(4);
+        assert_eq!(array.read(3).unwrap(), 40);
+        assert!(array.get(4).is_none());
+        assert!(matches!(
+            array.read(4),
+            Err(InterfaceError::IndexOutOfBounds {
+                index: 4,
+                length: 4
+            })
+        ));
+        assert_eq!(
+            array
+                .iter()
+                .map(|value| value.read().unwrap())
+                .collect::<Vec<_>>(),
+            [10, 20, 30, 40]
+        );
+        assert_eq!(array.read_all().unwrap(), [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn failed_hop() {
+        let interface = self::create_interface();
+        let process = RemoteProcess::new(&interface, 42, DirectoryTableType::Default);
+
+        assert!(matches!(
+            process.ptr::<Ptr64<Ptr64<u32>>>(0x10008).deref(),
+            Err(InterfaceError::PointerChainFailed { hop: 0, address: 0x10008, error })
+                if matches!(*error, InterfaceError::NullPointer)
+        ));
+
+        let result = process
+            .ptr::<Ptr64<Ptr64<u32>>>(0x10010)
+            .deref()
+            .unwrap()
+            .deref();
+        assert!(matches!(
+            result,
+            Err(InterfaceError::PointerChainFailed { hop: 1, address: 0x50000, error })
+                if matches!(*error, InterfaceError::MemoryAccessFailed)
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    time::Duration,
+};
+
+use hmac::{
+    Hmac,
+    Mac,
+};
+use sha2::Sha256;
+
+use super::{
+    REMOTE_HANDSHAKE_MAGIC,
+    REMOTE_PROTOCOL_VERSION,
+};
+use crate::host;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const NONCE_SIZE: usize = 0x20;
+const MAC_SIZE: usize = 0x20;
+
+/// Upper bound for any frame exchanged before the peer has been authenticated
+const HANDSHAKE_MAX_FRAME_SIZE: usize = 8 + NONCE_SIZE + MAC_SIZE;
+
+/// Time the peer has to complete the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DIRECTION_CLIENT: u8 = 0x00;
+const DIRECTION_SERVER: u8 = 0x01;
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+fn create_nonce() -> io::Result<[u8; NONCE_SIZE]> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    getrandom::getrandom(&mut nonce).map_err(io::Error::other)?;
+    Ok(nonce)
+}
+
+fn authentication_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message.to_string())
+}
+
+fn read_handshake_frame(stream: &mut impl Read, length: usize) -> io::Result<Vec<u8>> {
+    let payload = host::read_frame_limited(stream, HANDSHAKE_MAX_FRAME_SIZE)?
+        .ok_or_else(|| authentication_error("connection closed during the handshake"))?;
+
+    if payload.len() != length || &payload[0..4] != REMOTE_HANDSHAKE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid handshake",
+        ));
+    }
+
+    let version = u32::from_le_bytes(payload[4..8].try_into().unwrap());
+    if version != REMOTE_PROTOCOL_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "remote protocol version miss match (expected {}, received {})",
+                REMOTE_PROTOCOL_VERSION, version
+            ),
+        ));
+    }
+
+    Ok(payload)
+}
+
+fn handshake_frame(nonce: &[u8; NONCE_SIZE], mac: Option<&[u8]>) -> Vec<u8> {
+    let mut payload = REMOTE_HANDSHAKE_MAGIC.to_vec();
+    payload.extend_from_slice(&REMOTE_PROTOCOL_VERSION.to_le_bytes());
+    payload.extend_from_slice(nonce);
+    if let Some(mac) = mac {
+        payload.extend_from_slice(mac);
+    }
+    payload
+}
+
+/// Authenticated, message based channel between a remote driver client and server.
+///
+/// Both sides prove the knowledge of the pre-shared key via a challenge response handshake.
+/// Afterwards every frame carries a MAC over its contents and sequence number
+/// (keyed with a per session key) so frames can neither be forged, replayed nor reordered.
+/// The frame contents are not encrypted.
+pub struct RemoteChannel<S> {
+    stream: S,
+    session_key: [u8; MAC_SIZE],
+
+    send_direction: u8,
+    send_sequence: u64,
+    receive_sequence: u64,
+}
+
+impl<S: Read + Write> RemoteChannel<S> {
+    /// Perform the client side of the handshake
+    pub fn connect(mut stream: S, key: &[u8]) -> io::Result<Self> {
+        let hello = self::read_handshake_frame(&mut stream, 8 + NONCE_SIZE)?;
+        let server_nonce = &hello[8..];
+        let client_nonce = self::create_nonce()?;
+
+        let client_mac = self::hmac(key, &[b"VTDR client", server_nonce, &client_nonce])
+            .finalize()
+            .into_bytes();
+        host::write_frame(
+            &mut stream,
+            &self::handshake_frame(&client_nonce, Some(&client_mac)),
+        )?;
+
+        let server_mac = host::read_frame_limited(&mut stream, HANDSHAKE_MAX_FRAME_SIZE)?
+            .ok_or_else(|| authentication_error("server rejected the key"))?;
+        self::hmac(key, &[b"VTDR server", &client_nonce, server_nonce])
+            .verify_slice(&server_mac)
+            .map_err(|_| authentication_error("server does not know the key"))?;
+
+        Ok(Self::new(
+            stream,
+            key,
+            server_nonce,
+            &client_nonce,
+            DIRECTION_CLIENT,
+        ))
+    }
+
+    /// Perform the server side of the handshake
+    pub fn accept(mut stream: S, key: &[u8]) -> io::Result<Self> {
+        let server_nonce = self::create_nonce()?;
+        host::write_frame(&mut stream, &self::handshake_frame(&server_nonce, None))?;
+
+        let response = self::read_handshake_frame(&mut stream, 8 + NONCE_SIZE + MAC_SIZE)?;
+        let client_nonce = &response[8..8 + NONCE_SIZE];
+        let client_mac = &response[8 + NONCE_SIZE..];
+        self::hmac(key, &[b"VTDR client", &server_nonce, client_nonce])
+            .verify_slice(client_mac)
+            .map_err(|_| authentication_error("client does not know the key"))?;
+
+        let server_mac = self::hmac(key, &[b"VTDR server", client_nonce, &server_nonce])
+            .finalize()
+            .into_bytes();
+        host::write_frame(&mut stream, &server_mac)?;
+
+        Ok(Self::new(
+            stream,
+            key,
+            &server_nonce,
+            client_nonce,
+            DIRECTION_SERVER,
+        ))
+    }
+
+    fn new(
+        stream: S,
+        key: &[u8],
+        server_nonce: &[u8],
+        client_nonce: &[u8],
+        send_direction: u8,
+    ) -> Self {
+        let session_key = self::hmac(key, &[b"VTDR session", server_nonce, client_nonce])
+            .finalize()
+            .into_bytes()
+            .into();
+
+        Self {
+            stream,
+            session_key,
+
+            send_direction,
+            send_sequence: 0,
+            receive_sequence: 0,
+        }
+    }
+
+    /// The underlying stream
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    fn frame_mac(&self, direction: u8, sequence: u64, payload: &[u8]) -> HmacSha256 {
+        self::hmac(
+            &self.session_key,
+            &[&[direction], &sequence.to_le_bytes(), payload],
+        )
+    }
+
+    pub fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        let mac = self
+            .frame_mac(self.send_direction, self.send_sequence, payload)
+            .finalize()
+            .into_bytes();
+        self.send_sequence += 1;
+
+        let mut frame = Vec::with_capacity(payload.len() + MAC_SIZE);
+        frame.extend_from_slice(payload);
+        frame.extend_from_slice(&mac);
+        host::write_frame(&mut self.stream, &frame)
+    }
+
+    /// Receive the next frame.
+    /// Returns `None` if the peer closed the connection.
+    pub fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some(mut frame) = host::read_frame(&mut self.stream)? else {
+            return Ok(None);
+        };
+
+        if frame.len() < MAC_SIZE {
+            return Err(authentication_error("frame too short"));
+        }
+
+        let mac = frame.split_off(frame.len() - MAC_SIZE);
+        self.frame_mac(self.send_direction ^ 0x01, self.receive_sequence, &frame)
+            .verify_slice(&mac)
+            .map_err(|_| authentication_error("invalid frame MAC"))?;
+
+        self.receive_sequence += 1;
+        Ok(Some(frame))
+    }
+}
@@ -0,0 +1,475 @@
+//! Forwarding of driver commands to a driver on a remote machine.
+//!
+//! The [RemoteDriver] captures every command (including the buffers referenced by it)
+//! and sends it to a remote driver server (`vtd-driver-server`) via TCP or a unix socket.
+//! The server executes the commands with its local driver and returns the responses.
+//!
+//! Client and server authenticate each other using a pre-shared key (see [RemoteChannel]).
+use std::{
+    fmt,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    net::{
+        TcpListener,
+        TcpStream,
+    },
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+
+use obfstr::obfstr;
+use vtd_protocol::command::{
+    DriverCommand,
+    DriverCommandInitialize,
+};
+
+use crate::{
+    host,
+    marshal::{
+        CommandRequest,
+        CommandResponse,
+    },
+    DriverBackend,
+    IResult,
+    InterfaceError,
+};
+
+mod channel;
+pub use channel::*;
+
+/// Version of the protocol spoken between the remote driver and the driver server
+pub const REMOTE_PROTOCOL_VERSION: u32 = 0x02;
+const REMOTE_HANDSHAKE_MAGIC: &[u8; 4] = b"VTDR";
+
+/// Address of a remote driver server.
+///
+/// Endpoints are written as `tcp:<host>:<port>` or `unix:<path>`.
+/// Endpoints without a scheme are treated as TCP endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteEndpoint {
+    Tcp(String),
+
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl FromStr for RemoteEndpoint {
+    type Err = InterfaceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(address) = value.strip_prefix("tcp:") {
+            return Ok(Self::Tcp(address.to_string()));
+        }
+
+        if let Some(path) = value.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Self::Unix(path.into()));
+
+            #[cfg(not(unix))]
+            return Err(InterfaceError::RemoteUnavailable {
+                message: format!("unix sockets are not supported ({})", path),
+            });
+        }
+
+        Ok(Self::Tcp(value.to_string()))
+    }
+}
+
+impl fmt::Display for RemoteEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "tcp:{}", address),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub enum RemoteStream {
+    Tcp(TcpStream),
+
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl RemoteStream {
+    pub fn connect(endpoint: &RemoteEndpoint) -> io::Result<Self> {
+        match endpoint {
+            RemoteEndpoint::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                stream.set_nodelay(true)?;
+                Ok(Self::Tcp(stream))
+            }
+            #[cfg(unix)]
+            RemoteEndpoint::Unix(path) => {
+                Ok(Self::Unix(std::os::unix::net::UnixStream::connect(path)?))
+            }
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for RemoteStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for RemoteStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+pub enum RemoteListener {
+    Tcp(TcpListener),
+
+    #[cfg(unix)]
+    Unix {
+        path: std::path::PathBuf,
+        inner: std::os::unix::net::UnixListener,
+    },
+}
+
+impl RemoteListener {
+    pub fn bind(endpoint: &RemoteEndpoint) -> io::Result<Self> {
+        match endpoint {
+            RemoteEndpoint::Tcp(address) => Ok(Self::Tcp(TcpListener::bind(address)?)),
+            #[cfg(unix)]
+            RemoteEndpoint::Unix(path) => {
+                let _ = std::fs::remove_file(path);
+                Ok(Self::Unix {
+                    inner: std::os::unix::net::UnixListener::bind(path)?,
+                    path: path.clone(),
+                })
+            }
+        }
+    }
+
+    /// The endpoint the listener is bound to (including the actual port for TCP listeners)
+    pub fn local_endpoint(&self) -> io::Result<RemoteEndpoint> {
+        match self {
+            Self::Tcp(listener) => Ok(RemoteEndpoint::Tcp(listener.local_addr()?.to_string())),
+            #[cfg(unix)]
+            Self::Unix { path, .. } => Ok(RemoteEndpoint::Unix(path.clone())),
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<RemoteStream> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, address) = listener.accept()?;
+                log::debug!("Accepted connection from {}", address);
+                stream.set_nodelay(true)?;
+                Ok(RemoteStream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Self::Unix { inner, .. } => {
+                let (stream, _) = inner.accept()?;
+                Ok(RemoteStream::Unix(stream))
+            }
+        }
+    }
+}
+
+impl Drop for RemoteListener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Self::Unix { path, .. } = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Read the remote endpoint (`VT_REMOTE_ENDPOINT`) and key (`VT_REMOTE_KEY`) from the environment
+pub fn config_from_env() -> IResult<(RemoteEndpoint, Vec<u8>)> {
+    let endpoint = std::env::var(obfstr!("VT_REMOTE_ENDPOINT")).map_err(|_| {
+        InterfaceError::RemoteUnavailable {
+            message: "VT_REMOTE_ENDPOINT has not been set".to_string(),
+        }
+    })?;
+
+    let key = std::env::var(obfstr!("VT_REMOTE_KEY")).map_err(|_| {
+        InterfaceError::RemoteAuthenticationFailed {
+            message: "VT_REMOTE_KEY has not been set".to_string(),
+        }
+    })?;
+
+    Ok((endpoint.parse()?, key.into_bytes()))
+}
+
+fn connection_error(err: io::Error) -> InterfaceError {
+    if err.kind() == io::ErrorKind::PermissionDenied {
+        InterfaceError::RemoteAuthenticationFailed {
+            message: err.to_string(),
+        }
+    } else {
+        InterfaceError::RemoteUnavailable {
+            message: err.to_string(),
+        }
+    }
+}
+
+struct RemoteState {
+    channel: Option<RemoteChannel<RemoteStream>>,
+
+    /// The initialize request used to re-initialize the driver after a reconnect
+    init_request: Option<CommandRequest>,
+}
+
+/// Driver backend forwarding all commands to a remote driver server.
+///
+/// If the connection gets lost, the next command reconnects and
+/// re-initializes the remote driver with the last initialize command.
+pub struct RemoteDriver {
+    endpoint: RemoteEndpoint,
+    key: Vec<u8>,
+
+    state: Mutex<RemoteState>,
+}
+
+impl RemoteDriver {
+    pub fn connect(endpoint: RemoteEndpoint, key: &[u8]) -> IResult<Self> {
+        let channel = Self::open_channel(&endpoint, key)?;
+        Ok(Self {
+            endpoint,
+            key: key.to_vec(),
+
+            state: Mutex::new(RemoteState {
+                channel: Some(channel),
+                init_request: None,
+            }),
+        })
+    }
+
+    pub fn endpoint(&self) -> &RemoteEndpoint {
+        &self.endpoint
+    }
+
+    fn open_channel(endpoint: &RemoteEndpoint, key: &[u8]) -> IResult<RemoteChannel<RemoteStream>> {
+        log::debug!("Connecting to remote driver at {}", endpoint);
+        let stream = RemoteStream::connect(endpoint).map_err(self::connection_error)?;
+        RemoteChannel::connect(stream, key).map_err(self::connection_error)
+    }
+
+    fn exchange(
+        channel: &mut RemoteChannel<RemoteStream>,
+        request: &CommandRequest,
+    ) -> io::Result<CommandResponse> {
+        channel.send(&request.encode())?;
+        let response = channel.receive()?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")
+        })?;
+
+        CommandResponse::decode(&response)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn reconnect(&self, state: &mut RemoteState) -> IResult<()> {
+        log::info!("Reconnecting to remote driver at {}", self.endpoint);
+        let mut channel = Self::open_channel(&self.endpoint, &self.key)?;
+        if let Some(init_request) = &state.init_request {
+            Self::exchange(&mut channel, init_request).map_err(|err| {
+                InterfaceError::RemoteUnavailable {
+                    message: format!("re-initialize: {}", err),
+                }
+            })?;
+        }
+
+        state.channel = Some(channel);
+        Ok(())
+    }
+
+    /// Execute a captured command request on the remote driver.
+    pub fn transact(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if state.channel.is_none() {
+            self.reconnect(&mut state)?;
+        }
+
+        let channel = state.channel.as_mut().unwrap();
+        let response = match Self::exchange(channel, request) {
+            Ok(response) => response,
+            Err(err) => {
+                log::error!("Remote driver connection failed: {}", err);
+                state.channel = None;
+                return Err(self::connection_error(err));
+            }
+        };
+
+        if request.command_id == DriverCommandInitialize::COMMAND_ID {
+            state.init_request = Some(request.clone());
+        }
+
+        Ok(response)
+    }
+}
+
+impl DriverBackend for RemoteDriver {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        let request =
+            CommandRequest::capture(command_id, payload, error_message.len()).map_err(|err| {
+                InterfaceError::CommandGenericError {
+                    message: format!("marshal: {}", err),
+                }
+            })?;
+
+        let response = self.transact(&request)?;
+        response
+            .apply(command_id, payload, error_message)
+            .map_err(|err| InterfaceError::CommandGenericError {
+                message: format!("unmarshal: {}", err),
+            })
+    }
+
+    fn execute_request(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        self.transact(request)
+    }
+}
+
+/// Authenticate a client connection and serve its commands until it disconnects.
+/// The client has to complete the handshake within [HANDSHAKE_TIMEOUT].
+pub fn serve_connection(
+    stream: RemoteStream,
+    key: &[u8],
+    backend: &dyn DriverBackend,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut channel = RemoteChannel::accept(stream, key)?;
+    channel.stream().set_read_timeout(None)?;
+    while let Some(request) = channel.receive()? {
+        let response = host::execute_encoded_request(backend, &request);
+        channel.send(&response.encode())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{
+            self,
+            Write,
+        },
+        sync::Arc,
+        thread,
+    };
+
+    use vtd_protocol::types::DirectoryTableType;
+
+    use super::{
+        RemoteDriver,
+        RemoteEndpoint,
+        RemoteListener,
+        RemoteStream,
+    };
+    use crate::{
+        mock::{
+            MockDriver,
+            MockProcess,
+        },
+        DriverInterface,
+        InterfaceError,
+    };
+
+    const KEY: &[u8] = b"correct horse battery staple";
+
+    fn spawn_server(endpoint: &RemoteEndpoint, connections: usize) -> RemoteEndpoint {
+        let listener = RemoteListener::bind(endpoint).unwrap();
+        let endpoint = listener.local_endpoint().unwrap();
+
+        let driver = Arc::new(MockDriver::new().with_process(
+            MockProcess::new(42, "cs2.exe").with_memory(0x1000, (0..0x10).collect()),
+        ));
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let stream = listener.accept().unwrap();
+                let _ = super::serve_connection(stream, KEY, driver.as_ref());
+            }
+        });
+
+        endpoint
+    }
+
+    fn check_endpoint(endpoint: RemoteEndpoint) {
+        let endpoint = self::spawn_server(&endpoint, 2);
+
+        let result = RemoteDriver::connect(endpoint.clone(), b"wrong key");
+        assert!(matches!(
+            result,
+            Err(InterfaceError::RemoteAuthenticationFailed { .. })
+        ));
+
+        let interface =
+            DriverInterface::with_backend(RemoteDriver::connect(endpoint, KEY).unwrap()).unwrap();
+        assert_eq!(interface.list_processes().unwrap()[0].process_id, 42);
+
+        let mut buffer = [0u8; 4];
+        interface
+            .read_slice(42, DirectoryTableType::Default, 0x1004, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn oversized_handshake() {
+        let listener = RemoteListener::bind(&"tcp:127.0.0.1:0".parse().unwrap()).unwrap();
+        let endpoint = listener.local_endpoint().unwrap();
+        let server = thread::spawn(move || {
+            let stream = listener.accept().unwrap();
+            super::serve_connection(stream, KEY, &MockDriver::new())
+        });
+
+        let mut stream = RemoteStream::connect(&endpoint).unwrap();
+        crate::host::read_frame(&mut stream).unwrap().unwrap();
+        stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+
+        let error = server.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tcp() {
+        self::check_endpoint("tcp:127.0.0.1:0".parse().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix() {
+        let path = std::env::temp_dir().join(format!("vtd-remote-{}.sock", std::process::id()));
+        self::check_endpoint(RemoteEndpoint::Unix(path));
+    }
+}
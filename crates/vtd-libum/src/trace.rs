@@ -0,0 +1,425 @@
+//! Recording and replaying of driver command traffic.
+//!
+//! A trace file contains every executed command including the contents of its input buffers
+//! and the complete response (including all output elements written by the driver).
+//! Traces are recorded by the [RecordingBackend] and can be served offline by the [ReplayBackend].
+use std::{
+    fs::File,
+    io::{
+        self,
+        BufReader,
+        BufWriter,
+        Read,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Mutex,
+};
+
+use crate::{
+    host,
+    marshal::{
+        CommandRequest,
+        CommandResponse,
+    },
+    DriverBackend,
+    IResult,
+    InterfaceError,
+};
+
+const TRACE_MAGIC: &[u8; 4] = b"VTDT";
+
+/// Version of the trace file format
+pub const TRACE_VERSION: u32 = 0x02;
+
+/// A single recorded command
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    /// The request including the contents of its input buffer
+    pub request: CommandRequest,
+    pub response: CommandResponse,
+}
+
+fn trace_error(message: String) -> InterfaceError {
+    InterfaceError::TraceInvalid { message }
+}
+
+pub struct TraceWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(TRACE_MAGIC)?;
+        inner.write_all(&TRACE_VERSION.to_le_bytes())?;
+        Ok(Self { inner })
+    }
+
+    pub fn write_entry(
+        &mut self,
+        request: &CommandRequest,
+        response: &CommandResponse,
+    ) -> io::Result<()> {
+        host::write_frame(&mut self.inner, &request.encode())?;
+        host::write_frame(&mut self.inner, &response.encode())
+    }
+}
+
+pub struct TraceReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut inner: R) -> IResult<Self> {
+        let mut header = [0u8; 8];
+        inner
+            .read_exact(&mut header)
+            .map_err(|err| trace_error(format!("read header: {}", err)))?;
+
+        if &header[0..4] != TRACE_MAGIC {
+            return Err(trace_error("invalid trace magic".to_string()));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != TRACE_VERSION {
+            return Err(trace_error(format!(
+                "unsupported trace version {} (expected {})",
+                version, TRACE_VERSION
+            )));
+        }
+
+        Ok(Self { inner })
+    }
+
+    /// Read the next entry.
+    /// Returns `None` at the end of the trace.
+    pub fn read_entry(&mut self) -> IResult<Option<TraceEntry>> {
+        let Some(request) = host::read_frame(&mut self.inner)
+            .map_err(|err| trace_error(format!("read request: {}", err)))?
+        else {
+            return Ok(None);
+        };
+
+        let response = host::read_frame(&mut self.inner)
+            .map_err(|err| trace_error(format!("read response: {}", err)))?
+            .ok_or_else(|| trace_error("missing response".to_string()))?;
+
+        Ok(Some(TraceEntry {
+            request: CommandRequest::decode(&request)
+                .map_err(|err| trace_error(format!("invalid request: {}", err)))?,
+            response: CommandResponse::decode(&response)
+                .map_err(|err| trace_error(format!("invalid response: {}", err)))?,
+        }))
+    }
+
+    pub fn read_all(mut self) -> IResult<Vec<TraceEntry>> {
+        let mut entries = Vec::new();
+        while let Some(entry) = self.read_entry()? {
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Backend recording all commands executed by the inner backend into a trace file.
+pub struct RecordingBackend<B> {
+    inner: B,
+    path: PathBuf,
+    writer: Mutex<TraceWriter<BufWriter<File>>>,
+}
+
+impl<B: DriverBackend> RecordingBackend<B> {
+    pub fn create(inner: B, path: &Path) -> IResult<Self> {
+        let file_error = |error| InterfaceError::DriverFileAccess {
+            path: path.to_owned(),
+            error,
+        };
+
+        let file = File::create(path).map_err(file_error)?;
+        let writer = TraceWriter::new(BufWriter::new(file)).map_err(file_error)?;
+        log::info!("Recording driver commands to {}", path.display());
+
+        Ok(Self {
+            inner,
+            path: path.to_owned(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn record(&self, request: &CommandRequest, response: &CommandResponse) {
+        let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+
+        /* flush every entry so the trace remains usable if the application crashes */
+        let result = writer
+            .write_entry(request, response)
+            .and_then(|_| writer.inner.flush());
+
+        if let Err(err) = result {
+            log::warn!(
+                "Failed to record command into {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+impl<B: DriverBackend> DriverBackend for RecordingBackend<B> {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        let request =
+            CommandRequest::capture(command_id, payload, error_message.len()).map_err(|err| {
+                InterfaceError::CommandGenericError {
+                    message: format!("marshal: {}", err),
+                }
+            })?;
+
+        let response = self.execute_request(&request)?;
+        response
+            .apply(command_id, payload, error_message)
+            .map_err(|err| InterfaceError::CommandGenericError {
+                message: format!("unmarshal: {}", err),
+            })
+    }
+
+    fn execute_request(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        let response = self.inner.execute_request(request)?;
+        self.record(request, &response);
+        Ok(response)
+    }
+}
+
+struct ReplayState {
+    entries: Vec<Option<TraceEntry>>,
+
+    /// Index of the next entry expected in strict mode
+    cursor: usize,
+}
+
+/// Backend serving the responses of a recorded trace.
+///
+/// In strict mode the commands must be executed in exactly the recorded order,
+/// otherwise [InterfaceError::ReplayDivergence] is returned.
+/// In lenient mode every command is served by the first unused entry with an identical request.
+pub struct ReplayBackend {
+    strict: bool,
+    state: Mutex<ReplayState>,
+}
+
+impl ReplayBackend {
+    pub fn new(entries: Vec<TraceEntry>, strict: bool) -> Self {
+        Self {
+            strict,
+            state: Mutex::new(ReplayState {
+                entries: entries.into_iter().map(Some).collect(),
+                cursor: 0,
+            }),
+        }
+    }
+
+    pub fn load(path: &Path, strict: bool) -> IResult<Self> {
+        let file = File::open(path).map_err(|error| InterfaceError::DriverFileAccess {
+            path: path.to_owned(),
+            error,
+        })?;
+
+        let entries = TraceReader::new(BufReader::new(file))?.read_all()?;
+        log::info!(
+            "Replaying {} driver commands from {}",
+            entries.len(),
+            path.display()
+        );
+        Ok(Self::new(entries, strict))
+    }
+
+    /// Number of recorded commands which have not yet been replayed
+    pub fn remaining(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    fn next_response(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        let index = if self.strict {
+            let index = state.cursor;
+            let Some(Some(entry)) = state.entries.get(index) else {
+                return Err(InterfaceError::ReplayDivergence {
+                    index,
+                    message: format!("trace exhausted (command {:X})", request.command_id),
+                });
+            };
+
+            if entry.request != *request {
+                return Err(InterfaceError::ReplayDivergence {
+                    index,
+                    message: format!(
+                        "expected {} but received {}",
+                        entry.request.describe(),
+                        request.describe()
+                    ),
+                });
+            }
+
+            index
+        } else {
+            state
+                .entries
+                .iter()
+                .position(|entry| {
+                    entry
+                        .as_ref()
+                        .map(|entry| entry.request == *request)
+                        .unwrap_or(false)
+                })
+                .ok_or_else(|| InterfaceError::ReplayDivergence {
+                    index: state.cursor,
+                    message: format!("no recorded response for command {:X}", request.command_id),
+                })?
+        };
+
+        state.cursor = index + 1;
+        Ok(state.entries[index].take().unwrap().response)
+    }
+}
+
+impl DriverBackend for ReplayBackend {
+    unsafe fn execute_command(
+        &self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> IResult<u64> {
+        let request =
+            CommandRequest::capture(command_id, payload, error_message.len()).map_err(|err| {
+                InterfaceError::CommandGenericError {
+                    message: format!("marshal: {}", err),
+                }
+            })?;
+
+        let response = self.next_response(&request)?;
+        response
+            .apply(command_id, payload, error_message)
+            .map_err(|err| InterfaceError::CommandGenericError {
+                message: format!("unmarshal: {}", err),
+            })
+    }
+
+    fn execute_request(&self, request: &CommandRequest) -> IResult<CommandResponse> {
+        self.next_response(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs::{
+            self,
+            File,
+        },
+    };
+
+    use vtd_protocol::types::DirectoryTableType;
+
+    use super::{
+        RecordingBackend,
+        ReplayBackend,
+        TraceReader,
+    };
+    use crate::{
+        mock::{
+            MockDriver,
+            MockProcess,
+        },
+        DriverInterface,
+        InterfaceError,
+    };
+
+    #[test]
+    fn record_and_replay() {
+        let path = env::temp_dir().join(format!("vtd-trace-{}.bin", std::process::id()));
+        let driver = MockDriver::new()
+            .with_process(MockProcess::new(42, "cs2.exe").with_memory(0x1000, (0..0x10).collect()));
+
+        {
+            let backend = RecordingBackend::create(driver, &path).unwrap();
+            let interface = DriverInterface::with_backend(backend).unwrap();
+
+            let mut buffer = [0u8; 4];
+            interface
+                .read_slice(42, DirectoryTableType::Default, 0x1004, &mut buffer)
+                .unwrap();
+            interface
+                .read_slice(42, DirectoryTableType::Default, 0x1008, &mut buffer)
+                .unwrap();
+        }
+
+        /* replay in the recorded order */
+        let interface =
+            DriverInterface::with_backend(ReplayBackend::load(&path, true).unwrap()).unwrap();
+        let mut buffer = [0u8; 4];
+        interface
+            .read_slice(42, DirectoryTableType::Default, 0x1004, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [4, 5, 6, 7]);
+        assert!(matches!(
+            interface.read_slice(42, DirectoryTableType::Default, 0x100C, &mut buffer),
+            Err(InterfaceError::ReplayDivergence { index: 2, .. })
+        ));
+
+        /* lenient mode serves out of order requests */
+        let interface =
+            DriverInterface::with_backend(ReplayBackend::load(&path, false).unwrap()).unwrap();
+        interface
+            .read_slice(42, DirectoryTableType::Default, 0x1008, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [8, 9, 10, 11]);
+        interface
+            .read_slice(42, DirectoryTableType::Default, 0x1004, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [4, 5, 6, 7]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn compact_output() {
+        let path = env::temp_dir().join(format!("vtd-trace-compact-{}.bin", std::process::id()));
+        let driver = MockDriver::new().with_process(MockProcess::new(42, "cs2.exe"));
+
+        {
+            let backend = RecordingBackend::create(driver, &path).unwrap();
+            let interface = DriverInterface::with_backend(backend).unwrap();
+            assert_eq!(interface.list_processes().unwrap().len(), 1);
+        }
+
+        /* only the single process entry should be recorded, not the whole output buffer */
+        let entries = TraceReader::new(File::open(&path).unwrap())
+            .unwrap()
+            .read_all()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[1].response.message.len() < 0x100);
+
+        let interface =
+            DriverInterface::with_backend(ReplayBackend::load(&path, true).unwrap()).unwrap();
+        let processes = interface.list_processes().unwrap();
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].get_image_base_name(), Some("cs2.exe"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
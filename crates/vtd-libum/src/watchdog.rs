@@ -0,0 +1,262 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        mpsc::{
+            self,
+            RecvTimeoutError,
+        },
+        Arc,
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use vtd_protocol::command::DriverCommand;
+
+use crate::{
+    marshal::{
+        CommandRequest,
+        CommandResponse,
+    },
+    IResult,
+    InterfaceError,
+};
+
+/// Deadlines for driver commands.
+///
+/// Commands without a deadline are executed on the calling thread.
+/// Commands with a deadline are executed on a dedicated worker thread and
+/// fail with [InterfaceError::Timeout] once the deadline has been exceeded.
+#[derive(Debug, Default, Clone)]
+pub struct WatchdogConfig {
+    default_timeout: Option<Duration>,
+    command_timeouts: BTreeMap<u32, Duration>,
+}
+
+impl WatchdogConfig {
+    /// Deadline for all commands which do not have a specific deadline
+    pub fn with_default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Deadline for a specific command
+    pub fn with_command_timeout<C: DriverCommand>(mut self, timeout: Duration) -> Self {
+        self.command_timeouts.insert(C::COMMAND_ID, timeout);
+        self
+    }
+
+    pub fn timeout_for(&self, command_id: u32) -> Option<Duration> {
+        self.command_timeouts
+            .get(&command_id)
+            .copied()
+            .or(self.default_timeout)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.default_timeout.is_some() || !self.command_timeouts.is_empty()
+    }
+}
+
+pub(crate) type RequestExecutor =
+    Arc<dyn Fn(&CommandRequest) -> IResult<CommandResponse> + Send + Sync>;
+
+struct WatchdogJob {
+    sequence: u64,
+    request: CommandRequest,
+    response: mpsc::Sender<IResult<CommandResponse>>,
+}
+
+#[derive(Default)]
+struct WatchdogState {
+    next_sequence: AtomicU64,
+
+    /// Sequence of the last job the worker has finished
+    completed_sequence: AtomicU64,
+
+    /// Sequence of the last job which exceeded its deadline
+    expired_sequence: AtomicU64,
+}
+
+/// Executes driver commands on a worker thread and enforces their deadlines.
+///
+/// Once a command exceeded its deadline the driver is considered unhealthy
+/// and all further commands will fail immediately until the worker
+/// finished the hung command.
+pub(crate) struct Watchdog {
+    config: WatchdogConfig,
+    state: Arc<WatchdogState>,
+    jobs: Mutex<mpsc::Sender<WatchdogJob>>,
+}
+
+impl Watchdog {
+    pub fn new(config: WatchdogConfig, executor: RequestExecutor) -> IResult<Self> {
+        let state = Arc::new(WatchdogState::default());
+        let (jobs, job_receiver) = mpsc::channel::<WatchdogJob>();
+
+        {
+            let state = state.clone();
+            thread::Builder::new()
+                .name("vtd-watchdog".to_string())
+                .spawn(move || {
+                    /* the channel closes when the watchdog gets dropped */
+                    while let Ok(job) = job_receiver.recv() {
+                        let response = executor(&job.request);
+                        state
+                            .completed_sequence
+                            .store(job.sequence, Ordering::Release);
+
+                        /* the caller might already have given up */
+                        let _ = job.response.send(response);
+                    }
+                })
+                .map_err(|err| InterfaceError::CommandGenericError {
+                    message: format!("spawn watchdog worker: {}", err),
+                })?;
+        }
+
+        Ok(Self {
+            config,
+            state,
+            jobs: Mutex::new(jobs),
+        })
+    }
+
+    pub fn config(&self) -> &WatchdogConfig {
+        &self.config
+    }
+
+    /// Returns false if a command exceeded its deadline and has not yet completed
+    pub fn is_healthy(&self) -> bool {
+        self.state.completed_sequence.load(Ordering::Acquire)
+            >= self.state.expired_sequence.load(Ordering::Acquire)
+    }
+
+    pub fn execute(&self, request: CommandRequest, timeout: Duration) -> IResult<CommandResponse> {
+        if !self.is_healthy() {
+            return Err(InterfaceError::DriverUnhealthy);
+        }
+
+        let command_id = request.command_id;
+        let sequence = self.state.next_sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        let (response_sender, response) = mpsc::channel();
+        {
+            let jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+            jobs.send(WatchdogJob {
+                sequence,
+                request,
+                response: response_sender,
+            })
+            .map_err(|_| InterfaceError::DriverUnhealthy)?;
+        }
+
+        match response.recv_timeout(timeout) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => {
+                log::warn!(
+                    "Driver command {:X} exceeded its deadline of {:?}. Marking driver as unhealthy.",
+                    command_id,
+                    timeout
+                );
+                self.state
+                    .expired_sequence
+                    .fetch_max(sequence, Ordering::AcqRel);
+
+                Err(InterfaceError::Timeout {
+                    command_id,
+                    timeout,
+                })
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(InterfaceError::DriverUnhealthy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            mpsc,
+            Arc,
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use super::{
+        Watchdog,
+        WatchdogConfig,
+    };
+    use crate::{
+        marshal::{
+            CommandRequest,
+            CommandResponse,
+        },
+        InterfaceError,
+    };
+
+    fn request() -> CommandRequest {
+        CommandRequest {
+            command_id: 0xFFFF,
+            error_capacity: 0,
+            message: vec![0; 8],
+        }
+    }
+
+    #[test]
+    fn timeout_and_recovery() {
+        let (release, blocker) = mpsc::channel::<()>();
+        let blocker = Mutex::new(blocker);
+        let watchdog = Watchdog::new(
+            WatchdogConfig::default().with_default_timeout(Duration::from_millis(50)),
+            Arc::new(move |request| {
+                if request.message[0] == 1 {
+                    let _ = blocker.lock().unwrap().recv();
+                }
+
+                Ok(CommandResponse {
+                    status: 1,
+                    error_message: Vec::new(),
+                    message: request.message.clone(),
+                })
+            }),
+        )
+        .unwrap();
+
+        assert!(watchdog
+            .execute(self::request(), Duration::from_millis(500))
+            .is_ok());
+
+        let mut hanging = self::request();
+        hanging.message[0] = 1;
+        assert!(matches!(
+            watchdog.execute(hanging, Duration::from_millis(50)),
+            Err(InterfaceError::Timeout { .. })
+        ));
+        assert!(!watchdog.is_healthy());
+        assert!(matches!(
+            watchdog.execute(self::request(), Duration::from_millis(50)),
+            Err(InterfaceError::DriverUnhealthy)
+        ));
+
+        /* let the hung command complete */
+        release.send(()).unwrap();
+        for _ in 0..100 {
+            if watchdog.is_healthy() {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(watchdog.is_healthy());
+        assert!(watchdog
+            .execute(self::request(), Duration::from_millis(500))
+            .is_ok());
+    }
+}
@@ -0,0 +1,897 @@
+use core::{
+    fmt,
+    mem,
+    ptr,
+    slice,
+};
+
+use super::{
+    impl_codec_struct,
+    Codec,
+    CodecError,
+    CodecResult,
+    Decoder,
+    Encoder,
+    MAX_BUFFER_SIZE,
+};
+use crate::{
+    command::{
+        DriverCommand,
+        DriverCommandCr3ShenanigansDisable,
+        DriverCommandCr3ShenanigansEnable,
+        DriverCommandInitialize,
+        DriverCommandInputKeyboard,
+        DriverCommandInputMouse,
+        DriverCommandMemoryRead,
+        DriverCommandMemoryWrite,
+        DriverCommandMetricsFlush,
+        DriverCommandMetricsReportSend,
+        DriverCommandProcessList,
+        DriverCommandProcessModules,
+        DriverCommandProcessProtection,
+        KeyboardState,
+        MouseState,
+    },
+    types::{
+        MemoryAccessResult,
+        ProcessInfo,
+        ProcessModuleInfo,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferDirection {
+    /// The buffer is read by the driver
+    In,
+
+    /// The buffer is written by the driver
+    Out,
+}
+
+/// A command which can be encoded including the buffer it references.
+pub trait CommandCodec: DriverCommand + Codec + fmt::Debug {
+    /// Identifies the command type within encoded messages.
+    /// Equal to the command id unless multiple commands share the same id.
+    const MESSAGE_ID: u32 = Self::COMMAND_ID;
+
+    /// Direction of the referenced buffer or `None` if the command does not reference a buffer
+    const BUFFER: Option<BufferDirection>;
+
+    /// Name of the field referencing the buffer
+    const BUFFER_FIELD: &'static str = "buffer";
+
+    /// Element type of the referenced buffer
+    type Element: Codec + Default + Copy;
+
+    /// The referenced buffer and its element count
+    fn buffer(&self) -> (*mut Self::Element, usize);
+
+    fn set_buffer(&mut self, buffer: *mut Self::Element, count: usize);
+
+    /// Number of elements at the start of the output buffer which have been written by the driver.
+    /// Only these elements are transferred within a response.
+    fn output_count(&self) -> usize {
+        self.buffer().1
+    }
+}
+
+macro_rules! impl_command_codec {
+    ($command:ty) => {
+        impl CommandCodec for $command {
+            const BUFFER: Option<BufferDirection> = None;
+            type Element = u8;
+
+            fn buffer(&self) -> (*mut u8, usize) {
+                (ptr::null_mut(), 0)
+            }
+
+            fn set_buffer(&mut self, _buffer: *mut u8, _count: usize) {}
+        }
+    };
+    ($command:ty, $pointer:ident, $count:ident, $element:ty, $direction:ident) => {
+        impl CommandCodec for $command {
+            const BUFFER: Option<BufferDirection> = Some(BufferDirection::$direction);
+            const BUFFER_FIELD: &'static str = stringify!($pointer);
+            type Element = $element;
+
+            fn buffer(&self) -> (*mut $element, usize) {
+                (self.$pointer as *mut $element, self.$count)
+            }
+
+            fn set_buffer(&mut self, buffer: *mut $element, count: usize) {
+                self.$pointer = buffer as _;
+                self.$count = count;
+            }
+        }
+    };
+    ($command:ty, $pointer:ident, $count:ident, $element:ty, Out, $output_count:expr) => {
+        impl CommandCodec for $command {
+            const BUFFER: Option<BufferDirection> = Some(BufferDirection::Out);
+            const BUFFER_FIELD: &'static str = stringify!($pointer);
+            type Element = $element;
+
+            fn buffer(&self) -> (*mut $element, usize) {
+                (self.$pointer as *mut $element, self.$count)
+            }
+
+            fn set_buffer(&mut self, buffer: *mut $element, count: usize) {
+                self.$pointer = buffer as _;
+                self.$count = count;
+            }
+
+            fn output_count(&self) -> usize {
+                let output_count: fn(&$command) -> usize = $output_count;
+                output_count(self).min(self.$count)
+            }
+        }
+    };
+}
+
+impl_codec_struct!(DriverCommandInitialize {
+    client_protocol_version,
+    driver_protocol_version,
+    result,
+    client_version,
+    driver_version,
+    driver_features,
+});
+impl_command_codec!(DriverCommandInitialize);
+
+impl_codec_struct!(DriverCommandProcessList {
+    buffer_capacity,
+    process_count,
+});
+impl_command_codec!(
+    DriverCommandProcessList,
+    buffer,
+    buffer_capacity,
+    ProcessInfo,
+    Out,
+    |command| command.process_count
+);
+
+impl_codec_struct!(DriverCommandProcessModules {
+    process_id,
+    directory_table_type,
+    buffer_capacity,
+    module_count,
+    process_unknown,
+});
+impl_command_codec!(
+    DriverCommandProcessModules,
+    buffer,
+    buffer_capacity,
+    ProcessModuleInfo,
+    Out,
+    |command| command.module_count
+);
+
+impl_codec_struct!(DriverCommandMemoryRead {
+    process_id,
+    directory_table_type,
+    address,
+    count,
+    result,
+});
+impl_command_codec!(
+    DriverCommandMemoryRead,
+    buffer,
+    count,
+    u8,
+    Out,
+    |command| match command.result {
+        MemoryAccessResult::Success => command.count,
+        MemoryAccessResult::PartialSuccess { bytes_copied } => bytes_copied,
+        _ => 0,
+    }
+);
+
+impl_codec_struct!(DriverCommandMemoryWrite {
+    process_id,
+    directory_table_type,
+    address,
+    count,
+    result,
+});
+impl_command_codec!(DriverCommandMemoryWrite, buffer, count, u8, In);
+
+impl_codec_struct!(DriverCommandInputKeyboard { state_count });
+impl_command_codec!(
+    DriverCommandInputKeyboard,
+    buffer,
+    state_count,
+    KeyboardState,
+    In
+);
+
+impl_codec_struct!(DriverCommandInputMouse { state_count });
+impl_command_codec!(DriverCommandInputMouse, buffer, state_count, MouseState, In);
+
+impl_codec_struct!(DriverCommandMetricsReportSend {
+    report_type,
+    report_payload_length,
+});
+impl_command_codec!(
+    DriverCommandMetricsReportSend,
+    report_payload,
+    report_payload_length,
+    u8,
+    In
+);
+
+impl_codec_struct!(DriverCommandProcessProtection { mode });
+impl_command_codec!(DriverCommandProcessProtection);
+
+impl_codec_struct!(DriverCommandMetricsFlush {
+    blocking,
+    queue_remaining,
+});
+impl_command_codec!(DriverCommandMetricsFlush);
+
+impl_codec_struct!(DriverCommandCr3ShenanigansEnable {
+    mitigation_strategy,
+    mitigation_flags,
+    success,
+});
+impl CommandCodec for DriverCommandCr3ShenanigansEnable {
+    /* shares the command id with the metrics flush command */
+    const MESSAGE_ID: u32 = 0x100 | Self::COMMAND_ID;
+
+    const BUFFER: Option<BufferDirection> = None;
+    type Element = u8;
+
+    fn buffer(&self) -> (*mut u8, usize) {
+        (ptr::null_mut(), 0)
+    }
+
+    fn set_buffer(&mut self, _buffer: *mut u8, _count: usize) {}
+}
+
+impl_codec_struct!(DriverCommandCr3ShenanigansDisable {});
+impl_command_codec!(DriverCommandCr3ShenanigansDisable);
+
+fn check_buffer_size<T>(count: usize) -> CodecResult<()> {
+    let length = count
+        .checked_mul(mem::size_of::<T>())
+        .ok_or(CodecError::BufferTooLarge(usize::MAX))?;
+
+    if length > MAX_BUFFER_SIZE {
+        return Err(CodecError::BufferTooLarge(length));
+    }
+
+    Ok(())
+}
+
+fn decode_header<C: CommandCodec>(decoder: &mut Decoder) -> CodecResult<()> {
+    let message_id = u32::decode(decoder)?;
+    if message_id != C::MESSAGE_ID {
+        return Err(CodecError::CommandMissmatch {
+            expected: C::MESSAGE_ID,
+            received: message_id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns the [CommandCodec::MESSAGE_ID] of an encoded request or response
+pub fn message_id(message: &[u8]) -> CodecResult<u32> {
+    u32::decode(&mut Decoder::new(message))
+}
+
+/// Encode the command fields and the contents of its input buffer.
+///
+/// # Safety
+/// The buffer referenced by the command must be valid.
+pub unsafe fn encode_request<C: CommandCodec>(
+    command: &C,
+    encoder: &mut impl Encoder,
+) -> CodecResult<()> {
+    C::MESSAGE_ID.encode(encoder)?;
+    command.encode(encoder)?;
+
+    if C::BUFFER == Some(BufferDirection::In) {
+        let (buffer, count) = command.buffer();
+        self::check_buffer_size::<C::Element>(count)?;
+        if count > 0 {
+            if buffer.is_null() {
+                return Err(CodecError::InvalidValue("buffer pointer"));
+            }
+
+            C::Element::encode_slice(slice::from_raw_parts(buffer, count), encoder)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode the command fields and the elements of its output buffer written by the driver
+/// (see [CommandCodec::output_count]).
+///
+/// # Safety
+/// The buffer referenced by the command must be valid.
+pub unsafe fn encode_response<C: CommandCodec>(
+    command: &C,
+    encoder: &mut impl Encoder,
+) -> CodecResult<()> {
+    C::MESSAGE_ID.encode(encoder)?;
+    command.encode(encoder)?;
+
+    if C::BUFFER == Some(BufferDirection::Out) {
+        let (buffer, _) = command.buffer();
+        let count = if buffer.is_null() {
+            0
+        } else {
+            command.output_count()
+        };
+        self::check_buffer_size::<C::Element>(count)?;
+
+        count.encode(encoder)?;
+        if count > 0 {
+            C::Element::encode_slice(slice::from_raw_parts(buffer, count), encoder)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode a response and apply it to the original command.
+/// The transferred output elements are copied into the buffer referenced by the command,
+/// the remaining elements, the buffer pointer and size of the command remain unchanged.
+///
+/// # Safety
+/// The buffer referenced by the command must be valid.
+pub unsafe fn decode_response_into<C: CommandCodec>(
+    command: &mut C,
+    message: &[u8],
+) -> CodecResult<()> {
+    let mut decoder = Decoder::new(message);
+    self::decode_header::<C>(&mut decoder)?;
+    let mut response = C::decode(&mut decoder)?;
+
+    let (buffer, count) = command.buffer();
+    if C::BUFFER == Some(BufferDirection::Out) {
+        let response_count = usize::decode(&mut decoder)?;
+        self::check_buffer_size::<C::Element>(response_count)?;
+
+        let copy_count = if buffer.is_null() {
+            0
+        } else {
+            response_count.min(count)
+        };
+        if copy_count > 0 {
+            C::Element::decode_slice(slice::from_raw_parts_mut(buffer, copy_count), &mut decoder)?;
+        }
+
+        /* skip the elements which do not fit into our buffer */
+        for _ in copy_count..response_count {
+            C::Element::decode(&mut decoder)?;
+        }
+    }
+    decoder.finish()?;
+
+    response.set_buffer(buffer, count);
+    *command = response;
+    Ok(())
+}
+
+/// A decoded command owning the buffer it references.
+#[cfg(feature = "alloc")]
+pub struct OwnedCommand<C: CommandCodec> {
+    command: C,
+    buffer: alloc::vec::Vec<C::Element>,
+}
+
+#[cfg(feature = "alloc")]
+impl<C: CommandCodec> OwnedCommand<C> {
+    pub fn command(&self) -> &C {
+        &self.command
+    }
+
+    /// The command with its buffer pointer referencing the owned buffer
+    pub fn command_mut(&mut self) -> &mut C {
+        &mut self.command
+    }
+
+    pub fn buffer(&self) -> &[C::Element] {
+        &self.buffer
+    }
+
+    /// Encode the response for the executed command
+    pub fn encode_response(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        unsafe { self::encode_response(&self.command, encoder) }
+    }
+}
+
+/// Decode a request and allocate the buffer referenced by the command.
+/// Input buffers are initialized with the transferred contents, output buffers with default values.
+#[cfg(feature = "alloc")]
+pub fn decode_request<C: CommandCodec>(message: &[u8]) -> CodecResult<OwnedCommand<C>> {
+    let mut decoder = Decoder::new(message);
+    self::decode_header::<C>(&mut decoder)?;
+    let mut command = C::decode(&mut decoder)?;
+
+    let mut buffer = alloc::vec::Vec::new();
+    if let Some(direction) = C::BUFFER {
+        let (_, count) = command.buffer();
+        self::check_buffer_size::<C::Element>(count)?;
+
+        buffer.resize(count, C::Element::default());
+        if direction == BufferDirection::In {
+            C::Element::decode_slice(&mut buffer, &mut decoder)?;
+        }
+
+        let pointer = if buffer.is_empty() {
+            ptr::null_mut()
+        } else {
+            buffer.as_mut_ptr()
+        };
+        command.set_buffer(pointer, count);
+    }
+    decoder.finish()?;
+
+    Ok(OwnedCommand { command, buffer })
+}
+
+/// Generic operation on a command type (see [visit_command])
+pub trait CommandVisitor {
+    type Output;
+
+    fn visit<C: CommandCodec>(self) -> Self::Output;
+}
+
+/// Invoke the visitor with the command type identified by the command id and
+/// the size of the in-memory command struct.
+/// Returns `None` if the command is unknown.
+pub fn visit_command<V: CommandVisitor>(
+    command_id: u32,
+    command_size: usize,
+    visitor: V,
+) -> Option<V::Output> {
+    if command_id == DriverCommandCr3ShenanigansEnable::COMMAND_ID
+        && command_size == mem::size_of::<DriverCommandCr3ShenanigansEnable>()
+    {
+        return Some(visitor.visit::<DriverCommandCr3ShenanigansEnable>());
+    }
+
+    self::visit_message(command_id, visitor)
+}
+
+/// Invoke the visitor with the command type identified by the [CommandCodec::MESSAGE_ID].
+/// Returns `None` if the command is unknown.
+pub fn visit_message<V: CommandVisitor>(message_id: u32, visitor: V) -> Option<V::Output> {
+    macro_rules! dispatch {
+        ($($command:ty),*) => {
+            $(
+                if message_id == <$command as CommandCodec>::MESSAGE_ID {
+                    return Some(visitor.visit::<$command>());
+                }
+            )*
+        };
+    }
+
+    dispatch!(
+        DriverCommandInitialize,
+        DriverCommandProcessList,
+        DriverCommandProcessModules,
+        DriverCommandMemoryRead,
+        DriverCommandMemoryWrite,
+        DriverCommandInputKeyboard,
+        DriverCommandInputMouse,
+        DriverCommandMetricsReportSend,
+        DriverCommandProcessProtection,
+        DriverCommandMetricsFlush,
+        DriverCommandCr3ShenanigansEnable,
+        DriverCommandCr3ShenanigansDisable
+    );
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use core::fmt::Debug;
+    use std::{
+        format,
+        vec,
+        vec::Vec,
+    };
+
+    use proptest::{
+        collection::vec as vec_of,
+        prelude::*,
+    };
+
+    use super::{
+        decode_request,
+        decode_response_into,
+        encode_request,
+        BufferDirection,
+        CommandCodec,
+    };
+    use crate::{
+        codec::{
+            message_id,
+            visit_command,
+            CodecError,
+            CommandVisitor,
+        },
+        command::*,
+        types::{
+            DirectoryTableType,
+            DriverFeature,
+            MemoryAccessResult,
+            ProcessInfo,
+            ProcessModuleInfo,
+        },
+    };
+
+    fn directory_table_type() -> impl Strategy<Value = DirectoryTableType> {
+        prop_oneof![
+            Just(DirectoryTableType::Default),
+            any::<u64>().prop_map(|directory_table_base| DirectoryTableType::Explicit {
+                directory_table_base
+            }),
+            Just(DirectoryTableType::Cr3Shenanigans),
+        ]
+    }
+
+    fn memory_access_result() -> impl Strategy<Value = MemoryAccessResult> {
+        prop_oneof![
+            Just(MemoryAccessResult::Success),
+            any::<usize>()
+                .prop_map(|bytes_copied| MemoryAccessResult::PartialSuccess { bytes_copied }),
+            Just(MemoryAccessResult::ProcessUnknown),
+            Just(MemoryAccessResult::SourcePagedOut),
+            Just(MemoryAccessResult::DestinationPagedOut),
+        ]
+    }
+
+    fn version_info() -> impl Strategy<Value = VersionInfo> {
+        (any::<[u8; 0x20]>(), any::<(u32, u32, u32)>()).prop_map(|(name, version)| VersionInfo {
+            application_name: name,
+            version_major: version.0,
+            version_minor: version.1,
+            version_patch: version.2,
+        })
+    }
+
+    fn process_info() -> impl Strategy<Value = ProcessInfo> {
+        (any::<u32>(), any::<[u8; 0x0F]>(), any::<u64>()).prop_map(
+            |(process_id, image_base_name, directory_table_base)| ProcessInfo {
+                process_id,
+                image_base_name,
+                directory_table_base,
+            },
+        )
+    }
+
+    fn process_module_info() -> impl Strategy<Value = ProcessModuleInfo> {
+        (vec_of(any::<u8>(), 0x100), any::<u64>(), any::<u64>()).prop_map(
+            |(name, base_address, module_size)| ProcessModuleInfo {
+                base_dll_name: name.try_into().unwrap(),
+                base_address,
+                module_size,
+            },
+        )
+    }
+
+    fn keyboard_state() -> impl Strategy<Value = KeyboardState> {
+        (any::<u16>(), any::<bool>())
+            .prop_map(|(scane_code, down)| KeyboardState { scane_code, down })
+    }
+
+    fn mouse_state() -> impl Strategy<Value = MouseState> {
+        (
+            any::<[Option<bool>; 5]>(),
+            any::<(bool, bool)>(),
+            any::<(i32, i32)>(),
+        )
+            .prop_map(|(buttons, wheel, last)| MouseState {
+                buttons,
+                hwheel: wheel.0,
+                wheel: wheel.1,
+                last_x: last.0,
+                last_y: last.1,
+            })
+    }
+
+    /// Debug representation of the command without its buffer pointer
+    fn describe<C: CommandCodec>(command: &C) -> std::string::String {
+        let mut command = *command;
+        let (_, count) = command.buffer();
+        command.set_buffer(core::ptr::null_mut(), count);
+        format!("{:?}", command)
+    }
+
+    /// Encode the request, decode and execute it with the given output, apply the response
+    /// and verify all fields and buffers have been transferred.
+    fn round_trip<C: CommandCodec>(
+        mut command: C,
+        mut buffer: Vec<C::Element>,
+        output: C,
+        output_buffer: Vec<C::Element>,
+    ) where
+        C::Element: Debug,
+    {
+        command.set_buffer(
+            if buffer.is_empty() {
+                core::ptr::null_mut()
+            } else {
+                buffer.as_mut_ptr()
+            },
+            buffer.len(),
+        );
+
+        let mut request = Vec::new();
+        unsafe { encode_request(&command, &mut request) }.unwrap();
+        assert_eq!(message_id(&request).unwrap(), C::MESSAGE_ID);
+
+        let mut decoded = decode_request::<C>(&request).unwrap();
+        assert_eq!(describe(decoded.command()), describe(&command));
+        assert_eq!(decoded.buffer().len(), buffer.len());
+        if C::BUFFER == Some(BufferDirection::In) {
+            assert_eq!(format!("{:?}", decoded.buffer()), format!("{:?}", buffer));
+        }
+
+        /* execute the command */
+        let (pointer, count) = decoded.command().buffer();
+        *decoded.command_mut() = output;
+        decoded.command_mut().set_buffer(pointer, count);
+        if C::BUFFER == Some(BufferDirection::Out) {
+            for (index, value) in output_buffer.iter().take(count).enumerate() {
+                unsafe { pointer.add(index).write(*value) };
+            }
+        }
+
+        let mut response = Vec::new();
+        decoded.encode_response(&mut response).unwrap();
+
+        let original_buffer = command.buffer();
+        unsafe { decode_response_into(&mut command, &response) }.unwrap();
+        assert_eq!(command.buffer(), original_buffer);
+        assert_eq!(describe(&command), describe(decoded.command()));
+        if C::BUFFER == Some(BufferDirection::Out) {
+            let output_count = decoded.command().output_count();
+            assert_eq!(
+                format!("{:?}", &buffer[..output_count]),
+                format!("{:?}", &decoded.buffer()[..output_count])
+            );
+
+            /* elements not written by the driver are not transferred */
+            let untouched = vec![C::Element::default(); buffer.len() - output_count];
+            assert_eq!(
+                format!("{:?}", &buffer[output_count..]),
+                format!("{:?}", untouched)
+            );
+        }
+
+        /* truncated messages must be rejected */
+        for length in [0, request.len() / 2, request.len().saturating_sub(1)] {
+            if length < request.len() {
+                assert!(decode_request::<C>(&request[..length]).is_err());
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn initialize(
+            input in (any::<u32>(), version_info()),
+            output in (any::<u32>(), any::<bool>(), version_info(), any::<u64>()),
+        ) {
+            let command = DriverCommandInitialize {
+                client_protocol_version: input.0,
+                client_version: input.1,
+                ..Default::default()
+            };
+            let output = DriverCommandInitialize {
+                client_protocol_version: input.0,
+                driver_protocol_version: output.0,
+                result: if output.1 { InitializeResult::Success } else { InitializeResult::Unavailable },
+                client_version: input.1,
+                driver_version: output.2,
+                driver_features: DriverFeature::from_bits_retain(output.3),
+            };
+            round_trip(command, Vec::new(), output, Vec::new());
+        }
+
+        #[test]
+        fn process_list(
+            capacity in 0usize..8,
+            process_count in any::<usize>(),
+            output in vec_of(process_info(), 8),
+        ) {
+            let output_command = DriverCommandProcessList {
+                buffer_capacity: capacity,
+                process_count,
+                ..Default::default()
+            };
+            round_trip(
+                DriverCommandProcessList::default(),
+                vec![ProcessInfo::default(); capacity],
+                output_command,
+                output,
+            );
+        }
+
+        #[test]
+        fn process_modules(
+            input in (any::<u32>(), directory_table_type(), 0usize..4),
+            output in (any::<usize>(), any::<bool>(), vec_of(process_module_info(), 4)),
+        ) {
+            let command = DriverCommandProcessModules {
+                process_id: input.0,
+                directory_table_type: input.1,
+                ..Default::default()
+            };
+            let output_command = DriverCommandProcessModules {
+                module_count: output.0,
+                process_unknown: output.1,
+                ..command
+            };
+            round_trip(command, vec![ProcessModuleInfo::default(); input.2], output_command, output.2);
+        }
+
+        #[test]
+        fn memory_read(
+            input in (any::<u32>(), directory_table_type(), any::<u64>(), 0usize..0x100),
+            result in memory_access_result(),
+            output in vec_of(any::<u8>(), 0x100),
+        ) {
+            let command = DriverCommandMemoryRead {
+                process_id: input.0,
+                directory_table_type: input.1,
+                address: input.2,
+                ..Default::default()
+            };
+            let output_command = DriverCommandMemoryRead { result, ..command };
+            round_trip(command, vec![0; input.3], output_command, output);
+        }
+
+        #[test]
+        fn memory_write(
+            input in (any::<u32>(), directory_table_type(), any::<u64>(), vec_of(any::<u8>(), 0..0x100)),
+            result in memory_access_result(),
+        ) {
+            let command = DriverCommandMemoryWrite {
+                process_id: input.0,
+                directory_table_type: input.1,
+                address: input.2,
+                ..Default::default()
+            };
+            let output_command = DriverCommandMemoryWrite { result, ..command };
+            round_trip(command, input.3, output_command, Vec::new());
+        }
+
+        #[test]
+        fn input_keyboard(states in vec_of(keyboard_state(), 0..0x10)) {
+            round_trip(
+                DriverCommandInputKeyboard::default(),
+                states,
+                DriverCommandInputKeyboard::default(),
+                Vec::new(),
+            );
+        }
+
+        #[test]
+        fn input_mouse(states in vec_of(mouse_state(), 0..0x10)) {
+            round_trip(
+                DriverCommandInputMouse::default(),
+                states,
+                DriverCommandInputMouse::default(),
+                Vec::new(),
+            );
+        }
+
+        #[test]
+        fn metrics_report_send(
+            report_type in vec_of(any::<u8>(), 0x100),
+            payload in vec_of(any::<u8>(), 0..0x100),
+        ) {
+            let command = DriverCommandMetricsReportSend {
+                report_type: report_type.try_into().unwrap(),
+                ..Default::default()
+            };
+            round_trip(command, payload, command, Vec::new());
+        }
+
+        #[test]
+        fn process_protection(mode in 0u8..3) {
+            let command = DriverCommandProcessProtection {
+                mode: match mode {
+                    0 => ProcessProtectionMode::None,
+                    1 => ProcessProtectionMode::Kernel,
+                    _ => ProcessProtectionMode::Zenith,
+                },
+            };
+            round_trip(command, Vec::new(), command, Vec::new());
+        }
+
+        #[test]
+        fn metrics_flush(blocking in any::<bool>(), queue_remaining in any::<usize>()) {
+            let command = DriverCommandMetricsFlush { blocking, queue_remaining: 0 };
+            let output = DriverCommandMetricsFlush { blocking, queue_remaining };
+            round_trip(command, Vec::new(), output, Vec::new());
+        }
+
+        #[test]
+        fn cr3_shenanigans(strategy in any::<u32>(), flags in any::<u32>(), success in any::<bool>()) {
+            let command = DriverCommandCr3ShenanigansEnable {
+                mitigation_strategy: strategy,
+                mitigation_flags: flags,
+                success: false,
+            };
+            round_trip(command, Vec::new(), DriverCommandCr3ShenanigansEnable { success, ..command }, Vec::new());
+            round_trip(
+                DriverCommandCr3ShenanigansDisable {},
+                Vec::new(),
+                DriverCommandCr3ShenanigansDisable {},
+                Vec::new(),
+            );
+        }
+
+        #[test]
+        fn arbitrary_messages(message in vec_of(any::<u8>(), 0..0x200)) {
+            /* decoding arbitrary data must never panic */
+            let _ = decode_request::<DriverCommandInitialize>(&message);
+            let _ = decode_request::<DriverCommandProcessModules>(&message);
+            let _ = decode_request::<DriverCommandMemoryWrite>(&message);
+            let _ = decode_request::<DriverCommandInputMouse>(&message);
+
+            let mut buffer = [0u8; 0x10];
+            let mut command = DriverCommandMemoryRead {
+                buffer: buffer.as_mut_ptr(),
+                count: buffer.len(),
+                ..Default::default()
+            };
+            let _ = unsafe { decode_response_into(&mut command, &message) };
+            assert_eq!(command.buffer, buffer.as_mut_ptr());
+            assert_eq!(command.count, buffer.len());
+        }
+    }
+
+    #[test]
+    fn oversized_buffer() {
+        let command = DriverCommandMemoryRead {
+            count: usize::MAX / 2,
+            ..Default::default()
+        };
+
+        let mut request = Vec::new();
+        unsafe { encode_request(&command, &mut request) }.unwrap();
+        assert!(matches!(
+            decode_request::<DriverCommandMemoryRead>(&request),
+            Err(CodecError::BufferTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn shared_command_id() {
+        struct MessageId;
+        impl CommandVisitor for MessageId {
+            type Output = u32;
+
+            fn visit<C: CommandCodec>(self) -> u32 {
+                C::MESSAGE_ID
+            }
+        }
+
+        assert_eq!(
+            visit_command(
+                DriverCommandMetricsFlush::COMMAND_ID,
+                core::mem::size_of::<DriverCommandMetricsFlush>(),
+                MessageId
+            ),
+            Some(DriverCommandMetricsFlush::COMMAND_ID)
+        );
+        assert_eq!(
+            visit_command(
+                DriverCommandCr3ShenanigansEnable::COMMAND_ID,
+                core::mem::size_of::<DriverCommandCr3ShenanigansEnable>(),
+                MessageId
+            ),
+            Some(DriverCommandCr3ShenanigansEnable::MESSAGE_ID)
+        );
+        assert_eq!(visit_command(0xFF, 0, MessageId), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Field-wise encoding of driver commands into self-contained byte messages.
+//!
+//! Commands are plain in-memory structs which reference additional buffers through raw pointers.
+//! The codec encodes every field explicitly (little endian, no padding, no pointers) so the
+//! resulting messages can be transferred to and executed within a different address space.
+//!
+//! A request message contains the command fields and the contents of its input buffer.
+//! A response message contains the command fields and the contents of its output buffer.
+//! See [encode_request], [encode_response] and [decode_response_into].
+use core::fmt;
+
+mod command;
+pub use command::*;
+
+mod value;
+pub use value::*;
+
+/// Upper limit for the number of bytes of a single command buffer
+pub const MAX_BUFFER_SIZE: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    UnexpectedEof,
+    BufferFull,
+    BufferTooLarge(usize),
+    InvalidValue(&'static str),
+    UnknownCommand(u32),
+    CommandMissmatch { expected: u32, received: u32 },
+    TrailingData(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of message"),
+            Self::BufferFull => write!(f, "output buffer full"),
+            Self::BufferTooLarge(size) => write!(f, "buffer too large ({} bytes)", size),
+            Self::InvalidValue(name) => write!(f, "invalid {} value", name),
+            Self::UnknownCommand(command_id) => write!(f, "unknown command {:X}", command_id),
+            Self::CommandMissmatch { expected, received } => write!(
+                f,
+                "command miss match (expected {:X}, received {:X})",
+                expected, received
+            ),
+            Self::TrailingData(length) => write!(f, "{} bytes of trailing data", length),
+        }
+    }
+}
+
+pub type CodecResult<T> = Result<T, CodecError>;
+
+/// Destination for encoded messages
+pub trait Encoder {
+    fn write_bytes(&mut self, value: &[u8]) -> CodecResult<()>;
+}
+
+/// Encoder writing into a fixed size buffer
+pub struct SliceEncoder<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> SliceEncoder<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            position: 0,
+        }
+    }
+
+    /// Number of bytes written
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Encoder for SliceEncoder<'_> {
+    fn write_bytes(&mut self, value: &[u8]) -> CodecResult<()> {
+        let target = self
+            .buffer
+            .get_mut(self.position..self.position + value.len())
+            .ok_or(CodecError::BufferFull)?;
+
+        target.copy_from_slice(value);
+        self.position += value.len();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Encoder for alloc::vec::Vec<u8> {
+    fn write_bytes(&mut self, value: &[u8]) -> CodecResult<()> {
+        self.extend_from_slice(value);
+        Ok(())
+    }
+}
+
+/// Cursor for reading encoded messages
+pub struct Decoder<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> CodecResult<&'a [u8]> {
+        if self.buffer.len() < length {
+            return Err(CodecError::UnexpectedEof);
+        }
+
+        let (bytes, remaining) = self.buffer.split_at(length);
+        self.buffer = remaining;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> CodecResult<[u8; N]> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    /// Ensure the whole message has been consumed
+    pub fn finish(self) -> CodecResult<()> {
+        if self.buffer.is_empty() {
+            Ok(())
+        } else {
+            Err(CodecError::TrailingData(self.buffer.len()))
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use super::{
+    CodecError,
+    CodecResult,
+    Decoder,
+    Encoder,
+};
+use crate::{
+    command::{
+        InitializeResult,
+        KeyboardState,
+        MouseState,
+        ProcessProtectionMode,
+        VersionInfo,
+    },
+    types::{
+        DirectoryTableType,
+        DriverFeature,
+        MemoryAccessResult,
+        ProcessInfo,
+        ProcessModuleInfo,
+    },
+};
+
+/// A value which can be encoded field by field
+pub trait Codec: Sized {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()>;
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self>;
+
+    fn encode_slice(values: &[Self], encoder: &mut impl Encoder) -> CodecResult<()> {
+        for value in values.iter() {
+            value.encode(encoder)?;
+        }
+        Ok(())
+    }
+
+    fn decode_slice(values: &mut [Self], decoder: &mut Decoder) -> CodecResult<()> {
+        for value in values.iter_mut() {
+            *value = Self::decode(decoder)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! impl_codec_integer {
+    ($($type:ty),*) => {
+        $(
+            impl Codec for $type {
+                fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+                    encoder.write_bytes(&self.to_le_bytes())
+                }
+
+                fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+                    Ok(Self::from_le_bytes(decoder.read_array()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_integer!(u16, u32, u64, i32);
+
+impl Codec for u8 {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        encoder.write_bytes(&[*self])
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        Ok(decoder.read_bytes(1)?[0])
+    }
+
+    /* byte buffers (e.g. memory contents) are copied at once */
+    fn encode_slice(values: &[Self], encoder: &mut impl Encoder) -> CodecResult<()> {
+        encoder.write_bytes(values)
+    }
+
+    fn decode_slice(values: &mut [Self], decoder: &mut Decoder) -> CodecResult<()> {
+        values.copy_from_slice(decoder.read_bytes(values.len())?);
+        Ok(())
+    }
+}
+
+/// Sizes are always encoded as 64 bit values
+impl Codec for usize {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        (*self as u64).encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        usize::try_from(u64::decode(decoder)?).map_err(|_| CodecError::InvalidValue("usize"))
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        (*self as u8).encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        match u8::decode(decoder)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(CodecError::InvalidValue("bool")),
+        }
+    }
+}
+
+impl Codec for Option<bool> {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        let value: u8 = match self {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        };
+        value.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        match u8::decode(decoder)? {
+            0 => Ok(None),
+            1 => Ok(Some(false)),
+            2 => Ok(Some(true)),
+            _ => Err(CodecError::InvalidValue("Option<bool>")),
+        }
+    }
+}
+
+impl<const N: usize> Codec for [u8; N] {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        encoder.write_bytes(self)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        decoder.read_array()
+    }
+}
+
+impl Codec for [Option<bool>; 0x05] {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        Option::<bool>::encode_slice(self, encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        let mut result = [None; 0x05];
+        Option::<bool>::decode_slice(&mut result, decoder)?;
+        Ok(result)
+    }
+}
+
+impl Codec for DriverFeature {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        self.bits().encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        /* keep unknown features so newer drivers can be proxied */
+        Ok(Self::from_bits_retain(u64::decode(decoder)?))
+    }
+}
+
+/// Implement [Codec] for a struct by encoding the given fields in order.
+/// All other fields (e.g. buffer pointers) are set to their default value when decoding.
+macro_rules! impl_codec_struct {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl Codec for $type {
+            #[allow(unused_variables)]
+            fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+                $(self.$field.encode(encoder)?;)*
+                Ok(())
+            }
+
+            #[allow(unused_variables, clippy::needless_update)]
+            fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+                Ok(Self {
+                    $($field: Codec::decode(decoder)?,)*
+                    ..Default::default()
+                })
+            }
+        }
+    };
+}
+pub(crate) use impl_codec_struct;
+
+impl_codec_struct!(VersionInfo {
+    application_name,
+    version_major,
+    version_minor,
+    version_patch,
+});
+
+impl_codec_struct!(ProcessInfo {
+    process_id,
+    image_base_name,
+    directory_table_base,
+});
+
+impl_codec_struct!(ProcessModuleInfo {
+    base_dll_name,
+    base_address,
+    module_size,
+});
+
+impl_codec_struct!(KeyboardState { scane_code, down });
+
+impl_codec_struct!(MouseState {
+    buttons,
+    hwheel,
+    wheel,
+    last_x,
+    last_y,
+});
+
+impl Codec for InitializeResult {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        let tag: u8 = match self {
+            Self::Success => 0,
+            Self::Unavailable => 1,
+        };
+        tag.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        match u8::decode(decoder)? {
+            0 => Ok(Self::Success),
+            1 => Ok(Self::Unavailable),
+            _ => Err(CodecError::InvalidValue("InitializeResult")),
+        }
+    }
+}
+
+impl Codec for ProcessProtectionMode {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        let tag: u8 = match self {
+            Self::None => 0,
+            Self::Kernel => 1,
+            Self::Zenith => 2,
+        };
+        tag.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        match u8::decode(decoder)? {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Kernel),
+            2 => Ok(Self::Zenith),
+            _ => Err(CodecError::InvalidValue("ProcessProtectionMode")),
+        }
+    }
+}
+
+impl Codec for MemoryAccessResult {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        match self {
+            Self::Success => 0u8.encode(encoder),
+            Self::PartialSuccess { bytes_copied } => {
+                1u8.encode(encoder)?;
+                bytes_copied.encode(encoder)
+            }
+            Self::ProcessUnknown => 2u8.encode(encoder),
+            Self::SourcePagedOut => 3u8.encode(encoder),
+            Self::DestinationPagedOut => 4u8.encode(encoder),
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        match u8::decode(decoder)? {
+            0 => Ok(Self::Success),
+            1 => Ok(Self::PartialSuccess {
+                bytes_copied: Codec::decode(decoder)?,
+            }),
+            2 => Ok(Self::ProcessUnknown),
+            3 => Ok(Self::SourcePagedOut),
+            4 => Ok(Self::DestinationPagedOut),
+            _ => Err(CodecError::InvalidValue("MemoryAccessResult")),
+        }
+    }
+}
+
+impl Codec for DirectoryTableType {
+    fn encode(&self, encoder: &mut impl Encoder) -> CodecResult<()> {
+        match self {
+            Self::Default => 0u8.encode(encoder),
+            Self::Explicit {
+                directory_table_base,
+            } => {
+                1u8.encode(encoder)?;
+                directory_table_base.encode(encoder)
+            }
+            Self::Cr3Shenanigans => 2u8.encode(encoder),
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> CodecResult<Self> {
+        match u8::decode(decoder)? {
+            0 => Ok(Self::Default),
+            1 => Ok(Self::Explicit {
+                directory_table_base: Codec::decode(decoder)?,
+            }),
+            2 => Ok(Self::Cr3Shenanigans),
+            _ => Err(CodecError::InvalidValue("DirectoryTableType")),
+        }
+    }
+}
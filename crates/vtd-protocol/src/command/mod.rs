@@ -27,12 +27,14 @@ pub use cr3::*;
 
 pub trait DriverCommand: Default + Copy {
     const COMMAND_ID: u32;
+    const NAME: &'static str;
 }
 
 macro_rules! define_command {
     ($struct:ty, $id:expr) => {
         impl DriverCommand for $struct {
             const COMMAND_ID: u32 = $id;
+            const NAME: &'static str = stringify!($struct);
         }
     };
 }
@@ -53,3 +55,58 @@ define_command!(DriverCommandMetricsFlush, 0x09);
 
 define_command!(DriverCommandCr3ShenanigansEnable, 0x09);
 define_command!(DriverCommandCr3ShenanigansDisable, 0x0A);
+
+/// Description of a command known to the protocol
+#[derive(Debug, Clone, Copy)]
+pub struct CommandDescriptor {
+    pub command_id: u32,
+    pub name: &'static str,
+    pub payload_size: usize,
+}
+
+impl CommandDescriptor {
+    pub const fn of<C: DriverCommand>() -> Self {
+        Self {
+            command_id: C::COMMAND_ID,
+            name: C::NAME,
+            payload_size: core::mem::size_of::<C>(),
+        }
+    }
+}
+
+/// All commands known to the protocol.
+/// Multiple commands may share the same command id.
+pub const COMMAND_TABLE: &[CommandDescriptor] = &[
+    CommandDescriptor::of::<DriverCommandInitialize>(),
+    CommandDescriptor::of::<DriverCommandProcessList>(),
+    CommandDescriptor::of::<DriverCommandProcessModules>(),
+    CommandDescriptor::of::<DriverCommandMemoryRead>(),
+    CommandDescriptor::of::<DriverCommandMemoryWrite>(),
+    CommandDescriptor::of::<DriverCommandInputKeyboard>(),
+    CommandDescriptor::of::<DriverCommandInputMouse>(),
+    CommandDescriptor::of::<DriverCommandMetricsReportSend>(),
+    CommandDescriptor::of::<DriverCommandProcessProtection>(),
+    CommandDescriptor::of::<DriverCommandMetricsFlush>(),
+    CommandDescriptor::of::<DriverCommandCr3ShenanigansEnable>(),
+    CommandDescriptor::of::<DriverCommandCr3ShenanigansDisable>(),
+];
+
+/// One past the highest command id of the [COMMAND_TABLE]
+pub const COMMAND_ID_LIMIT: u32 = {
+    let mut limit = 0;
+    let mut index = 0;
+    while index < COMMAND_TABLE.len() {
+        if COMMAND_TABLE[index].command_id >= limit {
+            limit = COMMAND_TABLE[index].command_id + 1;
+        }
+        index += 1;
+    }
+    limit
+};
+
+/// Find the command descriptor by the command id and payload size
+pub fn find_command(command_id: u32, payload_size: usize) -> Option<&'static CommandDescriptor> {
+    COMMAND_TABLE
+        .iter()
+        .find(|command| command.command_id == command_id && command.payload_size == payload_size)
+}
@@ -1,5 +1,11 @@
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(test)]
+extern crate std;
+
 mod result;
 pub use result::*;
 
@@ -9,6 +15,10 @@ pub mod types;
 
 pub mod command;
 
+pub mod codec;
+
+pub mod validation;
+
 pub const PROTOCOL_VERSION: u32 = 0x03;
 
 pub type FnCommandHandler = unsafe extern "C" fn(
@@ -9,5 +9,26 @@ bitflags! {
         const CommandInvalid = 0x10;
         const CommandParameterInvalid = 0x11;
         const CommandFeatureUnsupported = 0x12;
+
+        /// The driver denied access to the command or its target
+        const AccessDenied = 0x13;
+    }
+}
+
+impl CommandResult {
+    /// Name of the result.
+    /// The results are plain values and must not be combined like flags.
+    pub fn name(&self) -> Option<&'static str> {
+        [
+            (Self::Error, "Error"),
+            (Self::Success, "Success"),
+            (Self::CommandInvalid, "CommandInvalid"),
+            (Self::CommandParameterInvalid, "CommandParameterInvalid"),
+            (Self::CommandFeatureUnsupported, "CommandFeatureUnsupported"),
+            (Self::AccessDenied, "AccessDenied"),
+        ]
+        .into_iter()
+        .find(|(value, _)| value == self)
+        .map(|(_, name)| name)
     }
 }
@@ -78,7 +78,7 @@ impl Default for ProcessModuleInfo {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryAccessResult {
     Success,
     PartialSuccess { bytes_copied: usize },
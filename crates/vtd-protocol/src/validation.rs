@@ -0,0 +1,298 @@
+//! Validation of client supplied command payloads.
+//!
+//! Commands reference buffers through raw pointers and element counts.
+//! Drivers must validate these before creating slices from them.
+use core::{
+    fmt,
+    mem,
+    slice,
+};
+
+use crate::codec::{
+    CommandCodec,
+    MAX_BUFFER_SIZE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    PayloadSizeMissmatch {
+        expected: usize,
+        received: usize,
+    },
+    PayloadMisaligned {
+        address: usize,
+        alignment: usize,
+    },
+    BufferNull {
+        field: &'static str,
+        count: usize,
+    },
+    BufferMisaligned {
+        field: &'static str,
+        address: usize,
+        alignment: usize,
+    },
+    BufferTooLarge {
+        field: &'static str,
+        count: usize,
+        limit: usize,
+    },
+    BufferOverflow {
+        field: &'static str,
+        address: usize,
+        length: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PayloadSizeMissmatch { expected, received } => write!(
+                f,
+                "command size miss match (expected {}, received: {})",
+                expected, received
+            ),
+            Self::PayloadMisaligned { address, alignment } => write!(
+                f,
+                "command payload at {:X} is not aligned to {} bytes",
+                address, alignment
+            ),
+            Self::BufferNull { field, count } => {
+                write!(f, "{} is null but references {} elements", field, count)
+            }
+            Self::BufferMisaligned {
+                field,
+                address,
+                alignment,
+            } => write!(
+                f,
+                "{} at {:X} is not aligned to {} bytes",
+                field, address, alignment
+            ),
+            Self::BufferTooLarge {
+                field,
+                count,
+                limit,
+            } => write!(
+                f,
+                "{} references {} elements but at most {} are allowed",
+                field, count, limit
+            ),
+            Self::BufferOverflow {
+                field,
+                address,
+                length,
+            } => write!(
+                f,
+                "{} at {:X} with {} bytes exceeds the address space",
+                field, address, length
+            ),
+        }
+    }
+}
+
+pub type ValidationResult<T> = Result<T, ValidationError>;
+
+/// Maximum number of elements a single command buffer may reference
+pub const fn max_buffer_elements<T>() -> usize {
+    if mem::size_of::<T>() == 0 {
+        usize::MAX
+    } else {
+        MAX_BUFFER_SIZE / mem::size_of::<T>()
+    }
+}
+
+/// Validate a buffer of `count` elements.
+/// Empty buffers are always valid as commands may use them to query the required capacity.
+pub fn validate_buffer<T>(
+    field: &'static str,
+    buffer: *const T,
+    count: usize,
+) -> ValidationResult<()> {
+    if count == 0 {
+        return Ok(());
+    }
+
+    if buffer.is_null() {
+        return Err(ValidationError::BufferNull { field, count });
+    }
+
+    let address = buffer as usize;
+    if !buffer.is_aligned() {
+        return Err(ValidationError::BufferMisaligned {
+            field,
+            address,
+            alignment: mem::align_of::<T>(),
+        });
+    }
+
+    let limit = self::max_buffer_elements::<T>();
+    if count > limit {
+        return Err(ValidationError::BufferTooLarge {
+            field,
+            count,
+            limit,
+        });
+    }
+
+    /* cannot overflow as the count is limited */
+    let length = count * mem::size_of::<T>();
+    if address.checked_add(length).is_none() {
+        return Err(ValidationError::BufferOverflow {
+            field,
+            address,
+            length,
+        });
+    }
+
+    Ok(())
+}
+
+/// Validate the raw payload of a command and the buffer it references.
+/// Returns the command if the payload is valid.
+///
+/// # Safety
+/// The payload must contain a bit valid instance of `C`.
+pub unsafe fn validate_payload<C: CommandCodec>(payload: &mut [u8]) -> ValidationResult<&mut C> {
+    if payload.len() != mem::size_of::<C>() {
+        return Err(ValidationError::PayloadSizeMissmatch {
+            expected: mem::size_of::<C>(),
+            received: payload.len(),
+        });
+    }
+
+    let command = payload.as_mut_ptr() as *mut C;
+    if !command.is_aligned() {
+        return Err(ValidationError::PayloadMisaligned {
+            address: command as usize,
+            alignment: mem::align_of::<C>(),
+        });
+    }
+
+    let command = &mut *command;
+    self::validate_command(command)?;
+    Ok(command)
+}
+
+/// Validate the buffer referenced by a command
+pub fn validate_command<C: CommandCodec>(command: &C) -> ValidationResult<()> {
+    if C::BUFFER.is_none() {
+        return Ok(());
+    }
+
+    let (buffer, count) = command.buffer();
+    self::validate_buffer(C::BUFFER_FIELD, buffer, count)
+}
+
+/// Create a slice from a validated buffer.
+/// Unlike [slice::from_raw_parts] empty buffers may be null.
+///
+/// # Safety
+/// The buffer must have been validated and be valid for reads of `count` elements.
+pub unsafe fn buffer_slice<'a, T>(buffer: *const T, count: usize) -> &'a [T] {
+    if count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(buffer, count)
+    }
+}
+
+/// Create a mutable slice from a validated buffer.
+/// Unlike [slice::from_raw_parts_mut] empty buffers may be null.
+///
+/// # Safety
+/// The buffer must have been validated and be valid for writes of `count` elements.
+pub unsafe fn buffer_slice_mut<'a, T>(buffer: *mut T, count: usize) -> &'a mut [T] {
+    if count == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(buffer, count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{
+        mem,
+        ptr,
+    };
+
+    use super::{
+        validate_buffer,
+        validate_command,
+        validate_payload,
+        ValidationError,
+    };
+    use crate::{
+        codec::MAX_BUFFER_SIZE,
+        command::{
+            DriverCommandInitialize,
+            DriverCommandInputMouse,
+            DriverCommandProcessList,
+        },
+        types::ProcessInfo,
+    };
+
+    #[test]
+    fn empty_buffer() {
+        assert_eq!(validate_buffer::<u64>("buffer", ptr::null(), 0), Ok(()));
+        assert!(validate_command(&DriverCommandProcessList::default()).is_ok());
+    }
+
+    #[test]
+    fn invalid_buffer() {
+        assert_eq!(
+            validate_buffer::<u8>("buffer", ptr::null(), 1),
+            Err(ValidationError::BufferNull {
+                field: "buffer",
+                count: 1
+            })
+        );
+        assert!(matches!(
+            validate_buffer::<u64>("buffer", 0x1001 as *const u64, 1),
+            Err(ValidationError::BufferMisaligned { alignment: 8, .. })
+        ));
+        assert!(matches!(
+            validate_buffer::<u8>("buffer", 0x1000 as *const u8, MAX_BUFFER_SIZE + 1),
+            Err(ValidationError::BufferTooLarge { .. })
+        ));
+        assert!(matches!(
+            validate_buffer::<u8>("buffer", usize::MAX as *const u8, 0x10),
+            Err(ValidationError::BufferOverflow { .. })
+        ));
+
+        let command = DriverCommandInputMouse {
+            buffer: ptr::null(),
+            state_count: 4,
+        };
+        assert_eq!(
+            validate_command(&command),
+            Err(ValidationError::BufferNull {
+                field: "buffer",
+                count: 4
+            })
+        );
+    }
+
+    #[test]
+    fn payload() {
+        let mut buffer = [ProcessInfo::default(); 4];
+        let mut command = DriverCommandProcessList {
+            buffer: buffer.as_mut_ptr(),
+            buffer_capacity: buffer.len(),
+            ..Default::default()
+        };
+
+        let payload = unsafe {
+            core::slice::from_raw_parts_mut(
+                &mut command as *mut _ as *mut u8,
+                mem::size_of::<DriverCommandProcessList>(),
+            )
+        };
+        assert!(unsafe { validate_payload::<DriverCommandProcessList>(payload) }.is_ok());
+        assert!(matches!(
+            unsafe { validate_payload::<DriverCommandInitialize>(payload) },
+            Err(ValidationError::PayloadSizeMissmatch { .. })
+        ));
+    }
+}
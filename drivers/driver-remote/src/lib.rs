@@ -0,0 +1,89 @@
+//! Driver forwarding all commands to a remote driver server (`vtd-driver-server`).
+//!
+//! The server endpoint and the pre-shared key are configured via
+//! `VT_REMOTE_ENDPOINT` and `VT_REMOTE_KEY`.
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use vtd_libum::{
+    remote::{
+        self,
+        RemoteDriver,
+    },
+    DriverBackend,
+    IResult,
+};
+use vtd_protocol::{
+    utils::str_to_fixed_buffer,
+    validation,
+    CommandResult,
+};
+
+static REMOTE_DRIVER: Mutex<Option<Arc<RemoteDriver>>> = Mutex::new(None);
+
+/// Get the remote driver and connect to the server if not yet connected
+fn remote_driver() -> IResult<Arc<RemoteDriver>> {
+    let mut driver = REMOTE_DRIVER.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(driver) = &*driver {
+        return Ok(driver.clone());
+    }
+
+    let (endpoint, key) = remote::config_from_env()?;
+    let remote = Arc::new(RemoteDriver::connect(endpoint, &key)?);
+    *driver = Some(remote.clone());
+    Ok(remote)
+}
+
+#[no_mangle]
+unsafe extern "C" fn startup() {
+    let _ = env_logger::try_init();
+}
+
+#[no_mangle]
+unsafe extern "C" fn teardown() {
+    let mut driver = REMOTE_DRIVER.lock().unwrap_or_else(|err| err.into_inner());
+    *driver = None;
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn DllMain(_dll_module: *const (), _call_reason: u32, _: *mut ()) -> bool {
+    true
+}
+
+#[no_mangle]
+extern "C" fn execute_command(
+    command_id: u32,
+
+    payload: *mut u8,
+    payload_length: usize,
+
+    error_message: *mut u8,
+    error_message_length: usize,
+) -> u64 {
+    if validation::validate_buffer("error_message", error_message, error_message_length).is_err() {
+        return CommandResult::CommandParameterInvalid.bits();
+    }
+    let error_message =
+        unsafe { validation::buffer_slice_mut(error_message, error_message_length) };
+
+    if let Err(error) = validation::validate_buffer("payload", payload, payload_length) {
+        str_to_fixed_buffer(error_message, &error.to_string());
+        return CommandResult::CommandParameterInvalid.bits();
+    }
+    let payload = unsafe { validation::buffer_slice_mut(payload, payload_length) };
+
+    let result = self::remote_driver()
+        .and_then(|driver| unsafe { driver.execute_command(command_id, payload, error_message) });
+
+    match result {
+        Ok(result) => result,
+        Err(err) => {
+            log::warn!("Failed to forward command {:X}: {}", command_id, err);
+            str_to_fixed_buffer(error_message, &err.to_string());
+            CommandResult::Error.bits()
+        }
+    }
+}
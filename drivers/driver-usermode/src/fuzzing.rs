@@ -0,0 +1,399 @@
+//! Fuzzing support for the command entry point.
+//!
+//! The fuzz targets within the `fuzz` directory execute arbitrary commands against the
+//! [HandlerRegistry] with a set of test handlers which only access the command buffers.
+//! The targets are runnable on Linux via `cargo +nightly fuzz run <target>`.
+//!
+//! Crashing inputs should be copied to `fuzz/regressions/<target>/`
+//! where they will be replayed by `cargo test`.
+use std::{
+    hint,
+    mem,
+    ptr,
+};
+
+use lazy_static::lazy_static;
+use vtd_protocol::{
+    codec::{
+        self,
+        BufferDirection,
+        CommandCodec,
+        CommandVisitor,
+        Decoder,
+    },
+    command::{
+        DriverCommandCr3ShenanigansDisable,
+        DriverCommandInitialize,
+        DriverCommandInputKeyboard,
+        DriverCommandInputMouse,
+        DriverCommandMemoryRead,
+        DriverCommandMemoryWrite,
+        DriverCommandMetricsFlush,
+        DriverCommandMetricsReportSend,
+        DriverCommandProcessList,
+        DriverCommandProcessModules,
+        DriverCommandProcessProtection,
+    },
+    validation,
+    CommandResult,
+};
+
+pub use crate::registry::HandlerRegistry;
+
+/// Maximum number of buffer elements which will be allocated for a command.
+/// Commands referencing more elements get a null buffer assigned.
+const MAX_FUZZ_ELEMENTS: usize = 0x1000;
+
+/// Size of the input header (see [FuzzInput::parse])
+const INPUT_HEADER_SIZE: usize = 0x09;
+
+lazy_static! {
+    static ref TEST_HANDLER: HandlerRegistry = test_handler();
+}
+
+/// Test handler which reads or writes the whole command buffer
+fn touch_buffer<C: CommandCodec>(command: &mut C) -> anyhow::Result<()> {
+    let (buffer, count) = command.buffer();
+    match C::BUFFER {
+        Some(BufferDirection::In) => {
+            for value in unsafe { validation::buffer_slice(buffer, count) } {
+                hint::black_box(*value);
+            }
+        }
+        Some(BufferDirection::Out) => {
+            unsafe { validation::buffer_slice_mut(buffer, count) }.fill(Default::default());
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+fn test_handler() -> HandlerRegistry {
+    let mut handler = HandlerRegistry::new();
+
+    handler.register(&touch_buffer::<DriverCommandInitialize>);
+    handler.register(&touch_buffer::<DriverCommandProcessList>);
+    handler.register(&touch_buffer::<DriverCommandProcessModules>);
+    handler.register(&touch_buffer::<DriverCommandMemoryRead>);
+    handler.register(&touch_buffer::<DriverCommandMemoryWrite>);
+    handler.register(&touch_buffer::<DriverCommandInputKeyboard>);
+    handler.register(&touch_buffer::<DriverCommandInputMouse>);
+    handler.register(&touch_buffer::<DriverCommandMetricsReportSend>);
+    handler.register(&touch_buffer::<DriverCommandProcessProtection>);
+    handler.register(&touch_buffer::<DriverCommandMetricsFlush>);
+    handler.register(&touch_buffer::<DriverCommandCr3ShenanigansDisable>);
+
+    handler
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BufferMode {
+    /// Allocate a buffer for the referenced elements
+    Allocated,
+
+    /// Keep the element count but assign a null buffer
+    Null,
+
+    /// Allocate a buffer but misalign it by one byte
+    Misaligned,
+}
+
+/// Fuzzer input describing a single command invocation.
+///
+/// Known message ids are executed with a bit valid command decoded from the body via the
+/// [codec] as only the buffer pointers can be validated by the driver.
+/// Unknown message ids are executed with the raw body as payload.
+#[derive(Debug)]
+struct FuzzInput<'a> {
+    message_id: u32,
+    error_capacity: usize,
+    payload_offset: usize,
+    payload_size_delta: isize,
+    buffer_mode: BufferMode,
+    body: &'a [u8],
+}
+
+impl<'a> FuzzInput<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < INPUT_HEADER_SIZE {
+            return None;
+        }
+
+        let (header, body) = data.split_at(INPUT_HEADER_SIZE);
+        let buffer_mode = match header[8] % 3 {
+            0 => BufferMode::Allocated,
+            1 => BufferMode::Null,
+            _ => BufferMode::Misaligned,
+        };
+
+        Some(Self {
+            message_id: u32::from_le_bytes(header[0..4].try_into().unwrap()),
+            error_capacity: u16::from_le_bytes(header[4..6].try_into().unwrap()) as usize % 0x1000,
+            payload_offset: header[6] as usize % 8,
+            payload_size_delta: header[7] as i8 as isize,
+            buffer_mode,
+            body,
+        })
+    }
+}
+
+/// Aligned storage for command payloads
+struct PayloadBuffer {
+    storage: Vec<u64>,
+}
+
+impl PayloadBuffer {
+    fn new(size: usize) -> Self {
+        Self {
+            storage: vec![0; size.div_ceil(8) + 2],
+        }
+    }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        let length = self.storage.len() * 8;
+        unsafe { std::slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut u8, length) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// [HandlerRegistry::handle]
+    Registry,
+
+    /// The command entry point.
+    /// Empty payloads and error messages will be passed as null buffers.
+    EntryPoint,
+}
+
+impl Target {
+    fn execute(
+        self,
+        command_id: u32,
+        payload: &mut [u8],
+        error_message: &mut [u8],
+    ) -> CommandResult {
+        match self {
+            Self::Registry => TEST_HANDLER.handle(command_id, payload, error_message),
+            Self::EntryPoint => {
+                let result = unsafe {
+                    crate::dispatch_command(
+                        &TEST_HANDLER,
+                        command_id,
+                        Self::nullable(payload),
+                        payload.len(),
+                        Self::nullable(error_message),
+                        error_message.len(),
+                    )
+                };
+
+                assert!(
+                    crate::unwind::degraded_reason().is_none(),
+                    "a handler panicked"
+                );
+                CommandResult::from_bits_retain(result)
+            }
+        }
+    }
+
+    fn nullable(buffer: &mut [u8]) -> *mut u8 {
+        if buffer.is_empty() {
+            ptr::null_mut()
+        } else {
+            buffer.as_mut_ptr()
+        }
+    }
+}
+
+struct ExecuteVisitor<'a> {
+    input: &'a FuzzInput<'a>,
+    target: Target,
+}
+
+impl CommandVisitor for ExecuteVisitor<'_> {
+    type Output = ();
+
+    fn visit<C: CommandCodec>(self) {
+        let input = self.input;
+        let mut command = C::decode(&mut Decoder::new(input.body)).unwrap_or_default();
+
+        let (_, count) = command.buffer();
+        let mut elements = Vec::<C::Element>::new();
+        let buffer = if count > MAX_FUZZ_ELEMENTS || input.buffer_mode == BufferMode::Null {
+            ptr::null_mut()
+        } else {
+            /* one additional element for the misaligned buffer */
+            elements.resize(count + 1, Default::default());
+            match input.buffer_mode {
+                BufferMode::Misaligned => unsafe {
+                    (elements.as_mut_ptr() as *mut u8).add(1) as *mut C::Element
+                },
+                _ => elements.as_mut_ptr(),
+            }
+        };
+        command.set_buffer(buffer, count);
+        let command_valid = validation::validate_command(&command).is_ok();
+
+        let payload_size =
+            mem::size_of::<C>().saturating_add_signed(input.payload_size_delta.clamp(-0x10, 0x10));
+        let mut payload = PayloadBuffer::new(mem::size_of::<C>().max(payload_size) + 8);
+        let payload = &mut payload.bytes()[input.payload_offset..];
+        unsafe { ptr::write_unaligned(payload.as_mut_ptr() as *mut C, command) };
+        let payload = &mut payload[0..payload_size];
+
+        let mut error_message = vec![0u8; input.error_capacity];
+        let result = self
+            .target
+            .execute(C::COMMAND_ID, payload, &mut error_message);
+
+        /* commands sharing the id of another command are handled by the other commands handler */
+        if C::MESSAGE_ID != C::COMMAND_ID {
+            return;
+        }
+
+        let payload_valid = payload_size == mem::size_of::<C>()
+            && (payload.as_ptr() as usize) % mem::align_of::<C>() == 0;
+        let expected = if payload_valid && command_valid {
+            CommandResult::Success
+        } else {
+            CommandResult::CommandParameterInvalid
+        };
+        assert_eq!(
+            result, expected,
+            "unexpected result for {:?} (command valid: {}, payload valid: {})",
+            input, command_valid, payload_valid
+        );
+    }
+}
+
+fn fuzz_target(data: &[u8], target: Target) {
+    let Some(input) = FuzzInput::parse(data) else {
+        return;
+    };
+
+    let visitor = ExecuteVisitor {
+        input: &input,
+        target,
+    };
+    if codec::visit_message(input.message_id, visitor).is_none() {
+        let mut payload = input.body.to_vec();
+        let mut error_message = vec![0u8; input.error_capacity];
+        let result = target.execute(input.message_id, &mut payload, &mut error_message);
+        assert_eq!(result, CommandResult::CommandInvalid);
+    }
+}
+
+/// Execute the fuzzer input against the [HandlerRegistry] using the test handlers
+pub fn fuzz_handle_command(data: &[u8]) {
+    self::fuzz_target(data, Target::Registry);
+}
+
+/// Execute the fuzzer input against the command entry point using the test handlers
+pub fn fuzz_execute_command(data: &[u8]) {
+    self::fuzz_target(data, Target::EntryPoint);
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        panic,
+        path::Path,
+    };
+
+    use vtd_protocol::{
+        codec::CommandCodec,
+        command::{
+            DriverCommandInputMouse,
+            DriverCommandProcessList,
+        },
+    };
+
+    use super::{
+        fuzz_execute_command,
+        fuzz_handle_command,
+    };
+
+    fn input<C: CommandCodec>(command: &C, offset: u8, size_delta: i8, buffer_mode: u8) -> Vec<u8> {
+        let mut input = Vec::new();
+        input.extend_from_slice(&C::MESSAGE_ID.to_le_bytes());
+        input.extend_from_slice(&0x100u16.to_le_bytes());
+        input.push(offset);
+        input.push(size_delta as u8);
+        input.push(buffer_mode);
+        command.encode(&mut input).unwrap();
+        input
+    }
+
+    fn run(input: &[u8]) {
+        fuzz_handle_command(input);
+        fuzz_execute_command(input);
+    }
+
+    #[test]
+    fn valid_commands() {
+        self::run(&self::input(
+            &DriverCommandProcessList {
+                buffer_capacity: 0x10,
+                ..Default::default()
+            },
+            0,
+            0,
+            0,
+        ));
+        self::run(&self::input(&DriverCommandInputMouse::default(), 0, 0, 1));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let command = DriverCommandProcessList {
+            buffer_capacity: 0x10,
+            ..Default::default()
+        };
+
+        /* null buffer, misaligned buffer, oversized count */
+        self::run(&self::input(&command, 0, 0, 1));
+        self::run(&self::input(&command, 0, 0, 2));
+        self::run(&self::input(
+            &DriverCommandProcessList {
+                buffer_capacity: usize::MAX,
+                ..Default::default()
+            },
+            0,
+            0,
+            0,
+        ));
+
+        /* misaligned and wrongly sized payloads */
+        self::run(&self::input(&command, 3, 0, 0));
+        self::run(&self::input(&command, 0, -1, 0));
+        self::run(&self::input(&command, 0, 8, 0));
+
+        /* unknown command and empty payload */
+        self::run(&[0xFF; 0x20]);
+        self::run(&[0x01, 0, 0, 0, 0, 0, 0, 0x80, 0]);
+    }
+
+    #[test]
+    fn regressions() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+        for (target, execute) in [
+            ("handle_command", fuzz_handle_command as fn(&[u8])),
+            ("execute_command", fuzz_execute_command as fn(&[u8])),
+        ] {
+            let Ok(entries) = fs::read_dir(directory.join(target)) else {
+                continue;
+            };
+
+            for entry in entries {
+                let path = entry.unwrap().path();
+                let data = fs::read(&path).unwrap();
+                assert!(
+                    panic::catch_unwind(|| execute(&data)).is_ok(),
+                    "regression {} failed",
+                    path.display()
+                );
+            }
+        }
+    }
+}
@@ -8,6 +8,8 @@ use vtd_protocol::{
     PROTOCOL_VERSION,
 };
 
+use crate::policy;
+
 fn driver_version() -> VersionInfo {
     let mut info = VersionInfo::default();
     info.set_application_name("um-driver");
@@ -31,8 +33,17 @@ pub fn init(command: &mut DriverCommandInitialize) -> anyhow::Result<()> {
     command.driver_features = DriverFeature::ProcessList
         | DriverFeature::ProcessModules
         | DriverFeature::MemoryRead
-        | DriverFeature::InputMouse
-        | DriverFeature::InputKeyboard;
+        | DriverFeature::MemoryWrite;
+
+    /* do not advertise writing memory if every write would be denied */
+    if policy::current().read_only {
+        command.driver_features.remove(DriverFeature::MemoryWrite);
+    }
+
+    /* input is simulated using SendInput which is only available on Windows */
+    if cfg!(windows) {
+        command.driver_features |= DriverFeature::InputMouse | DriverFeature::InputKeyboard;
+    }
 
     command.result = InitializeResult::Success;
     Ok(())
@@ -1,11 +1,11 @@
-use std::{
-    mem,
-    slice,
-};
+use std::mem;
 
-use vtd_protocol::command::{
-    DriverCommandInputKeyboard,
-    KeyboardState,
+use vtd_protocol::{
+    command::{
+        DriverCommandInputKeyboard,
+        KeyboardState,
+    },
+    validation,
 };
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     SendInput,
@@ -15,7 +15,7 @@ use windows::Win32::UI::Input::KeyboardAndMouse::{
 };
 
 pub fn keyboard_state(command: &mut DriverCommandInputKeyboard) -> anyhow::Result<()> {
-    let states = unsafe { slice::from_raw_parts(command.buffer, command.state_count) };
+    let states = unsafe { validation::buffer_slice(command.buffer, command.state_count) };
     let inputs = states
         .iter()
         .map(keyboard_state_to_input)
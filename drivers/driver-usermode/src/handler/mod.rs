@@ -1,17 +1,103 @@
-mod init;
-pub use init::*;
-
-mod modules;
-pub use modules::*;
-
-mod read;
-pub use read::*;
-
-mod mouse;
-pub use mouse::*;
-
-mod keyboard;
-pub use keyboard::*;
-
-mod metrics;
-pub use metrics::*;
+use vtd_protocol::types::DirectoryTableType;
+
+use crate::registry::FeatureUnsupported;
+
+mod init;
+pub use init::*;
+
+mod modules;
+pub use modules::*;
+
+mod read;
+pub use read::*;
+
+mod write;
+pub use write::*;
+
+#[cfg(windows)]
+mod mouse;
+#[cfg(windows)]
+pub use mouse::*;
+
+#[cfg(windows)]
+mod keyboard;
+#[cfg(windows)]
+pub use keyboard::*;
+
+mod metrics;
+pub use metrics::*;
+
+/// Processes can only be accessed using the directory table base known to the system
+fn ensure_default_directory_table(
+    directory_table_type: &DirectoryTableType,
+) -> Result<(), FeatureUnsupported> {
+    match directory_table_type {
+        DirectoryTableType::Default => Ok(()),
+        DirectoryTableType::Explicit { .. } => Err(FeatureUnsupported::new(
+            "explicit directory table bases are not supported".to_string(),
+        )),
+        DirectoryTableType::Cr3Shenanigans => Err(FeatureUnsupported::new(
+            "cr3 shenanigan mitigations are not supported".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        mem,
+        slice,
+    };
+
+    use vtd_protocol::{
+        command::{
+            DriverCommand,
+            DriverCommandMemoryRead,
+            DriverCommandMemoryWrite,
+        },
+        types::DirectoryTableType,
+        CommandResult,
+    };
+
+    use crate::registry::HandlerRegistry;
+
+    fn execute<C: DriverCommand>(registry: &HandlerRegistry, command: &mut C) -> CommandResult {
+        let payload =
+            unsafe { slice::from_raw_parts_mut(command as *mut _ as *mut u8, mem::size_of::<C>()) };
+
+        let mut error_buffer = [0u8; 0x100];
+        registry.handle(C::COMMAND_ID, payload, &mut error_buffer)
+    }
+
+    #[test]
+    fn directory_table_type_unsupported() {
+        let mut registry = HandlerRegistry::new();
+        registry.register(&super::read);
+        registry.register(&super::write);
+
+        for directory_table_type in [
+            DirectoryTableType::Explicit {
+                directory_table_base: 0x1000,
+            },
+            DirectoryTableType::Cr3Shenanigans,
+        ] {
+            let mut command = DriverCommandMemoryRead {
+                directory_table_type,
+                ..Default::default()
+            };
+            assert_eq!(
+                self::execute(&registry, &mut command),
+                CommandResult::CommandFeatureUnsupported
+            );
+
+            let mut command = DriverCommandMemoryWrite {
+                directory_table_type,
+                ..Default::default()
+            };
+            assert_eq!(
+                self::execute(&registry, &mut command),
+                CommandResult::CommandFeatureUnsupported
+            );
+        }
+    }
+}
@@ -1,77 +1,54 @@
-use core::slice;
-use std::mem;
-
-use anyhow::Context;
 use vtd_protocol::{
     command::{
         DriverCommandProcessList,
         DriverCommandProcessModules,
     },
-    types::{
-        ProcessId,
-        ProcessInfo,
-        ProcessModuleInfo,
-    },
+    types::ProcessId,
+    validation,
 };
-use windows::Win32::{
-    Foundation::{
-        HANDLE,
-        HMODULE,
+
+use crate::{
+    policy::{
+        self,
+        ProcessDetails,
+        SystemProcessDetails,
     },
-    System::{
-        ProcessStatus::{
-            GetModuleBaseNameA,
-            GetModuleInformation,
-        },
-        Threading::{
-            PROCESS_QUERY_INFORMATION,
-            PROCESS_VM_READ,
-        },
+    process::{
+        self,
+        ProcessEntry,
     },
 };
 
-use crate::util::{
-    self,
-    list_process_modules,
-    list_system_process_ids,
-};
+/// Process details of a process within the snapshot
+struct SnapshotProcessDetails<'a> {
+    process: &'a ProcessEntry,
+}
 
-fn fill_process_info(process_id: ProcessId, output: &mut ProcessInfo) -> anyhow::Result<()> {
-    let process = util::open_process_by_id(process_id, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ)
-        .context("open process")?;
-
-    let modules = list_process_modules(&process, Some(1)).context("list modules")?;
-    let main_module = modules.first().context("missing main module")?;
-
-    let name_length = unsafe {
-        GetModuleBaseNameA(
-            process.raw_handle(),
-            *main_module,
-            &mut output.image_base_name,
-        )
-    } as usize;
-    if name_length == 0 {
-        anyhow::bail!("failed to get module base name");
+impl ProcessDetails for SnapshotProcessDetails<'_> {
+    fn process_name(&self, _process_id: ProcessId) -> Option<String> {
+        Some(self.process.name.clone())
     }
 
-    if name_length < output.image_base_name.len() - 1 {
-        output.image_base_name[name_length] = 0x00;
+    fn user_name(&self, process_id: ProcessId) -> Option<String> {
+        SystemProcessDetails.user_name(process_id)
     }
-    output.process_id = process_id;
-    output.directory_table_base = 0;
-    Ok(())
 }
 
 pub fn get_processes(command: &mut DriverCommandProcessList) -> anyhow::Result<()> {
-    let processes = list_system_process_ids()?;
-    let buffer = unsafe { slice::from_raw_parts_mut(command.buffer, command.buffer_capacity) };
+    let processes = process::list_processes()?;
+    let buffer = unsafe { validation::buffer_slice_mut(command.buffer, command.buffer_capacity) };
+
+    let policy = policy::current();
+    for process in processes.iter() {
+        if !policy.is_allowed(process.process_id, &SnapshotProcessDetails { process }) {
+            /* denied processes are hidden from the process list */
+            continue;
+        }
 
-    for process_id in processes {
         if let Some(output) = buffer.get_mut(command.process_count) {
-            if let Err(err) = self::fill_process_info(process_id, output) {
-                log::debug!("Failed to fill process info for {}: {}", process_id, err);
-                continue;
-            }
+            output.process_id = process.process_id;
+            output.set_image_base_name(&process.name);
+            output.directory_table_base = 0;
         }
 
         command.process_count += 1;
@@ -80,44 +57,15 @@ pub fn get_processes(command: &mut DriverCommandProcessList) -> anyhow::Result<(
     Ok(())
 }
 
-fn fill_module_info(
-    hprocess: HANDLE,
-    hmodule: HMODULE,
-    output: &mut ProcessModuleInfo,
-) -> anyhow::Result<()> {
-    let bytes_copied =
-        unsafe { GetModuleBaseNameA(hprocess, hmodule, &mut output.base_dll_name) } as usize;
-    if bytes_copied == 0 {
-        anyhow::bail!("GetModuleFileNameExA failed");
-    }
-
-    let mut module_info = Default::default();
-    let success = unsafe {
-        GetModuleInformation(
-            hprocess,
-            hmodule,
-            &mut module_info,
-            mem::size_of_val(&module_info) as u32,
-        )
-    };
-    if !success.as_bool() {
-        anyhow::bail!("GetModuleInformation failed");
-    }
-
-    output.module_size = module_info.SizeOfImage as u64;
-    output.base_address = module_info.lpBaseOfDll as u64;
-    Ok(())
-}
-
 pub fn get_modules(command: &mut DriverCommandProcessModules) -> anyhow::Result<()> {
-    let process = match util::open_process_by_id(
-        command.process_id,
-        PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
-    ) {
-        Ok(handle) => handle,
+    super::ensure_default_directory_table(&command.directory_table_type)?;
+    policy::check_process(command.process_id)?;
+
+    let process = match process::open_process_modules(command.process_id) {
+        Ok(process) => process,
         Err(err) => {
             log::warn!(
-                "Failed to open process {} for enumeration: {}",
+                "Failed to open process {} for enumeration: {:#}",
                 command.process_id,
                 err
             );
@@ -126,22 +74,17 @@ pub fn get_modules(command: &mut DriverCommandProcessModules) -> anyhow::Result<
         }
     };
 
-    let modules = util::list_process_modules(&process, None)?;
+    let modules = process.list()?;
 
     let module_buffer =
-        unsafe { slice::from_raw_parts_mut(command.buffer, command.buffer_capacity) };
+        unsafe { validation::buffer_slice_mut(command.buffer, command.buffer_capacity) };
 
     command.process_unknown = false;
-    for hmodule in modules.iter() {
+    for module in modules.iter() {
         if let Some(output) = module_buffer.get_mut(command.module_count) {
-            if let Err(err) = self::fill_module_info(process.raw_handle(), *hmodule, output) {
-                log::debug!(
-                    "Failed to fill process module info for {:X}: {}",
-                    hmodule.0,
-                    err
-                );
-                continue;
-            }
+            output.set_base_dll_name(&module.name);
+            output.base_address = module.base_address;
+            output.module_size = module.module_size;
         }
 
         command.module_count += 1;
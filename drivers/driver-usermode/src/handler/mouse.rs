@@ -1,9 +1,11 @@
-use core::slice;
 use std::mem;
 
-use vtd_protocol::command::{
-    DriverCommandInputMouse,
-    MouseState,
+use vtd_protocol::{
+    command::{
+        DriverCommandInputMouse,
+        MouseState,
+    },
+    validation,
 };
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     SendInput,
@@ -23,7 +25,7 @@ use windows::Win32::UI::Input::KeyboardAndMouse::{
 };
 
 pub fn mouse_move(command: &mut DriverCommandInputMouse) -> anyhow::Result<()> {
-    let states = unsafe { slice::from_raw_parts(command.buffer, command.state_count) };
+    let states = unsafe { validation::buffer_slice(command.buffer, command.state_count) };
     let inputs = states.iter().map(mouse_state_to_input).collect::<Vec<_>>();
 
     unsafe { SendInput(&inputs, mem::size_of::<INPUT>() as i32) };
@@ -1,60 +1,19 @@
-use std::ptr;
-
 use vtd_protocol::{
     command::DriverCommandMemoryRead,
-    types::{
-        DirectoryTableType,
-        MemoryAccessResult,
-    },
-};
-use windows::Win32::{
-    Foundation::{
-        HANDLE,
-        NTSTATUS,
-    },
-    System::Threading::PROCESS_VM_READ,
+    validation,
 };
 
-use crate::util;
-
-extern "C" {
-    fn NtReadVirtualMemory(
-        ProcessHandle: HANDLE,
-        BaseAddress: *const (),
-        Buffer: *const (),
-        NumberOfBytesToRead: u32,
-        NumberOfBytesReaded: *mut u32,
-    ) -> NTSTATUS;
-}
+use crate::{
+    memory,
+    policy,
+};
 
 pub fn read(command: &mut DriverCommandMemoryRead) -> anyhow::Result<()> {
-    if !matches!(command.directory_table_type, DirectoryTableType::Default) {
-        anyhow::bail!("unsupported memory read type");
-    }
+    super::ensure_default_directory_table(&command.directory_table_type)?;
 
-    let read_buffer = unsafe { core::slice::from_raw_parts_mut(command.buffer, command.count) };
-    let process = match util::open_process_by_id(command.process_id as u32, PROCESS_VM_READ) {
-        Ok(handle) => handle,
-        Err(err) => {
-            log::warn!("Failed to open process {}: {}", command.process_id, err);
-            command.result = MemoryAccessResult::ProcessUnknown;
-            return Ok(());
-        }
-    };
+    policy::check_process(command.process_id)?;
 
-    let status = unsafe {
-        NtReadVirtualMemory(
-            process.raw_handle(),
-            command.address as *const (),
-            read_buffer.as_mut_ptr() as *mut (),
-            read_buffer.len() as u32,
-            ptr::null_mut(),
-        )
-    };
-    if status.is_ok() {
-        command.result = MemoryAccessResult::Success;
-    } else {
-        command.result = MemoryAccessResult::PartialSuccess { bytes_copied: 0 };
-    }
+    let read_buffer = unsafe { validation::buffer_slice_mut(command.buffer, command.count) };
+    command.result = memory::read(command.process_id, command.address, read_buffer);
     Ok(())
 }
@@ -0,0 +1,19 @@
+use vtd_protocol::{
+    command::DriverCommandMemoryWrite,
+    validation,
+};
+
+use crate::{
+    memory,
+    policy,
+};
+
+pub fn write(command: &mut DriverCommandMemoryWrite) -> anyhow::Result<()> {
+    super::ensure_default_directory_table(&command.directory_table_type)?;
+
+    policy::check_process(command.process_id)?;
+
+    let write_buffer = unsafe { validation::buffer_slice(command.buffer, command.count) };
+    command.result = memory::write(command.process_id, command.address, write_buffer);
+    Ok(())
+}
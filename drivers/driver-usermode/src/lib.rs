@@ -1,14 +1,32 @@
 #![feature(inherent_str_constructors)]
+#![cfg_attr(test, feature(test))]
 
-use core::slice;
+use std::{
+    env,
+    time::Duration,
+};
 
 use lazy_static::lazy_static;
 use registry::HandlerRegistry;
+use vtd_protocol::{
+    utils::str_to_fixed_buffer,
+    validation,
+    CommandResult,
+};
 
+#[cfg(any(test, fuzzing))]
+pub mod fuzzing;
+#[cfg(windows)]
 mod handle;
 mod handler;
+mod memory;
 mod metrics;
+mod middleware;
+mod policy;
+mod process;
 mod registry;
+mod unwind;
+#[cfg(windows)]
 mod util;
 
 lazy_static! {
@@ -22,26 +40,62 @@ fn init_request_handler() -> HandlerRegistry {
     handler.register(&handler::get_processes);
     handler.register(&handler::get_modules);
     handler.register(&handler::read);
-    handler.register(&handler::mouse_move);
-    handler.register(&handler::keyboard_state);
+    handler.register(&handler::write);
+    #[cfg(windows)]
+    {
+        handler.register(&handler::mouse_move);
+        handler.register(&handler::keyboard_state);
+    }
     handler.register(&handler::metrics_report_send);
     handler.register(&handler::metrics_flush);
 
+    handler.add_middleware(middleware::AuditLog);
+    handler.add_middleware(middleware::ReadOnlyPolicy);
+    handler.add_middleware(middleware::LatencyMeasurement::new(Duration::from_millis(
+        100,
+    )));
+    if let Some(calls_per_second) = self::rate_limit_from_env() {
+        handler.add_middleware(middleware::RateLimit::new(calls_per_second));
+    }
+
     handler
 }
 
+/// Maximum number of calls per second and command configured via `VT_RATE_LIMIT`
+fn rate_limit_from_env() -> Option<u32> {
+    let value = env::var("VT_RATE_LIMIT").ok()?;
+    match value.parse::<u32>() {
+        Ok(0) => None,
+        Ok(value) => Some(value),
+        Err(_) => {
+            log::warn!("Ignoring invalid VT_RATE_LIMIT value {}", value);
+            None
+        }
+    }
+}
+
 #[no_mangle]
 unsafe extern "C" fn startup() {
-    env_logger::init();
+    let _ = unwind::catch_panic("startup", || {
+        let _ = env_logger::try_init();
+        policy::load_from_env();
 
-    use crate::metrics;
-    metrics::maybe_init();
+        use crate::metrics;
+        metrics::maybe_init();
+    });
 }
 
 #[no_mangle]
 unsafe extern "C" fn teardown() {
-    use crate::metrics;
-    metrics::shutdown();
+    let _ = unwind::catch_panic("teardown", || {
+        memory::report_cache_statistics();
+        memory::clear_cache();
+
+        use crate::metrics;
+        metrics::shutdown();
+
+        middleware::log_latency_summary();
+    });
 }
 
 #[no_mangle]
@@ -60,10 +114,61 @@ extern "C" fn execute_command(
     error_message: *mut u8,
     error_message_length: usize,
 ) -> u64 {
-    let payload = unsafe { slice::from_raw_parts_mut(payload, payload_length) };
-    let error_message = unsafe { slice::from_raw_parts_mut(error_message, error_message_length) };
+    unsafe {
+        self::dispatch_command(
+            &REQUEST_HANDLER,
+            command_id,
+            payload,
+            payload_length,
+            error_message,
+            error_message_length,
+        )
+    }
+}
+
+/// Execute a raw command using the given registry.
+/// Panics are caught and mark the driver as degraded.
+///
+/// # Safety
+/// Non null buffers must be valid for the given length.
+unsafe fn dispatch_command(
+    registry: &HandlerRegistry,
+    command_id: u32,
+
+    payload: *mut u8,
+    payload_length: usize,
+
+    error_message: *mut u8,
+    error_message_length: usize,
+) -> u64 {
+    if validation::validate_buffer("error_message", error_message, error_message_length).is_err() {
+        return CommandResult::CommandParameterInvalid.bits();
+    }
+    let error_message = validation::buffer_slice_mut(error_message, error_message_length);
+
+    if let Err(error) = validation::validate_buffer("payload", payload, payload_length) {
+        str_to_fixed_buffer(error_message, &error.to_string());
+        return CommandResult::CommandParameterInvalid.bits();
+    }
+    let payload = validation::buffer_slice_mut(payload, payload_length);
+
+    if let Some(reason) = unwind::degraded_reason() {
+        str_to_fixed_buffer(
+            error_message,
+            &format!("driver degraded due to a previous panic: {}", reason),
+        );
+        return CommandResult::Error.bits();
+    }
+
+    let result = unwind::catch_panic("execute_command", || {
+        registry.handle(command_id, payload, error_message)
+    });
 
-    REQUEST_HANDLER
-        .handle(command_id, payload, error_message)
-        .bits()
+    match result {
+        Ok(result) => result.bits(),
+        Err(message) => {
+            str_to_fixed_buffer(error_message, &format!("driver panicked: {}", message));
+            CommandResult::Error.bits()
+        }
+    }
 }
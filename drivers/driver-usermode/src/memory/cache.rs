@@ -0,0 +1,262 @@
+use std::sync::{
+    atomic::{
+        AtomicU64,
+        Ordering,
+    },
+    Arc,
+    Mutex,
+    MutexGuard,
+};
+
+use vtd_protocol::types::ProcessId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    Read,
+    Write,
+}
+
+struct CacheEntry<H> {
+    process_id: ProcessId,
+    mode: AccessMode,
+
+    /// Creation time of the process at the time the handle has been opened
+    creation_time: u64,
+    handle: Arc<H>,
+
+    last_used: u64,
+}
+
+struct CacheEntries<H> {
+    entries: Vec<CacheEntry<H>>,
+    tick: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStatistics {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub invalidations: u64,
+}
+
+/// Cache for open process handles with LRU eviction.
+///
+/// Handles are bound to the process they have been opened for.
+/// As process ids may be reused, handles must be invalidated via [HandleCache::invalidate_stale]
+/// if the creation time of the process owning the process id changed.
+pub struct HandleCache<H> {
+    capacity: usize,
+    entries: Mutex<CacheEntries<H>>,
+
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl<H> HandleCache<H> {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(CacheEntries {
+                entries: Vec::new(),
+                tick: 0,
+            }),
+
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<CacheEntries<H>> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Get the cached handle for the process or open a new one.
+    /// Returns the handle and the creation time of the process it has been opened for.
+    pub fn get_or_open<E>(
+        &self,
+        process_id: ProcessId,
+        mode: AccessMode,
+        open: impl FnOnce() -> Result<(H, u64), E>,
+    ) -> Result<(Arc<H>, u64), E> {
+        {
+            let mut entries = self.entries();
+            entries.tick += 1;
+
+            let tick = entries.tick;
+            if let Some(entry) = entries
+                .entries
+                .iter_mut()
+                .find(|entry| entry.process_id == process_id && entry.mode == mode)
+            {
+                entry.last_used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok((entry.handle.clone(), entry.creation_time));
+            }
+        }
+
+        /* do not block other accesses while opening the handle */
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let (handle, creation_time) = open()?;
+        let handle = Arc::new(handle);
+
+        let mut entries = self.entries();
+        entries
+            .entries
+            .retain(|entry| entry.process_id != process_id || entry.mode != mode);
+
+        if entries.entries.len() >= self.capacity {
+            if let Some((index, _)) = entries
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_used)
+            {
+                entries.entries.swap_remove(index);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let last_used = entries.tick;
+        if self.capacity > 0 {
+            entries.entries.push(CacheEntry {
+                process_id,
+                mode,
+
+                creation_time,
+                handle: handle.clone(),
+
+                last_used,
+            });
+        }
+
+        Ok((handle, creation_time))
+    }
+
+    /// Remove all handles of the process which have been opened for a different process instance.
+    /// `creation_time` is the creation time of the process currently owning the process id
+    /// or `None` if the process has exited.
+    ///
+    /// Returns true if any handle has been removed.
+    pub fn invalidate_stale(&self, process_id: ProcessId, creation_time: Option<u64>) -> bool {
+        let mut entries = self.entries();
+        let length = entries.entries.len();
+        entries.entries.retain(|entry| {
+            entry.process_id != process_id || Some(entry.creation_time) == creation_time
+        });
+
+        let removed = (length - entries.entries.len()) as u64;
+        self.invalidations.fetch_add(removed, Ordering::Relaxed);
+        removed > 0
+    }
+
+    pub fn clear(&self) {
+        self.entries().entries.clear();
+    }
+
+    /// Take the statistics collected since the last call
+    pub fn take_statistics(&self) -> CacheStatistics {
+        CacheStatistics {
+            hits: self.hits.swap(0, Ordering::Relaxed),
+            misses: self.misses.swap(0, Ordering::Relaxed),
+            evictions: self.evictions.swap(0, Ordering::Relaxed),
+            invalidations: self.invalidations.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use super::{
+        AccessMode,
+        CacheStatistics,
+        HandleCache,
+    };
+
+    fn open(cache: &HandleCache<u32>, process_id: u32, mode: AccessMode) -> u32 {
+        let (handle, _) = cache
+            .get_or_open(process_id, mode, || {
+                Ok::<_, Infallible>((process_id * 10, process_id as u64 * 100))
+            })
+            .unwrap();
+        *handle
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let cache = HandleCache::new(4);
+        assert_eq!(self::open(&cache, 1, AccessMode::Read), 10);
+        assert_eq!(self::open(&cache, 1, AccessMode::Read), 10);
+        assert_eq!(self::open(&cache, 1, AccessMode::Write), 10);
+        assert_eq!(self::open(&cache, 2, AccessMode::Read), 20);
+
+        assert!(cache
+            .get_or_open(3, AccessMode::Read, || Err("failed"))
+            .is_err());
+
+        assert_eq!(cache.entries().entries.len(), 3);
+        assert_eq!(
+            cache.take_statistics(),
+            CacheStatistics {
+                hits: 1,
+                misses: 4,
+                ..Default::default()
+            }
+        );
+        assert_eq!(cache.take_statistics(), CacheStatistics::default());
+
+        cache.clear();
+        assert_eq!(cache.entries().entries.len(), 0);
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let cache = HandleCache::new(2);
+        self::open(&cache, 1, AccessMode::Read);
+        self::open(&cache, 2, AccessMode::Read);
+        self::open(&cache, 1, AccessMode::Read);
+
+        /* process 2 is the least recently used */
+        self::open(&cache, 3, AccessMode::Read);
+        assert_eq!(cache.entries().entries.len(), 2);
+
+        cache.take_statistics();
+        self::open(&cache, 1, AccessMode::Read);
+        self::open(&cache, 2, AccessMode::Read);
+        assert_eq!(
+            cache.take_statistics(),
+            CacheStatistics {
+                hits: 1,
+                misses: 1,
+                evictions: 1,
+                invalidations: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn invalidation() {
+        let cache = HandleCache::new(4);
+        self::open(&cache, 1, AccessMode::Read);
+        self::open(&cache, 1, AccessMode::Write);
+        self::open(&cache, 2, AccessMode::Read);
+
+        /* process still running */
+        assert!(!cache.invalidate_stale(1, Some(100)));
+
+        /* process id reused */
+        assert!(cache.invalidate_stale(1, Some(101)));
+        assert_eq!(cache.entries().entries.len(), 1);
+
+        /* process exited */
+        assert!(cache.invalidate_stale(2, None));
+        assert_eq!(cache.entries().entries.len(), 0);
+        assert_eq!(cache.take_statistics().invalidations, 3);
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        ErrorKind,
+    },
+    os::unix::fs::FileExt,
+};
+
+use anyhow::Context;
+use vtd_protocol::types::{
+    MemoryAccessResult,
+    ProcessId,
+};
+
+use super::AccessMode;
+
+/// Start time of the process in clock ticks after boot.
+/// Returns `None` if the process does not exist or has exited.
+pub fn process_creation_time(process_id: ProcessId) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", process_id)).ok()?;
+
+    /* the command name may contain spaces and parentheses */
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace();
+
+    let state = fields.next()?;
+    if state == "Z" || state == "X" {
+        return None;
+    }
+
+    /* starttime is the 22nd field, state the 3rd */
+    fields.nth(18)?.parse().ok()
+}
+
+/// Copy `length` bytes starting at `address`.
+/// The kernel stops copying at the first inaccessible page and reports the bytes copied until then.
+fn transfer(
+    address: u64,
+    length: usize,
+    mut copy: impl FnMut(u64, usize) -> io::Result<usize>,
+) -> MemoryAccessResult {
+    let mut bytes_copied = 0;
+    while bytes_copied < length {
+        let Some(position) = address.checked_add(bytes_copied as u64) else {
+            break;
+        };
+
+        match copy(position, bytes_copied) {
+            Ok(0) => break,
+            Ok(count) => bytes_copied += count,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => {
+                log::trace!("Memory access at {:X} failed: {}", position, err);
+                break;
+            }
+        }
+    }
+
+    if bytes_copied == length {
+        MemoryAccessResult::Success
+    } else {
+        MemoryAccessResult::PartialSuccess { bytes_copied }
+    }
+}
+
+/// The memory of a process accessed through `/proc/<pid>/mem`
+pub struct ProcessMemory {
+    memory: File,
+}
+
+impl ProcessMemory {
+    /// Open the process memory.
+    /// Returns the memory and the creation time of the process.
+    pub fn open(process_id: ProcessId, mode: AccessMode) -> anyhow::Result<(Self, u64)> {
+        let memory = OpenOptions::new()
+            .read(mode == AccessMode::Read)
+            .write(mode == AccessMode::Write)
+            .open(format!("/proc/{}/mem", process_id))?;
+
+        let creation_time =
+            self::process_creation_time(process_id).context("process has exited")?;
+        Ok((Self { memory }, creation_time))
+    }
+
+    pub fn read(&self, address: u64, buffer: &mut [u8]) -> MemoryAccessResult {
+        self::transfer(address, buffer.len(), |position, offset| {
+            self.memory.read_at(&mut buffer[offset..], position)
+        })
+    }
+
+    pub fn write(&self, address: u64, buffer: &[u8]) -> MemoryAccessResult {
+        self::transfer(address, buffer.len(), |position, offset| {
+            self.memory.write_at(&buffer[offset..], position)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        process::{
+            Child,
+            Command,
+        },
+    };
+
+    use vtd_protocol::{
+        command::DriverCommandMemoryWrite,
+        types::MemoryAccessResult,
+    };
+
+    use crate::{
+        handler,
+        memory,
+    };
+
+    /// Child process which will be killed when dropped
+    struct TestProcess(Child);
+
+    impl TestProcess {
+        fn spawn() -> Self {
+            Self(Command::new("sleep").arg("60").spawn().unwrap())
+        }
+
+        fn id(&self) -> u32 {
+            self.0.id()
+        }
+
+        /// Find a writable mapping which is not directly followed by another mapping
+        fn writable_mapping_end(&self) -> u64 {
+            let maps = fs::read_to_string(format!("/proc/{}/maps", self.id())).unwrap();
+            let mappings = maps
+                .lines()
+                .map(|line| {
+                    let mut columns = line.split_whitespace();
+                    let (start, end) = columns.next().unwrap().split_once('-').unwrap();
+                    let permissions = columns.next().unwrap().to_string();
+                    (
+                        u64::from_str_radix(start, 16).unwrap(),
+                        u64::from_str_radix(end, 16).unwrap(),
+                        permissions,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            mappings
+                .iter()
+                .enumerate()
+                .find(|(index, (_, end, permissions))| {
+                    permissions.starts_with("rw")
+                        && mappings
+                            .get(index + 1)
+                            .is_none_or(|(next_start, _, _)| next_start != end)
+                })
+                .map(|(_, (_, end, _))| *end)
+                .expect("writable mapping")
+        }
+    }
+
+    impl Drop for TestProcess {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn write(process_id: u32, address: u64, buffer: &[u8]) -> MemoryAccessResult {
+        let mut command = DriverCommandMemoryWrite {
+            process_id,
+            address,
+            buffer: buffer.as_ptr(),
+            count: buffer.len(),
+            ..Default::default()
+        };
+
+        handler::write(&mut command).unwrap();
+        command.result
+    }
+
+    #[test]
+    fn write_child_process() {
+        let process = TestProcess::spawn();
+        let address = process.writable_mapping_end() - 0x10;
+
+        let value = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+        assert_eq!(
+            self::write(process.id(), address, &value),
+            MemoryAccessResult::Success
+        );
+
+        let mut buffer = [0u8; 8];
+        assert_eq!(
+            memory::read(process.id(), address, &mut buffer),
+            MemoryAccessResult::Success
+        );
+        assert_eq!(buffer, value);
+    }
+
+    #[test]
+    fn partial_write() {
+        let process = TestProcess::spawn();
+        let address = process.writable_mapping_end() - 0x04;
+
+        assert_eq!(
+            self::write(process.id(), address, &[0xAA; 8]),
+            MemoryAccessResult::PartialSuccess { bytes_copied: 4 }
+        );
+
+        let mut buffer = [0u8; 8];
+        assert_eq!(
+            memory::read(process.id(), address, &mut buffer),
+            MemoryAccessResult::PartialSuccess { bytes_copied: 4 }
+        );
+        assert_eq!(buffer[0..4], [0xAA; 4]);
+
+        assert_eq!(
+            self::write(process.id(), address + 0x04, &[0xAA; 8]),
+            MemoryAccessResult::PartialSuccess { bytes_copied: 0 }
+        );
+    }
+
+    #[test]
+    fn unknown_process() {
+        let mut process = TestProcess::spawn();
+        let process_id = process.id();
+        process.0.kill().unwrap();
+        process.0.wait().unwrap();
+
+        assert_eq!(
+            self::write(process_id, 0x1000, &[0x00; 4]),
+            MemoryAccessResult::ProcessUnknown
+        );
+    }
+
+    #[test]
+    fn exited_process() {
+        let mut process = TestProcess::spawn();
+        let process_id = process.id();
+        let address = process.writable_mapping_end() - 0x10;
+
+        let mut buffer = [0u8; 8];
+        assert_eq!(
+            memory::read(process_id, address, &mut buffer),
+            MemoryAccessResult::Success
+        );
+
+        /* the cached handle must not be used for the exited process */
+        process.0.kill().unwrap();
+        process.0.wait().unwrap();
+        assert_eq!(
+            memory::read(process_id, address, &mut buffer),
+            MemoryAccessResult::ProcessUnknown
+        );
+    }
+}
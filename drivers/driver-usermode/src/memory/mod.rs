@@ -0,0 +1,104 @@
+//! Access to the virtual memory of other processes.
+//!
+//! Windows uses `NtReadVirtualMemory` / `NtWriteVirtualMemory`,
+//! Linux accesses the memory through `/proc/<pid>/mem`.
+//! The process handles (file descriptors on Linux) are kept open within a [HandleCache].
+use vtd_protocol::types::{
+    MemoryAccessResult,
+    ProcessId,
+};
+
+mod cache;
+pub use cache::*;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+use linux as platform;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+use self::windows as platform;
+use crate::metrics;
+
+/// Maximum number of process handles kept open
+const HANDLE_CACHE_CAPACITY: usize = 32;
+
+static HANDLE_CACHE: HandleCache<platform::ProcessMemory> = HandleCache::new(HANDLE_CACHE_CAPACITY);
+
+/// Execute the memory access using a cached process handle.
+///
+/// Failed accesses may be caused by a cached handle of a process which exited.
+/// In that case the handle will be invalidated and the access will be retried once.
+fn access(
+    process_id: ProcessId,
+    mode: AccessMode,
+    mut callback: impl FnMut(&platform::ProcessMemory) -> MemoryAccessResult,
+) -> MemoryAccessResult {
+    for _ in 0..2 {
+        let (memory, creation_time) = match HANDLE_CACHE.get_or_open(process_id, mode, || {
+            platform::ProcessMemory::open(process_id, mode)
+        }) {
+            Ok(memory) => memory,
+            Err(err) => {
+                log::warn!("Failed to open process {}: {:#}", process_id, err);
+                return MemoryAccessResult::ProcessUnknown;
+            }
+        };
+
+        let result = callback(&memory);
+        if matches!(result, MemoryAccessResult::Success) {
+            return result;
+        }
+
+        let current_creation_time = platform::process_creation_time(process_id);
+        if current_creation_time == Some(creation_time) {
+            /* the handle is still valid */
+            return result;
+        }
+
+        HANDLE_CACHE.invalidate_stale(process_id, current_creation_time);
+        if current_creation_time.is_none() {
+            return MemoryAccessResult::ProcessUnknown;
+        }
+    }
+
+    MemoryAccessResult::ProcessUnknown
+}
+
+/// Read the memory of the target process into the buffer
+pub fn read(process_id: ProcessId, address: u64, buffer: &mut [u8]) -> MemoryAccessResult {
+    self::access(process_id, AccessMode::Read, |memory| {
+        memory.read(address, buffer)
+    })
+}
+
+/// Write the buffer into the memory of the target process
+pub fn write(process_id: ProcessId, address: u64, buffer: &[u8]) -> MemoryAccessResult {
+    self::access(process_id, AccessMode::Write, |memory| {
+        memory.write(address, buffer)
+    })
+}
+
+/// Report the handle cache statistics since the last report
+pub fn report_cache_statistics() {
+    let statistics = HANDLE_CACHE.take_statistics();
+    if statistics == CacheStatistics::default() {
+        return;
+    }
+
+    log::debug!("Handle cache statistics: {:?}", statistics);
+    metrics::add_record(
+        "handle-cache",
+        format!(
+            "hits: {}, misses: {}, evictions: {}, invalidations: {}",
+            statistics.hits, statistics.misses, statistics.evictions, statistics.invalidations
+        ),
+    );
+}
+
+/// Close all cached process handles
+pub fn clear_cache() {
+    HANDLE_CACHE.clear();
+}
@@ -0,0 +1,134 @@
+use anyhow::Context;
+use vtd_protocol::types::{
+    MemoryAccessResult,
+    ProcessId,
+};
+use windows::Win32::{
+    Foundation::{
+        FILETIME,
+        HANDLE,
+        NTSTATUS,
+    },
+    System::Threading::{
+        GetProcessTimes,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+        PROCESS_VM_OPERATION,
+        PROCESS_VM_READ,
+        PROCESS_VM_WRITE,
+    },
+};
+
+use super::AccessMode;
+use crate::{
+    handle::OwnedHandle,
+    util,
+};
+
+extern "C" {
+    fn NtReadVirtualMemory(
+        ProcessHandle: HANDLE,
+        BaseAddress: *const (),
+        Buffer: *const (),
+        NumberOfBytesToRead: usize,
+        NumberOfBytesReaded: *mut usize,
+    ) -> NTSTATUS;
+
+    fn NtWriteVirtualMemory(
+        ProcessHandle: HANDLE,
+        BaseAddress: *const (),
+        Buffer: *const (),
+        NumberOfBytesToWrite: usize,
+        NumberOfBytesWritten: *mut usize,
+    ) -> NTSTATUS;
+}
+
+fn filetime_to_u64(time: &FILETIME) -> u64 {
+    (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64
+}
+
+/// Creation time of the process referenced by the handle
+/// or `None` if the process has exited.
+fn handle_creation_time(process: &OwnedHandle) -> Option<u64> {
+    let mut creation_time = FILETIME::default();
+    let mut exit_time = FILETIME::default();
+    let mut kernel_time = FILETIME::default();
+    let mut user_time = FILETIME::default();
+    let success = unsafe {
+        GetProcessTimes(
+            process.raw_handle(),
+            &mut creation_time,
+            &mut exit_time,
+            &mut kernel_time,
+            &mut user_time,
+        )
+    };
+    if !success.as_bool() || self::filetime_to_u64(&exit_time) != 0 {
+        return None;
+    }
+
+    Some(self::filetime_to_u64(&creation_time))
+}
+
+/// Creation time of the process owning the process id
+/// or `None` if the process does not exist or has exited.
+pub fn process_creation_time(process_id: ProcessId) -> Option<u64> {
+    let process = util::open_process_by_id(process_id, PROCESS_QUERY_LIMITED_INFORMATION).ok()?;
+    self::handle_creation_time(&process)
+}
+
+fn access_result(status: NTSTATUS, bytes_copied: usize) -> MemoryAccessResult {
+    if status.is_ok() {
+        MemoryAccessResult::Success
+    } else {
+        MemoryAccessResult::PartialSuccess { bytes_copied }
+    }
+}
+
+/// The memory of a process accessed through a process handle
+pub struct ProcessMemory {
+    process: OwnedHandle,
+}
+
+impl ProcessMemory {
+    /// Open the process memory.
+    /// Returns the memory and the creation time of the process.
+    pub fn open(process_id: ProcessId, mode: AccessMode) -> anyhow::Result<(Self, u64)> {
+        let access = match mode {
+            AccessMode::Read => PROCESS_VM_READ,
+            AccessMode::Write => PROCESS_VM_WRITE | PROCESS_VM_OPERATION,
+        };
+        let process =
+            util::open_process_by_id(process_id, access | PROCESS_QUERY_LIMITED_INFORMATION)?;
+
+        let creation_time = self::handle_creation_time(&process).context("process has exited")?;
+        Ok((Self { process }, creation_time))
+    }
+
+    pub fn read(&self, address: u64, buffer: &mut [u8]) -> MemoryAccessResult {
+        let mut bytes_read = 0;
+        let status = unsafe {
+            NtReadVirtualMemory(
+                self.process.raw_handle(),
+                address as *const (),
+                buffer.as_mut_ptr() as *mut (),
+                buffer.len(),
+                &mut bytes_read,
+            )
+        };
+        self::access_result(status, bytes_read)
+    }
+
+    pub fn write(&self, address: u64, buffer: &[u8]) -> MemoryAccessResult {
+        let mut bytes_written = 0;
+        let status = unsafe {
+            NtWriteVirtualMemory(
+                self.process.raw_handle(),
+                address as *const (),
+                buffer.as_ptr() as *const (),
+                buffer.len(),
+                &mut bytes_written,
+            )
+        };
+        self::access_result(status, bytes_written)
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use vtd_protocol::{
+    command::{
+        DriverCommand,
+        DriverCommandMemoryRead,
+        DriverCommandMemoryWrite,
+        DriverCommandProcessProtection,
+        COMMAND_ID_LIMIT,
+    },
+    CommandResult,
+};
+
+use crate::{
+    policy,
+    registry::{
+        CommandContext,
+        Middleware,
+        Next,
+    },
+};
+
+fn command_name(context: &CommandContext) -> &'static str {
+    context.command_name.unwrap_or("unknown command")
+}
+
+fn result_name(result: &CommandResult) -> &'static str {
+    result.name().unwrap_or("invalid result")
+}
+
+/// Logs every command and its result to the `audit` log target.
+/// Memory reads are issued at a high rate and therefore only logged at the trace level.
+pub struct AuditLog;
+
+impl AuditLog {
+    const TRACE_COMMANDS: [u32; 1] = [DriverCommandMemoryRead::COMMAND_ID];
+}
+
+impl Middleware for AuditLog {
+    fn handle(&self, context: &mut CommandContext, next: Next) -> CommandResult {
+        let result = next.run(context);
+        let level = if Self::TRACE_COMMANDS.contains(&context.command_id) {
+            log::Level::Trace
+        } else {
+            log::Level::Info
+        };
+
+        log::log!(
+            target: "audit",
+            level,
+            "{} ({:X}, {} bytes): {}",
+            self::command_name(context),
+            context.command_id,
+            context.payload.len(),
+            self::result_name(&result)
+        );
+        result
+    }
+}
+
+struct CommandLatency {
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+static COMMAND_LATENCY: [CommandLatency; COMMAND_ID_LIMIT as usize] = [const {
+    CommandLatency {
+        count: AtomicU64::new(0),
+        total_nanos: AtomicU64::new(0),
+        max_nanos: AtomicU64::new(0),
+    }
+}; COMMAND_ID_LIMIT as usize];
+
+/// Measures the execution time of every command.
+/// Commands exceeding the threshold will be logged as slow.
+pub struct LatencyMeasurement {
+    slow_threshold: Duration,
+}
+
+impl LatencyMeasurement {
+    pub fn new(slow_threshold: Duration) -> Self {
+        Self { slow_threshold }
+    }
+}
+
+impl Middleware for LatencyMeasurement {
+    fn handle(&self, context: &mut CommandContext, next: Next) -> CommandResult {
+        let start = Instant::now();
+        let result = next.run(context);
+        let elapsed = start.elapsed();
+
+        if let Some(latency) = COMMAND_LATENCY.get(context.command_id as usize) {
+            let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+            latency.count.fetch_add(1, Ordering::Relaxed);
+            latency.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+            latency.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        }
+
+        if elapsed > self.slow_threshold {
+            log::warn!(
+                "{} took {:#?} (threshold {:#?})",
+                self::command_name(context),
+                elapsed,
+                self.slow_threshold
+            );
+        } else {
+            log::trace!("{} took {:#?}", self::command_name(context), elapsed);
+        }
+
+        result
+    }
+}
+
+/// Log the execution time of all commands measured by [LatencyMeasurement]
+pub fn log_latency_summary() {
+    for (command_id, latency) in COMMAND_LATENCY.iter().enumerate() {
+        let count = latency.count.load(Ordering::Relaxed);
+        if count == 0 {
+            continue;
+        }
+
+        let total_nanos = latency.total_nanos.load(Ordering::Relaxed);
+        log::debug!(
+            "Command {:X}: {} calls, avg {:#?}, max {:#?}",
+            command_id,
+            count,
+            Duration::from_nanos(total_nanos / count),
+            Duration::from_nanos(latency.max_nanos.load(Ordering::Relaxed))
+        );
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Limits the number of calls per second for each command
+pub struct RateLimit {
+    calls_per_second: f64,
+    buckets: Mutex<Vec<TokenBucket>>,
+}
+
+impl RateLimit {
+    pub fn new(calls_per_second: u32) -> Self {
+        let calls_per_second = calls_per_second as f64;
+        let now = Instant::now();
+
+        Self {
+            calls_per_second,
+            buckets: Mutex::new(
+                (0..COMMAND_ID_LIMIT)
+                    .map(|_| TokenBucket {
+                        tokens: calls_per_second,
+                        last_refill: now,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn try_acquire(&self, command_id: u32) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(bucket) = buckets.get_mut(command_id as usize) else {
+            /* unknown commands will be rejected anyways */
+            return true;
+        };
+
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.calls_per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.calls_per_second);
+        bucket.last_refill = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+impl Middleware for RateLimit {
+    fn handle(&self, context: &mut CommandContext, next: Next) -> CommandResult {
+        if !self.try_acquire(context.command_id) {
+            log::warn!("Rate limit exceeded for {}", self::command_name(context));
+            return context.fail(
+                CommandResult::Error,
+                &format!(
+                    "rate limit of {} calls per second exceeded",
+                    self.calls_per_second
+                ),
+            );
+        }
+
+        next.run(context)
+    }
+}
+
+/// Rejects commands modifying the target process if the access policy is read only.
+/// Accesses to individual processes are checked by the handlers.
+pub struct ReadOnlyPolicy;
+
+impl ReadOnlyPolicy {
+    const WRITE_COMMANDS: [u32; 2] = [
+        DriverCommandMemoryWrite::COMMAND_ID,
+        DriverCommandProcessProtection::COMMAND_ID,
+    ];
+}
+
+impl Middleware for ReadOnlyPolicy {
+    fn handle(&self, context: &mut CommandContext, next: Next) -> CommandResult {
+        if Self::WRITE_COMMANDS.contains(&context.command_id) {
+            if let Err(error) = policy::check_write(self::command_name(context)) {
+                return context.fail(CommandResult::AccessDenied, &error.to_string());
+            }
+        }
+
+        next.run(context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        mem,
+        slice,
+    };
+
+    use vtd_protocol::{
+        command::DriverCommandMetricsFlush,
+        CommandResult,
+    };
+
+    use super::{
+        AuditLog,
+        RateLimit,
+    };
+    use crate::registry::HandlerRegistry;
+
+    fn flush(_command: &mut DriverCommandMetricsFlush) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn execute(registry: &HandlerRegistry, command_id: u32) -> CommandResult {
+        let mut command = DriverCommandMetricsFlush::default();
+        let payload = unsafe {
+            slice::from_raw_parts_mut(
+                &mut command as *mut _ as *mut u8,
+                mem::size_of::<DriverCommandMetricsFlush>(),
+            )
+        };
+
+        let mut error_buffer = [0u8; 0x100];
+        registry.handle(command_id, payload, &mut error_buffer)
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut registry = HandlerRegistry::new();
+        registry.register(&flush);
+        registry.add_middleware(AuditLog);
+        registry.add_middleware(RateLimit::new(2));
+
+        assert_eq!(self::execute(&registry, 0x09), CommandResult::Success);
+        assert_eq!(self::execute(&registry, 0x09), CommandResult::Success);
+        assert_eq!(self::execute(&registry, 0x09), CommandResult::Error);
+
+        /* limits are tracked per command */
+        assert_eq!(
+            self::execute(&registry, 0x0A),
+            CommandResult::CommandInvalid
+        );
+        assert_eq!(
+            self::execute(&registry, 0xFFFF),
+            CommandResult::CommandInvalid
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Target access policy.
+//!
+//! The policy restricts which processes the driver may access and is loaded on startup
+//! from the JSON file referenced by `VT_ACCESS_POLICY`:
+//! ```json
+//! {
+//!     "default": "deny",
+//!     "read_only": true,
+//!     "rules": [
+//!         { "action": "deny", "user": "NT AUTHORITY\\SYSTEM" },
+//!         { "action": "allow", "process_name": "cs2.exe" },
+//!         { "action": "allow", "process_id": 1234 }
+//!     ]
+//! }
+//! ```
+//! Rules are evaluated in order and the first rule matching the target process decides.
+//! A rule matches if all of its criteria match. Names are compared case insensitive and
+//! users without a domain match the user of any domain.
+//!
+//! Without a policy file every process can be accessed.
+//! An invalid policy file denies access to all processes.
+use std::{
+    cell::OnceCell,
+    env,
+    fmt,
+    fs,
+    path::Path,
+    sync::OnceLock,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+use vtd_protocol::types::ProcessId;
+
+use crate::process;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    #[default]
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub action: Action,
+
+    pub process_name: Option<String>,
+    pub process_id: Option<ProcessId>,
+    pub user: Option<String>,
+}
+
+impl Rule {
+    fn matches(&self, target: &Target) -> bool {
+        if let Some(process_id) = self.process_id {
+            if process_id != target.process_id {
+                return false;
+            }
+        }
+
+        if let Some(process_name) = &self.process_name {
+            match target.process_name() {
+                Some(name) if name.eq_ignore_ascii_case(process_name) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(user) = &self.user {
+            let Some(target_user) = target.user_name() else {
+                return false;
+            };
+
+            let target_user = if user.contains('\\') {
+                target_user
+            } else {
+                target_user
+                    .rsplit_once('\\')
+                    .map_or(target_user, |(_, user)| user)
+            };
+            if !target_user.eq_ignore_ascii_case(user) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Details of a process required to evaluate the policy
+pub trait ProcessDetails {
+    fn process_name(&self, process_id: ProcessId) -> Option<String>;
+    fn user_name(&self, process_id: ProcessId) -> Option<String>;
+}
+
+/// Resolves the process details using the operating system
+pub struct SystemProcessDetails;
+
+impl ProcessDetails for SystemProcessDetails {
+    fn process_name(&self, process_id: ProcessId) -> Option<String> {
+        process::image_name(process_id)
+            .inspect_err(|err| log::debug!("Failed to get name of process {}: {}", process_id, err))
+            .ok()
+    }
+
+    fn user_name(&self, process_id: ProcessId) -> Option<String> {
+        process::user_name(process_id)
+            .inspect_err(|err| log::debug!("Failed to get user of process {}: {}", process_id, err))
+            .ok()
+    }
+}
+
+/// The process being evaluated.
+/// Details are only resolved if a rule requires them.
+struct Target<'a> {
+    process_id: ProcessId,
+    details: &'a dyn ProcessDetails,
+
+    process_name: OnceCell<Option<String>>,
+    user_name: OnceCell<Option<String>>,
+}
+
+impl Target<'_> {
+    fn process_name(&self) -> Option<&str> {
+        self.process_name
+            .get_or_init(|| self.details.process_name(self.process_id))
+            .as_deref()
+    }
+
+    fn user_name(&self) -> Option<&str> {
+        self.user_name
+            .get_or_init(|| self.details.user_name(self.process_id))
+            .as_deref()
+    }
+}
+
+/// Error returned by handlers if the policy denies access.
+/// Reported to the client as [vtd_protocol::CommandResult::AccessDenied].
+#[derive(Debug)]
+pub struct AccessDenied {
+    message: String,
+}
+
+impl AccessDenied {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl fmt::Display for AccessDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AccessDenied {}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessPolicy {
+    /// Action if no rule matches
+    pub default: Action,
+
+    /// Deny all commands modifying the target process
+    pub read_only: bool,
+
+    pub rules: Vec<Rule>,
+}
+
+impl AccessPolicy {
+    pub fn deny_all() -> Self {
+        Self {
+            default: Action::Deny,
+            read_only: true,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let policy = fs::read_to_string(path).context("read policy")?;
+        serde_json::from_str(&policy).context("parse policy")
+    }
+
+    pub fn evaluate(&self, process_id: ProcessId, details: &dyn ProcessDetails) -> Action {
+        let target = Target {
+            process_id,
+            details,
+
+            process_name: OnceCell::new(),
+            user_name: OnceCell::new(),
+        };
+
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(&target))
+            .map_or(self.default, |rule| rule.action)
+    }
+
+    pub fn is_allowed(&self, process_id: ProcessId, details: &dyn ProcessDetails) -> bool {
+        self.evaluate(process_id, details) == Action::Allow
+    }
+}
+
+static POLICY: OnceLock<AccessPolicy> = OnceLock::new();
+
+/// Load the policy referenced by `VT_ACCESS_POLICY`
+pub fn load_from_env() {
+    let policy = match env::var_os("VT_ACCESS_POLICY") {
+        Some(path) => match AccessPolicy::load(Path::new(&path)) {
+            Ok(policy) => {
+                log::info!(
+                    "Loaded access policy from {} ({} rules, read only: {})",
+                    Path::new(&path).display(),
+                    policy.rules.len(),
+                    policy.read_only
+                );
+                policy
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to load access policy from {}: {:#}. Denying access to all processes.",
+                    Path::new(&path).display(),
+                    err
+                );
+                AccessPolicy::deny_all()
+            }
+        },
+        None => AccessPolicy::default(),
+    };
+
+    if POLICY.set(policy).is_err() {
+        log::warn!("Access policy has already been initialized");
+    }
+}
+
+/// The active policy
+pub fn current() -> &'static AccessPolicy {
+    POLICY.get_or_init(AccessPolicy::default)
+}
+
+/// Check if the process may be accessed.
+/// Denied accesses will be logged to the `audit` log target.
+pub fn check_process(process_id: ProcessId) -> Result<(), AccessDenied> {
+    if self::current().is_allowed(process_id, &SystemProcessDetails) {
+        return Ok(());
+    }
+
+    log::warn!(target: "audit", "Denied access to process {}", process_id);
+    Err(AccessDenied::new(format!(
+        "access to process {} denied by policy",
+        process_id
+    )))
+}
+
+/// Check if commands modifying the target process may be executed.
+/// Denied commands will be logged to the `audit` log target.
+pub fn check_write(command_name: &str) -> Result<(), AccessDenied> {
+    if !self::current().read_only {
+        return Ok(());
+    }
+
+    log::warn!(target: "audit", "Denied {} as the driver is read only", command_name);
+    Err(AccessDenied::new(format!(
+        "{} denied as the driver is read only",
+        command_name
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use vtd_protocol::types::ProcessId;
+
+    use super::{
+        AccessPolicy,
+        Action,
+        ProcessDetails,
+    };
+
+    struct TestProcesses;
+
+    impl ProcessDetails for TestProcesses {
+        fn process_name(&self, process_id: ProcessId) -> Option<String> {
+            match process_id {
+                1 => Some("cs2.exe".to_string()),
+                2 => Some("lsass.exe".to_string()),
+                _ => None,
+            }
+        }
+
+        fn user_name(&self, process_id: ProcessId) -> Option<String> {
+            match process_id {
+                1 => Some("LAB\\player".to_string()),
+                2 => Some("NT AUTHORITY\\SYSTEM".to_string()),
+                _ => None,
+            }
+        }
+    }
+
+    fn policy(policy: &str) -> AccessPolicy {
+        serde_json::from_str(policy).unwrap()
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy = AccessPolicy::default();
+        assert!(policy.is_allowed(1, &TestProcesses));
+        assert!(policy.is_allowed(3, &TestProcesses));
+        assert!(!policy.read_only);
+
+        assert!(!AccessPolicy::deny_all().is_allowed(1, &TestProcesses));
+    }
+
+    #[test]
+    fn rules() {
+        let policy = self::policy(
+            r#"{
+                "default": "deny",
+                "rules": [
+                    { "action": "deny", "process_name": "CS2.exe", "user": "other" },
+                    { "action": "allow", "process_name": "cs2.exe" },
+                    { "action": "allow", "process_id": 3 }
+                ]
+            }"#,
+        );
+        assert_eq!(policy.evaluate(1, &TestProcesses), Action::Allow);
+        assert_eq!(policy.evaluate(2, &TestProcesses), Action::Deny);
+        assert_eq!(policy.evaluate(3, &TestProcesses), Action::Allow);
+        assert_eq!(policy.evaluate(4, &TestProcesses), Action::Deny);
+
+        let policy = self::policy(
+            r#"{
+                "rules": [
+                    { "action": "deny", "user": "nt authority\\system" },
+                    { "action": "deny", "user": "PLAYER" }
+                ]
+            }"#,
+        );
+        assert_eq!(policy.evaluate(1, &TestProcesses), Action::Deny);
+        assert_eq!(policy.evaluate(2, &TestProcesses), Action::Deny);
+        assert_eq!(policy.evaluate(3, &TestProcesses), Action::Allow);
+    }
+
+    #[test]
+    fn invalid_policy() {
+        assert!(serde_json::from_str::<AccessPolicy>(r#"{ "default": "maybe" }"#).is_err());
+        assert!(serde_json::from_str::<AccessPolicy>(
+            r#"{ "rules": [{ "action": "allow", "process": "cs2.exe" }] }"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Benchmarks of the process listing against opening every process to resolve its name,
+//! which the process listing did before using a system snapshot.
+//!
+//! Run via `cargo bench -p driver-usermode process::bench`.
+extern crate test;
+
+use test::Bencher;
+
+use super::ProcessEntry;
+
+#[cfg(target_os = "linux")]
+fn list_processes_by_opening() -> Vec<ProcessEntry> {
+    use std::fs::{
+        self,
+        File,
+    };
+
+    let mut processes = Vec::new();
+    for entry in fs::read_dir("/proc").unwrap().flatten() {
+        let Some(process_id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+
+        if File::open(entry.path().join("mem")).is_err() {
+            continue;
+        }
+
+        let Some(name) = fs::read_link(entry.path().join("exe"))
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+        else {
+            continue;
+        };
+
+        processes.push(ProcessEntry { process_id, name });
+    }
+
+    processes
+}
+
+#[cfg(windows)]
+fn list_processes_by_opening() -> Vec<ProcessEntry> {
+    use std::mem;
+
+    use vtd_protocol::utils;
+    use windows::Win32::System::{
+        ProcessStatus::{
+            EnumProcesses,
+            GetModuleBaseNameA,
+        },
+        Threading::{
+            PROCESS_QUERY_INFORMATION,
+            PROCESS_VM_READ,
+        },
+    };
+
+    use crate::util;
+
+    let mut process_ids = vec![0u32; 0x1000];
+    let mut bytes_needed = 0;
+    unsafe {
+        EnumProcesses(
+            process_ids.as_mut_ptr(),
+            (process_ids.len() * mem::size_of::<u32>()) as u32,
+            &mut bytes_needed,
+        )
+    };
+    process_ids.truncate(bytes_needed as usize / mem::size_of::<u32>());
+
+    let mut processes = Vec::new();
+    for process_id in process_ids {
+        let Ok(process) =
+            util::open_process_by_id(process_id, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ)
+        else {
+            continue;
+        };
+
+        let Some(main_module) = util::list_process_modules(&process, Some(1))
+            .ok()
+            .and_then(|modules| modules.first().copied())
+        else {
+            continue;
+        };
+
+        let mut name = [0u8; 0x0F];
+        if unsafe { GetModuleBaseNameA(process.raw_handle(), main_module, &mut name) } == 0 {
+            continue;
+        }
+
+        processes.push(ProcessEntry {
+            process_id,
+            name: utils::fixed_buffer_to_str(&name)
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+
+    processes
+}
+
+#[test]
+fn snapshot_includes_all_processes() {
+    /* other tests spawn and reap processes concurrently, only check processes running throughout */
+    let opened_before = self::list_processes_by_opening();
+    let processes = super::list_processes().unwrap();
+    let opened_after = self::list_processes_by_opening();
+
+    assert!(processes
+        .iter()
+        .any(|process| process.process_id == std::process::id()));
+
+    for process in opened_before.iter().filter(|process| {
+        opened_after
+            .iter()
+            .any(|entry| entry.process_id == process.process_id)
+    }) {
+        assert!(
+            processes
+                .iter()
+                .any(|entry| entry.process_id == process.process_id),
+            "process {} ({}) is missing in the snapshot",
+            process.process_id,
+            process.name
+        );
+    }
+}
+
+#[bench]
+fn snapshot(bencher: &mut Bencher) {
+    bencher.iter(|| super::list_processes().unwrap());
+}
+
+#[bench]
+fn open_every_process(bencher: &mut Bencher) {
+    bencher.iter(self::list_processes_by_opening);
+}
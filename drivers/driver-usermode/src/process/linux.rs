@@ -0,0 +1,234 @@
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    os::unix::fs::FileExt,
+    path::Path,
+};
+
+use anyhow::Context;
+use vtd_protocol::types::ProcessId;
+
+use super::{
+    pe,
+    ModuleEntry,
+    ProcessEntry,
+};
+
+/// Get the executable file name of a process.
+/// Falls back to the (truncated) command name if the executable is not accessible.
+pub fn image_name(process_id: ProcessId) -> anyhow::Result<String> {
+    let process = Path::new("/proc").join(process_id.to_string());
+    if let Some(name) = fs::read_link(process.join("exe")).ok().and_then(|path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+    }) {
+        return Ok(name);
+    }
+
+    let name = fs::read_to_string(process.join("comm")).context("read comm")?;
+    Ok(name.trim_end().to_string())
+}
+
+/// Get the name of the user owning a process.
+/// Users without a passwd entry are named by their user id.
+pub fn user_name(process_id: ProcessId) -> anyhow::Result<String> {
+    let status =
+        fs::read_to_string(format!("/proc/{}/status", process_id)).context("read status")?;
+    let user_id = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().next())
+        .context("missing process uid")?;
+
+    let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    let user_name = passwd.lines().find_map(|entry| {
+        let mut fields = entry.split(':');
+        let name = fields.next()?;
+        (fields.nth(1)? == user_id).then(|| name.to_string())
+    });
+
+    Ok(user_name.unwrap_or_else(|| user_id.to_string()))
+}
+
+/// List all processes using `/proc`.
+/// The process names are limited to 15 characters.
+pub fn list_processes() -> anyhow::Result<Vec<ProcessEntry>> {
+    let mut processes = Vec::new();
+    for entry in fs::read_dir("/proc").context("read /proc")? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+
+        let Some(process_id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<ProcessId>().ok())
+        else {
+            continue;
+        };
+
+        /* the process may have exited in the meantime */
+        let Ok(name) = fs::read_to_string(entry.path().join("comm")) else {
+            continue;
+        };
+
+        processes.push(ProcessEntry {
+            process_id,
+            name: name.trim_end().to_string(),
+        });
+    }
+
+    Ok(processes)
+}
+
+/// A memory mapping of `/proc/<pid>/maps`
+struct Mapping<'a> {
+    start: u64,
+    readable: bool,
+    offset: u64,
+    path: Option<&'a str>,
+}
+
+impl<'a> Mapping<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        /* the path is padded with spaces and may contain spaces itself */
+        let mut columns = line.splitn(6, ' ');
+        let (start, _end) = columns.next()?.split_once('-')?;
+        let permissions = columns.next()?;
+        let offset = columns.next()?;
+        let _device = columns.next()?;
+        let _inode = columns.next()?;
+        let path = columns
+            .next()
+            .map(|path| path.trim_start())
+            .map(|path| path.strip_suffix(" (deleted)").unwrap_or(path))
+            .filter(|path| path.starts_with('/'));
+
+        Some(Self {
+            start: u64::from_str_radix(start, 16).ok()?,
+            readable: permissions.starts_with('r'),
+            offset: u64::from_str_radix(offset, 16).ok()?,
+            path,
+        })
+    }
+}
+
+/// The modules of a process
+pub struct ProcessModules {
+    maps: String,
+    memory: File,
+}
+
+/// Open the process for enumerating its modules
+pub fn open_process_modules(process_id: ProcessId) -> anyhow::Result<ProcessModules> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", process_id)).context("read maps")?;
+    let memory = File::open(format!("/proc/{}/mem", process_id)).context("open memory")?;
+    Ok(ProcessModules { maps, memory })
+}
+
+impl ProcessModules {
+    /// List all PE images mapped into the process.
+    ///
+    /// Processes running under Wine / Proton map their PE images from the `.dll` and `.exe` files.
+    /// Images are detected by the PE header at the start of file mappings.
+    pub fn list(&self) -> anyhow::Result<Vec<ModuleEntry>> {
+        let mut modules = Vec::<ModuleEntry>::new();
+        let mut header = [0u8; 0x1000];
+        for mapping in self.maps.lines().filter_map(Mapping::parse) {
+            if !mapping.readable || mapping.offset != 0 {
+                continue;
+            }
+
+            let Some(path) = mapping.path else {
+                continue;
+            };
+
+            if modules
+                .iter()
+                .any(|module| module.base_address == mapping.start)
+            {
+                continue;
+            }
+
+            if self
+                .memory
+                .read_exact_at(&mut header, mapping.start)
+                .is_err()
+            {
+                continue;
+            }
+
+            let Some(module_size) = pe::image_size(&header) else {
+                continue;
+            };
+
+            let name = Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            modules.push(ModuleEntry {
+                name,
+                base_address: mapping.start,
+                module_size: module_size as u64,
+            });
+        }
+
+        Ok(modules)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs::{
+            self,
+            File,
+        },
+    };
+
+    use super::{
+        pe,
+        ModuleEntry,
+        ProcessModules,
+    };
+
+    #[test]
+    fn wine_modules() {
+        /* the process memory is backed by a file containing a PE header at 0x2000 */
+        let path = env::temp_dir().join(format!("vtd-wine-modules-{}", std::process::id()));
+        let mut memory = vec![0u8; 0x2000];
+        memory.extend(pe::create_test_header(0x3000));
+        fs::write(&path, memory).unwrap();
+
+        let modules = ProcessModules {
+            maps: [
+                "00001000-00002000 r--p 00000000 08:01 1001                       /usr/bin/wine64-preloader",
+                "00002000-00003000 r--p 00000000 08:01 1002                       /home/user/Steam/steamapps/common/Counter Strike/game/client.dll (deleted)",
+                "00003000-00004000 r-xp 00001000 08:01 1002                       /home/user/Steam/steamapps/common/Counter Strike/game/client.dll",
+                "00002000-00003000 r--p 00000000 08:01 1002                       /home/user/Steam/steamapps/common/Counter Strike/game/client.dll",
+                "00004000-00005000 rw-p 00000000 00:00 0                          [heap]",
+            ]
+            .join("\n"),
+            memory: File::open(&path).unwrap(),
+        };
+
+        let modules = modules.list();
+        fs::remove_file(&path).unwrap();
+
+        let modules = modules.unwrap();
+        assert_eq!(modules.len(), 1);
+
+        let ModuleEntry {
+            name,
+            base_address,
+            module_size,
+        } = &modules[0];
+        assert_eq!(name, "client.dll");
+        assert_eq!(*base_address, 0x2000);
+        assert_eq!(*module_size, 0x3000);
+    }
+}
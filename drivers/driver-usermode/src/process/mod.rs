@@ -0,0 +1,32 @@
+//! Enumeration of the system processes and their details
+use vtd_protocol::types::ProcessId;
+
+#[cfg(test)]
+mod bench;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+mod pe;
+#[cfg(target_os = "linux")]
+pub use linux::*;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use self::windows::*;
+
+/// A process within a snapshot of the system processes
+#[derive(Debug, Clone)]
+pub struct ProcessEntry {
+    pub process_id: ProcessId,
+    pub name: String,
+}
+
+/// A module loaded into a process
+#[derive(Debug, Clone)]
+pub struct ModuleEntry {
+    /// Base name of the module (e.g. `kernel32.dll`)
+    pub name: String,
+    pub base_address: u64,
+    pub module_size: u64,
+}
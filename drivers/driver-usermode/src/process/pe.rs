@@ -0,0 +1,87 @@
+//! Minimal parsing of PE image headers
+
+const DOS_SIGNATURE: &[u8; 2] = b"MZ";
+const NT_SIGNATURE: &[u8; 4] = b"PE\0\0";
+
+const OPTIONAL_HEADER_MAGIC_PE32: u16 = 0x10B;
+const OPTIONAL_HEADER_MAGIC_PE64: u16 = 0x20B;
+
+fn read_u16(buffer: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buffer.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buffer.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Get the `SizeOfImage` of the PE image starting with the given header.
+/// Returns `None` if the header is not a valid PE header.
+pub fn image_size(header: &[u8]) -> Option<u32> {
+    if header.get(0..2)? != DOS_SIGNATURE {
+        return None;
+    }
+
+    let nt_header = read_u32(header, 0x3C)? as usize;
+    if header.get(nt_header..nt_header.checked_add(4)?)? != NT_SIGNATURE {
+        return None;
+    }
+
+    /* the optional header follows the signature and the 20 byte file header */
+    let optional_header = nt_header + 0x18;
+    match read_u16(header, optional_header)? {
+        OPTIONAL_HEADER_MAGIC_PE32 | OPTIONAL_HEADER_MAGIC_PE64 => {}
+        _ => return None,
+    }
+
+    /* SizeOfImage has the same offset for PE32 and PE32+ */
+    match read_u32(header, optional_header + 0x38)? {
+        0 => None,
+        size => Some(size),
+    }
+}
+
+/// Create the headers of a PE image for testing
+#[cfg(test)]
+pub fn create_test_header(size_of_image: u32) -> Vec<u8> {
+    let mut header = vec![0u8; 0x1000];
+    header[0..2].copy_from_slice(DOS_SIGNATURE);
+    header[0x3C..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+    header[0x80..0x84].copy_from_slice(NT_SIGNATURE);
+    header[0x98..0x9A].copy_from_slice(&OPTIONAL_HEADER_MAGIC_PE64.to_le_bytes());
+    header[0xD0..0xD4].copy_from_slice(&size_of_image.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        create_test_header,
+        image_size,
+    };
+
+    #[test]
+    fn valid_header() {
+        assert_eq!(image_size(&create_test_header(0x3000)), Some(0x3000));
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert_eq!(image_size(&[]), None);
+        assert_eq!(image_size(b"\x7fELF"), None);
+
+        /* empty image */
+        assert_eq!(image_size(&create_test_header(0)), None);
+
+        /* nt header outside of the buffer */
+        let mut header = create_test_header(0x3000);
+        header[0x3C..0x40].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(image_size(&header), None);
+
+        /* invalid optional header magic */
+        let mut header = create_test_header(0x3000);
+        header[0x98] = 0x00;
+        assert_eq!(image_size(&header), None);
+    }
+}
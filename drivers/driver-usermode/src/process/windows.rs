@@ -0,0 +1,229 @@
+use std::mem;
+
+use anyhow::Context;
+use vtd_protocol::types::ProcessId;
+use windows::{
+    core::{
+        PCWSTR,
+        PWSTR,
+    },
+    Win32::{
+        Foundation::{
+            HANDLE,
+            HMODULE,
+        },
+        Security::{
+            GetTokenInformation,
+            LookupAccountSidW,
+            TokenUser,
+            SID_NAME_USE,
+            TOKEN_QUERY,
+            TOKEN_USER,
+        },
+        System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot,
+                Process32FirstW,
+                Process32NextW,
+                PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+            ProcessStatus::{
+                GetModuleBaseNameA,
+                GetModuleInformation,
+            },
+            Threading::{
+                OpenProcessToken,
+                QueryFullProcessImageNameW,
+                PROCESS_NAME_WIN32,
+                PROCESS_QUERY_INFORMATION,
+                PROCESS_QUERY_LIMITED_INFORMATION,
+                PROCESS_VM_READ,
+            },
+        },
+    },
+};
+
+use super::{
+    ModuleEntry,
+    ProcessEntry,
+};
+use crate::{
+    handle::OwnedHandle,
+    util,
+};
+
+/// Get the image file name (e.g. `cs2.exe`) of a process
+pub fn image_name(process_id: ProcessId) -> anyhow::Result<String> {
+    let process = util::open_process_by_id(process_id, PROCESS_QUERY_LIMITED_INFORMATION)?;
+
+    let mut buffer = [0u16; 0x400];
+    let mut length = buffer.len() as u32;
+    let success = unsafe {
+        QueryFullProcessImageNameW(
+            process.raw_handle(),
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut length,
+        )
+    };
+    if !success.as_bool() {
+        anyhow::bail!("QueryFullProcessImageNameW");
+    }
+
+    let path = String::from_utf16_lossy(&buffer[0..length as usize]);
+    Ok(match path.rsplit_once('\\') {
+        Some((_, name)) => name.to_string(),
+        None => path,
+    })
+}
+
+/// Get the account name (`DOMAIN\user`) of the user owning a process
+pub fn user_name(process_id: ProcessId) -> anyhow::Result<String> {
+    let process = util::open_process_by_id(process_id, PROCESS_QUERY_LIMITED_INFORMATION)?;
+
+    let token = unsafe {
+        let mut token = HANDLE::default();
+        if !OpenProcessToken(process.raw_handle(), TOKEN_QUERY, &mut token).as_bool() {
+            anyhow::bail!("OpenProcessToken");
+        }
+
+        OwnedHandle::from_raw_handle(token)
+    };
+
+    /* TOKEN_USER is followed by the SID it references */
+    let mut token_user = [0u64; 0x40];
+    let mut bytes_needed = 0;
+    let success = unsafe {
+        GetTokenInformation(
+            token.raw_handle(),
+            TokenUser,
+            Some(token_user.as_mut_ptr() as *mut _),
+            mem::size_of_val(&token_user) as u32,
+            &mut bytes_needed,
+        )
+    };
+    if !success.as_bool() {
+        anyhow::bail!("GetTokenInformation");
+    }
+
+    let token_user = unsafe { &*(token_user.as_ptr() as *const TOKEN_USER) };
+    let mut name = [0u16; 0x100];
+    let mut name_length = name.len() as u32;
+    let mut domain = [0u16; 0x100];
+    let mut domain_length = domain.len() as u32;
+    let mut sid_type = SID_NAME_USE::default();
+    let success = unsafe {
+        LookupAccountSidW(
+            PCWSTR::null(),
+            token_user.User.Sid,
+            PWSTR(name.as_mut_ptr()),
+            &mut name_length,
+            PWSTR(domain.as_mut_ptr()),
+            &mut domain_length,
+            &mut sid_type,
+        )
+    };
+    if !success.as_bool() {
+        anyhow::bail!("LookupAccountSidW");
+    }
+
+    Ok(format!(
+        "{}\\{}",
+        String::from_utf16_lossy(&domain[0..domain_length as usize]),
+        String::from_utf16_lossy(&name[0..name_length as usize])
+    ))
+}
+
+/// List all processes using a toolhelp snapshot.
+/// Unlike opening every process this includes processes we are not allowed to open.
+pub fn list_processes() -> anyhow::Result<Vec<ProcessEntry>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .context("CreateToolhelp32Snapshot")?;
+    let snapshot = OwnedHandle::from_raw_handle(snapshot);
+
+    let mut entry = PROCESSENTRY32W {
+        dwSize: mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+
+    let mut processes = Vec::new();
+    let mut success = unsafe { Process32FirstW(snapshot.raw_handle(), &mut entry) };
+    while success.as_bool() {
+        let name_length = entry
+            .szExeFile
+            .iter()
+            .position(|value| *value == 0)
+            .unwrap_or(entry.szExeFile.len());
+
+        processes.push(ProcessEntry {
+            process_id: entry.th32ProcessID,
+            name: String::from_utf16_lossy(&entry.szExeFile[0..name_length]),
+        });
+        success = unsafe { Process32NextW(snapshot.raw_handle(), &mut entry) };
+    }
+
+    Ok(processes)
+}
+
+/// The modules of a process
+pub struct ProcessModules {
+    process: OwnedHandle,
+}
+
+/// Open the process for enumerating its modules
+pub fn open_process_modules(process_id: ProcessId) -> anyhow::Result<ProcessModules> {
+    let process =
+        util::open_process_by_id(process_id, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ)?;
+    Ok(ProcessModules { process })
+}
+
+impl ProcessModules {
+    fn module_info(&self, hmodule: HMODULE) -> anyhow::Result<ModuleEntry> {
+        let mut name = [0u8; 0x100];
+        let name_length =
+            unsafe { GetModuleBaseNameA(self.process.raw_handle(), hmodule, &mut name) } as usize;
+        if name_length == 0 {
+            anyhow::bail!("GetModuleBaseNameA failed");
+        }
+
+        let mut module_info = Default::default();
+        let success = unsafe {
+            GetModuleInformation(
+                self.process.raw_handle(),
+                hmodule,
+                &mut module_info,
+                mem::size_of_val(&module_info) as u32,
+            )
+        };
+        if !success.as_bool() {
+            anyhow::bail!("GetModuleInformation failed");
+        }
+
+        Ok(ModuleEntry {
+            name: String::from_utf8_lossy(&name[0..name_length]).to_string(),
+            base_address: module_info.lpBaseOfDll as u64,
+            module_size: module_info.SizeOfImage as u64,
+        })
+    }
+
+    /// List all modules of the process.
+    /// Modules which can not be inspected will be skipped.
+    pub fn list(&self) -> anyhow::Result<Vec<ModuleEntry>> {
+        let modules = util::list_process_modules(&self.process, None)?;
+        Ok(modules
+            .into_iter()
+            .filter_map(|hmodule| {
+                self.module_info(hmodule)
+                    .inspect_err(|err| {
+                        log::debug!(
+                            "Failed to get process module info for {:X}: {}",
+                            hmodule.0,
+                            err
+                        )
+                    })
+                    .ok()
+            })
+            .collect())
+    }
+}
@@ -1,62 +1,154 @@
-use std::mem;
+use std::fmt;
 
 use vtd_protocol::{
-    command::DriverCommand,
+    codec::CommandCodec,
+    command::COMMAND_ID_LIMIT,
     utils::str_to_fixed_buffer,
+    validation,
     CommandResult,
 };
 
+use crate::policy::AccessDenied;
+
+/// Error returned by handlers if the command requests a feature the driver does not support.
+/// Reported to the client as [CommandResult::CommandFeatureUnsupported].
+#[derive(Debug)]
+pub struct FeatureUnsupported {
+    message: String,
+}
+
+impl FeatureUnsupported {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl fmt::Display for FeatureUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for FeatureUnsupported {}
+
 trait HandlerInvoker: Send + Sync {
     fn invoke(&self, command: &mut [u8], error_buffer: &mut [u8]) -> CommandResult;
 }
 
-struct HandlerImpl<C: DriverCommand + 'static> {
+struct HandlerImpl<C: CommandCodec + 'static> {
     inner: &'static (dyn Fn(&mut C) -> anyhow::Result<()> + Send + Sync),
 }
 
-impl<C: DriverCommand + 'static> HandlerInvoker for HandlerImpl<C> {
+impl<C: CommandCodec + 'static> HandlerInvoker for HandlerImpl<C> {
     fn invoke(&self, command: &mut [u8], error_buffer: &mut [u8]) -> CommandResult {
-        if mem::size_of::<C>() != command.len() {
-            let message = format!(
-                "command size miss match (expected {}, received: {})",
-                mem::size_of::<C>(),
-                command.len()
-            );
-
-            str_to_fixed_buffer(error_buffer, &message);
-            return CommandResult::CommandParameterInvalid;
-        }
+        /* validate all client supplied pointers before the handler creates slices from them */
+        let command = match unsafe { validation::validate_payload::<C>(command) } {
+            Ok(command) => command,
+            Err(error) => {
+                str_to_fixed_buffer(error_buffer, &error.to_string());
+                return CommandResult::CommandParameterInvalid;
+            }
+        };
 
-        let command = unsafe { &mut *(command.as_mut_ptr() as *mut C) };
         match (self.inner)(command) {
             Ok(_) => CommandResult::Success,
             Err(error) => {
                 let message = format!("{:#}", error);
                 str_to_fixed_buffer(error_buffer, &message);
-                return CommandResult::Error;
+                if error.is::<AccessDenied>() {
+                    return CommandResult::AccessDenied;
+                }
+                if error.is::<FeatureUnsupported>() {
+                    return CommandResult::CommandFeatureUnsupported;
+                }
+
+                CommandResult::Error
             }
         }
     }
 }
 
+/// A command passing through the middleware chain
+pub struct CommandContext<'a> {
+    pub command_id: u32,
+
+    /// Name of the registered command or `None` if no handler has been registered
+    pub command_name: Option<&'static str>,
+
+    pub payload: &'a mut [u8],
+    pub error_buffer: &'a mut [u8],
+}
+
+impl CommandContext<'_> {
+    /// Fail the command with the given result and error message
+    pub fn fail(&mut self, result: CommandResult, message: &str) -> CommandResult {
+        str_to_fixed_buffer(self.error_buffer, message);
+        result
+    }
+}
+
+/// A hook around every command handled by the [HandlerRegistry].
+/// Middleware is executed in the order it has been added.
+pub trait Middleware: Send + Sync {
+    /// Handle the command.
+    /// Call [Next::run] to pass the command to the next middleware and finally to its handler.
+    fn handle(&self, context: &mut CommandContext, next: Next) -> CommandResult;
+}
+
+/// The remaining middleware chain of a command
+pub struct Next<'a> {
+    middleware: &'a [Box<dyn Middleware>],
+    handler: Option<&'a dyn HandlerInvoker>,
+}
+
+impl Next<'_> {
+    pub fn run(self, context: &mut CommandContext) -> CommandResult {
+        match self.middleware.split_first() {
+            Some((middleware, remaining)) => middleware.handle(
+                context,
+                Next {
+                    middleware: remaining,
+                    handler: self.handler,
+                },
+            ),
+            None => match self.handler {
+                Some(handler) => handler.invoke(context.payload, context.error_buffer),
+                None => CommandResult::CommandInvalid,
+            },
+        }
+    }
+}
+
 pub struct HandlerRegistry {
-    handler: [Option<Box<dyn HandlerInvoker>>; 0x20],
+    handler: [Option<(&'static str, Box<dyn HandlerInvoker>)>; COMMAND_ID_LIMIT as usize],
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Default for HandlerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub const COMMAND_ID_MAX: u32 = 0x10;
 impl HandlerRegistry {
     pub fn new() -> Self {
         Self {
-            handler: [const { None }; 0x20],
+            handler: [const { None }; COMMAND_ID_LIMIT as usize],
+            middleware: Vec::new(),
         }
     }
 
-    pub fn register<C: DriverCommand>(
+    pub fn register<C: CommandCodec>(
         &mut self,
         handler: &'static (dyn Fn(&mut C) -> anyhow::Result<()> + Send + Sync),
     ) {
-        assert!(C::COMMAND_ID < COMMAND_ID_MAX);
-        self.handler[C::COMMAND_ID as usize] = Some(Box::new(HandlerImpl { inner: handler }))
+        assert!(C::COMMAND_ID < COMMAND_ID_LIMIT);
+        self.handler[C::COMMAND_ID as usize] =
+            Some((C::NAME, Box::new(HandlerImpl { inner: handler })))
+    }
+
+    pub fn add_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Box::new(middleware));
     }
 
     pub fn handle(
@@ -65,15 +157,22 @@ impl HandlerRegistry {
         command: &mut [u8],
         error_buffer: &mut [u8],
     ) -> CommandResult {
-        if command_id >= COMMAND_ID_MAX {
-            return CommandResult::CommandInvalid;
-        }
+        let handler = self
+            .handler
+            .get(command_id as usize)
+            .and_then(Option::as_ref);
 
-        let handler = match &self.handler[command_id as usize] {
-            Some(handler) => handler,
-            None => return CommandResult::CommandInvalid,
+        let mut context = CommandContext {
+            command_id,
+            command_name: handler.map(|(name, _)| *name),
+            payload: command,
+            error_buffer,
         };
 
-        handler.invoke(command, error_buffer)
+        let next = Next {
+            middleware: &self.middleware,
+            handler: handler.map(|(_, handler)| handler.as_ref()),
+        };
+        next.run(&mut context)
     }
 }
@@ -0,0 +1,51 @@
+//! Panics must never unwind across the exported `extern "C"` functions
+//! as this would abort the host process.
+use std::{
+    any::Any,
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::Mutex,
+};
+
+/// Message of the first panic.
+/// Once set the driver is degraded and rejects all further commands.
+static DEGRADED: Mutex<Option<String>> = Mutex::new(None);
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// Returns the panic message if the driver has been degraded
+pub fn degraded_reason() -> Option<String> {
+    DEGRADED
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// Execute the entry point and catch any panic.
+/// A panic marks the driver as degraded and the panic message will be returned.
+pub fn catch_panic<T>(entry_point: &str, callback: impl FnOnce() -> T) -> Result<T, String> {
+    match panic::catch_unwind(AssertUnwindSafe(callback)) {
+        Ok(result) => Ok(result),
+        Err(payload) => {
+            let message = self::panic_message(&*payload);
+            log::error!("Driver panicked in {}: {}", entry_point, message);
+
+            let mut degraded = DEGRADED.lock().unwrap_or_else(|err| err.into_inner());
+            if degraded.is_none() {
+                *degraded = Some(message.clone());
+            }
+
+            Err(message)
+        }
+    }
+}
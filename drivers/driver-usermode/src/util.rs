@@ -1,15 +1,11 @@
 use std::mem;
 
-use vtd_protocol::types::ProcessId;
 use windows::{
     core::Error,
     Win32::{
         Foundation::HMODULE,
         System::{
-            ProcessStatus::{
-                EnumProcessModules,
-                EnumProcesses,
-            },
+            ProcessStatus::EnumProcessModules,
             Threading::{
                 OpenProcess,
                 PROCESS_ACCESS_RIGHTS,
@@ -60,31 +56,3 @@ pub fn list_process_modules(
         return Ok(modules);
     }
 }
-
-pub fn list_system_process_ids() -> anyhow::Result<Vec<ProcessId>> {
-    let mut processes = Vec::new();
-    processes.resize(1000, u32::default());
-
-    loop {
-        let mut bytes_needed = 0;
-        let success = unsafe {
-            EnumProcesses(
-                processes.as_mut_ptr(),
-                (processes.len() * mem::size_of::<u32>()) as u32,
-                &mut bytes_needed,
-            )
-        };
-        if !success.as_bool() {
-            anyhow::bail!("EnumProcesses")
-        }
-
-        let process_count = bytes_needed as usize / mem::size_of::<u32>();
-        if process_count == processes.len() {
-            processes.resize(processes.len() * 2, u32::default());
-            continue;
-        }
-
-        unsafe { processes.set_len(process_count) };
-        return Ok(processes);
-    }
-}